@@ -0,0 +1,454 @@
+use crate::transaction;
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{ChildStdin, Command, Stdio};
+
+/// Argument switching the binary into the privileged worker mode.
+pub const WORKER_ARG: &str = "--alpm-worker";
+
+/// pkexec exit code when the authentication dialog is dismissed.
+const PKEXEC_DISMISSED: i32 = 126;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransactionError {
+    /// The transaction did not start, the user dismissed the authentication.
+    Cancelled,
+    Failed(String),
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cancelled => write!(f, "authentication was cancelled"),
+            Self::Failed(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// Packages the worker has to install and remove in a single transaction.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerRequest {
+    pub install: Vec<String>,
+    pub remove: Vec<String>,
+}
+
+/// Messages written by the worker to its stdout, one JSON object per line.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WorkerEvent {
+    /// Progress of a transaction step, `current` out of `total` packages.
+    Progress {
+        step: String,
+        package: String,
+        percent: i32,
+        current: usize,
+        total: usize,
+    },
+    Download {
+        file: String,
+        downloaded: i64,
+        total: i64,
+    },
+    /// A package has been installed, upgraded or removed.
+    Package {
+        action: String,
+        package: String,
+    },
+    Message {
+        text: String,
+    },
+    Log {
+        level: String,
+        text: String,
+    },
+    /// The worker waits for a "y" or "n" line on its stdin.
+    Question {
+        text: String,
+    },
+    Done {
+        success: bool,
+        error: Option<String>,
+    },
+    /// Sent on the unprivileged side only, when pkexec did not start the worker.
+    Cancelled,
+}
+
+impl WorkerEvent {
+    pub fn parse(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok()
+    }
+}
+
+/// Writes an event for the unprivileged side, stdout is reserved to the protocol.
+fn emit(event: &WorkerEvent) {
+    if let Ok(line) = serde_json::to_string(event) {
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{line}");
+        let _ = stdout.flush();
+    }
+}
+
+/// Asks the unprivileged side, an unanswered question is declined.
+fn ask(text: String) -> bool {
+    emit(&WorkerEvent::Question { text });
+
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(_) => answer.trim() == "y",
+        Err(_) => false,
+    }
+}
+
+fn progress_step(progress: alpm::Progress) -> &'static str {
+    match progress {
+        alpm::Progress::AddStart => "Installing",
+        alpm::Progress::UpgradeStart => "Upgrading",
+        alpm::Progress::DowngradeStart => "Downgrading",
+        alpm::Progress::ReinstallStart => "Reinstalling",
+        alpm::Progress::RemoveStart => "Removing",
+        alpm::Progress::ConflictsStart => "Checking for file conflicts",
+        alpm::Progress::DiskspaceStart => "Checking available disk space",
+        alpm::Progress::IntegrityStart => "Checking package integrity",
+        alpm::Progress::LoadStart => "Loading package files",
+        alpm::Progress::KeyringStart => "Checking keys in keyring",
+    }
+}
+
+fn on_event(event: alpm::AnyEvent) {
+    let event = match event.event() {
+        alpm::Event::PackageOperationDone(event) => {
+            let (action, pkg) = match event.operation() {
+                alpm::PackageOperation::Install(pkg) => ("installed", pkg),
+                alpm::PackageOperation::Upgrade(_, pkg) => ("upgraded", pkg),
+                alpm::PackageOperation::Reinstall(_, pkg) => ("reinstalled", pkg),
+                alpm::PackageOperation::Downgrade(_, pkg) => ("downgraded", pkg),
+                alpm::PackageOperation::Remove(pkg) => ("removed", pkg),
+            };
+            WorkerEvent::Package {
+                action: String::from(action),
+                package: String::from(pkg.name()),
+            }
+        }
+        alpm::Event::ScriptletInfo(event) => WorkerEvent::Message {
+            text: String::from(event.line().trim_end()),
+        },
+        alpm::Event::HookRunStart(event) => WorkerEvent::Message {
+            text: String::from(event.desc().unwrap_or(event.name())),
+        },
+        _ => return,
+    };
+    emit(&event);
+}
+
+fn on_question(question: alpm::AnyQuestion) {
+    match question.question() {
+        alpm::Question::InstallIgnorepkg(mut question) => {
+            let text = format!("{} is in IgnorePkg. Install anyway?", question.pkg().name());
+            question.set_install(ask(text));
+        }
+        alpm::Question::Replace(mut question) => {
+            let text = format!(
+                "Replace {} with {}/{}?",
+                question.oldpkg().name(),
+                question.newdb().name(),
+                question.newpkg().name()
+            );
+            question.set_replace(ask(text));
+        }
+        alpm::Question::Conflict(mut question) => {
+            let conflict = question.conflict();
+            let text = format!(
+                "{} and {} are in conflict. Remove {}?",
+                conflict.package1(),
+                conflict.package2(),
+                conflict.package2()
+            );
+            question.set_remove(ask(text));
+        }
+        alpm::Question::Corrupted(mut question) => {
+            let text = format!(
+                "File {} is corrupted ({}). Do you want to delete it?",
+                question.filepath(),
+                question.reason()
+            );
+            question.set_remove(ask(text));
+        }
+        alpm::Question::RemovePkgs(mut question) => {
+            let pkg_names = question
+                .packages()
+                .iter()
+                .map(|pkg| pkg.name())
+                .collect::<Vec<_>>();
+            let text = format!(
+                "The following packages cannot be upgraded due to unresolvable dependencies: {}. \
+                 Skip them for this upgrade?",
+                pkg_names.join(", ")
+            );
+            question.set_skip(ask(text));
+        }
+        alpm::Question::SelectProvider(mut question) => {
+            // same default as pacman --noconfirm
+            let provider = question.providers().iter().next();
+            if let Some(provider) = provider {
+                emit(&WorkerEvent::Message {
+                    text: format!("Using {} to provide {}", provider.name(), question.depend()),
+                });
+            }
+            question.set_index(0);
+        }
+        alpm::Question::ImportKey(mut question) => {
+            let key = question.key();
+            let text = format!("Import PGP key {}, \"{}\"?", key.fingerprint(), key.uid());
+            question.set_import(ask(text));
+        }
+    }
+}
+
+fn set_callbacks(alpm_handle: &mut alpm::Alpm) {
+    alpm_handle.set_log_cb((), |level, msg, _| {
+        let level = if level.contains(alpm::LogLevel::ERROR) {
+            "error"
+        } else if level.contains(alpm::LogLevel::WARNING) {
+            "warning"
+        } else {
+            return;
+        };
+        emit(&WorkerEvent::Log {
+            level: String::from(level),
+            text: String::from(msg.trim_end()),
+        });
+    });
+    alpm_handle.set_dl_cb((), |filename, event, _| {
+        let (downloaded, total) = match event.event() {
+            alpm::DownloadEvent::Progress(progress) => (progress.downloaded, progress.total),
+            alpm::DownloadEvent::Completed(completed) => (completed.total, completed.total),
+            _ => return,
+        };
+        emit(&WorkerEvent::Download {
+            file: String::from(filename),
+            downloaded,
+            total,
+        });
+    });
+    alpm_handle.set_progress_cb((), |progress, pkg_name, percent, howmany, current, _| {
+        emit(&WorkerEvent::Progress {
+            step: String::from(progress_step(progress)),
+            package: String::from(pkg_name),
+            percent,
+            current,
+            total: howmany,
+        });
+    });
+    alpm_handle.set_event_cb((), |event, _| on_event(event));
+    alpm_handle.set_question_cb((), |question, _| on_question(question));
+}
+
+/// Applies `request` against the sync databases as they are, like `pacman -S`: refreshing them
+/// without a system upgrade would be a partial upgrade, and the reviewed preview was resolved
+/// against them.
+fn apply(alpm_handle: &mut alpm::Alpm, request: &WorkerRequest) -> Result<(), String> {
+    alpm_handle
+        .trans_init(alpm::TransFlag::NONE)
+        .map_err(|err| format!("failed to initialize the transaction: {err}"))?;
+    let result = fill_and_commit(alpm_handle, request);
+    let _ = alpm_handle.trans_release();
+
+    result
+}
+
+fn fill_and_commit(alpm_handle: &mut alpm::Alpm, request: &WorkerRequest) -> Result<(), String> {
+    for target in transaction::split_targets(&request.remove) {
+        // already gone, reported as skipped by the caller
+        let pkg = match alpm_handle.localdb().pkg(target.as_str()) {
+            Ok(pkg) => pkg,
+            Err(_) => {
+                emit(&WorkerEvent::Message {
+                    text: format!("{target} is not installed, skipping"),
+                });
+                continue;
+            }
+        };
+        alpm_handle
+            .trans_remove_pkg(pkg)
+            .map_err(|err| format!("{target}: {err}"))?;
+    }
+    for target in transaction::split_targets(&request.install) {
+        let pkg = alpm_handle
+            .syncdbs()
+            .find_satisfier(target.as_str())
+            .ok_or_else(|| format!("target not found: {target}"))?;
+        alpm_handle
+            .trans_add_pkg(pkg)
+            .map_err(|err| format!("{target}: {err}"))?;
+    }
+
+    alpm_handle
+        .trans_prepare()
+        .map_err(|(_, err)| format!("failed to prepare the transaction: {err}"))?;
+    alpm_handle
+        .trans_commit()
+        .map_err(|(_, err)| format!("failed to commit the transaction: {err}"))?;
+
+    Ok(())
+}
+
+/// Entry point of the privileged worker, runs as root through pkexec.
+/// Returns the process exit code.
+pub fn run_worker(request: &str) -> i32 {
+    let result = serde_json::from_str::<WorkerRequest>(request)
+        .map_err(|err| format!("invalid request: {err}"))
+        .and_then(|request| {
+            let pacman = pacmanconf::Config::with_opts(None, Some("/etc/pacman.conf"), Some("/"))
+                .map_err(|err| format!("unable to read pacman.conf: {err}"))?;
+            let mut alpm_handle = alpm_utils::alpm_with_conf(&pacman)
+                .map_err(|err| format!("unable to initialize alpm: {err}"))?;
+            set_callbacks(&mut alpm_handle);
+            apply(&mut alpm_handle, &request)
+        });
+
+    let exit_code = match result {
+        Ok(_) => 0,
+        Err(_) => 1,
+    };
+    emit(&WorkerEvent::Done {
+        success: result.is_ok(),
+        error: result.err(),
+    });
+
+    exit_code
+}
+
+/// Unprivileged handle on a running worker.
+pub struct Worker {
+    stdin: ChildStdin,
+}
+
+impl Worker {
+    /// Answers the pending `WorkerEvent::Question`.
+    pub fn answer(&mut self, yes: bool) {
+        let answer = match yes {
+            true => "y",
+            false => "n",
+        };
+        let _ = writeln!(self.stdin, "{answer}");
+        let _ = self.stdin.flush();
+    }
+}
+
+/// Starts the worker through pkexec, its events are passed to `on_event` from a separate thread.
+/// The last event is always `WorkerEvent::Done` or `WorkerEvent::Cancelled`.
+pub fn spawn_worker<F>(request: &WorkerRequest, on_event: F) -> io::Result<Worker>
+where
+    F: Fn(WorkerEvent) + Send + 'static,
+{
+    let request = serde_json::to_string(request)?;
+    let mut child = Command::new("pkexec")
+        .arg(std::env::current_exe()?)
+        .arg(WORKER_ARG)
+        .arg(request)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let stdin = child.stdin.take().expect("worker stdin is piped");
+    let stdout = child.stdout.take().expect("worker stdout is piped");
+    std::thread::spawn(move || {
+        let mut done = false;
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(event) = WorkerEvent::parse(&line) {
+                done |= matches!(event, WorkerEvent::Done { .. });
+                on_event(event);
+            }
+        }
+
+        let status = child.wait();
+        if !done {
+            let event = match status {
+                Ok(status) if status.code() == Some(PKEXEC_DISMISSED) => WorkerEvent::Cancelled,
+                Ok(status) => WorkerEvent::Done {
+                    success: false,
+                    error: Some(format!("the privileged helper exited with {status}")),
+                },
+                Err(err) => WorkerEvent::Done {
+                    success: false,
+                    error: Some(format!("the privileged helper failed: {err}")),
+                },
+            };
+            on_event(event);
+        }
+    });
+
+    Ok(Worker { stdin })
+}
+
+/// Runs the transaction without a display, progress goes to stderr
+/// and questions are asked on the terminal.
+pub fn run_headless(request: &WorkerRequest) -> Result<(), TransactionError> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut worker = spawn_worker(request, move |event| {
+        let _ = tx.send(event);
+    })
+    .map_err(|err| {
+        TransactionError::Failed(format!("unable to start the privileged helper: {err}"))
+    })?;
+
+    for event in rx {
+        match event {
+            WorkerEvent::Progress { .. } | WorkerEvent::Download { .. } => {}
+            WorkerEvent::Package { action, package } => eprintln!("{package} {action}"),
+            WorkerEvent::Message { text } => eprintln!("{text}"),
+            WorkerEvent::Log { level, text } => eprintln!("{level}: {text}"),
+            WorkerEvent::Question { text } => {
+                eprint!("{text} [y/N] ");
+                let mut answer = String::new();
+                let _ = io::stdin().read_line(&mut answer);
+                worker.answer(matches!(answer.trim(), "y" | "Y" | "yes"));
+            }
+            WorkerEvent::Done { success: true, .. } => return Ok(()),
+            WorkerEvent::Done { error, .. } => {
+                return Err(TransactionError::Failed(
+                    error.unwrap_or_else(|| String::from("unknown error")),
+                ))
+            }
+            WorkerEvent::Cancelled => return Err(TransactionError::Cancelled),
+        }
+    }
+
+    Err(TransactionError::Failed(String::from(
+        "the privileged helper stopped unexpectedly",
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn event_protocol() {
+        let event = WorkerEvent::Progress {
+            step: String::from("Installing"),
+            package: String::from("firefox"),
+            percent: 42,
+            current: 1,
+            total: 2,
+        };
+        let line = serde_json::to_string(&event).unwrap();
+        assert!(line.starts_with(r#"{"event":"progress","#));
+        assert_eq!(WorkerEvent::parse(&line), Some(event));
+
+        assert_eq!(
+            WorkerEvent::parse(r#"{"event":"done","success":false,"error":"locked"}"#),
+            Some(WorkerEvent::Done {
+                success: false,
+                error: Some(String::from("locked"))
+            })
+        );
+        assert_eq!(
+            WorkerEvent::parse(":: Synchronizing package databases..."),
+            None
+        );
+    }
+}
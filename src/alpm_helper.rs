@@ -1,21 +1,333 @@
+use crate::alpm_backend::{self, TransactionError, WorkerRequest};
+use crate::catalog::Catalog;
+use crate::history::{self, HistoryAction, HistoryEntry, HistoryOutcome};
+use crate::hooks::{self, HookAction, HookReport, HookStage};
+use crate::progress_dialog;
+use crate::system_helper::{self, HelperClient};
+use crate::terminal_dialog;
+use crate::transaction;
 use crate::utils;
 use crate::utils::PacmanWrapper;
+use std::collections::HashSet;
 use std::path::Path;
-use subprocess::Exec;
+use subprocess::{Exec, ExitStatus};
 
 #[derive(Clone, Debug)]
 #[repr(C)]
 pub struct AlpmHelper {
     pub pkg_list_install: Vec<String>,
     pub pkg_list_removal: Vec<String>,
+    pub backend: TransactionBackend,
+}
+
+/// How transactions are applied.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransactionBackend {
+    /// libalpm, through the privileged worker.
+    #[default]
+    Native,
+    /// pamac-installer or the package manager in a terminal emulator.
+    Terminal,
+}
+
+impl TransactionBackend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(Self::Native),
+            "terminal" => Some(Self::Terminal),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            Self::Terminal => "terminal",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackageOutcome {
+    Installed,
+    Removed,
+    AlreadyPresent,
+    Failed,
+    Skipped,
+}
+
+impl PackageOutcome {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Installed => "Installed",
+            Self::Removed => "Removed",
+            Self::AlreadyPresent => "Already present",
+            Self::Failed => "Failed",
+            Self::Skipped => "Skipped",
+        }
+    }
+
+    /// Stable identifier, for machine readable output.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Installed => "installed",
+            Self::Removed => "removed",
+            Self::AlreadyPresent => "already_present",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub enum AlpmHelperResult {
-    Nothing,
-    Remove,
-    Add,
-    Both,
+pub struct PackageReport {
+    pub package: String,
+    /// Pending list entry the package comes from, an app with its extra packages.
+    pub entry: String,
+    pub install: bool,
+    pub outcome: PackageOutcome,
+    pub reason: Option<String>,
+    /// The requested change is still not applied.
+    pub pending: bool,
+}
+
+/// Outcome of every package requested to `AlpmHelper::do_update`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UpdateReport {
+    pub packages: Vec<PackageReport>,
+    /// Catalog hooks run around the transaction.
+    pub hooks: Vec<HookReport>,
+}
+
+impl UpdateReport {
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.packages
+            .iter()
+            .any(|pkg| pkg.outcome == PackageOutcome::Failed)
+            || self.hooks.iter().any(|hook| hook.error.is_some())
+    }
+
+    /// Packages the transaction installed.
+    pub fn installed_packages(&self) -> Vec<&str> {
+        self.packages
+            .iter()
+            .filter(|pkg| pkg.outcome == PackageOutcome::Installed)
+            .map(|pkg| pkg.package.as_str())
+            .collect()
+    }
+
+    fn pending_entries(&self, install: bool) -> Vec<String> {
+        let mut entries: Vec<String> = Vec::new();
+        for pkg in &self.packages {
+            if pkg.pending && pkg.install == install && !entries.contains(&pkg.entry) {
+                entries.push(pkg.entry.clone());
+            }
+        }
+
+        entries
+    }
+}
+
+/// Compares the local database `before` and `after` the transaction,
+/// `install_error` and `removal_error` are the failures reported by the backend.
+pub fn build_report(
+    pkg_list_install: &[String],
+    pkg_list_removal: &[String],
+    before: &HashSet<String>,
+    after: &HashSet<String>,
+    install_error: Option<&TransactionError>,
+    removal_error: Option<&TransactionError>,
+) -> UpdateReport {
+    let mut report = UpdateReport::default();
+
+    let error_outcome = |error: Option<&TransactionError>, fallback: &str| match error {
+        Some(TransactionError::Cancelled) => (
+            PackageOutcome::Skipped,
+            Some(TransactionError::Cancelled.to_string()),
+        ),
+        Some(error) => (PackageOutcome::Failed, Some(error.to_string())),
+        None => (PackageOutcome::Failed, Some(String::from(fallback))),
+    };
+
+    for entry in pkg_list_removal {
+        for pkg_name in entry.split_whitespace() {
+            let (outcome, reason) = if !before.contains(pkg_name) {
+                (PackageOutcome::Skipped, Some(String::from("not installed")))
+            } else if !after.contains(pkg_name) {
+                (PackageOutcome::Removed, None)
+            } else {
+                error_outcome(removal_error, "still installed after the transaction")
+            };
+            report.packages.push(PackageReport {
+                package: String::from(pkg_name),
+                entry: entry.clone(),
+                install: false,
+                outcome,
+                reason,
+                pending: after.contains(pkg_name),
+            });
+        }
+    }
+    for entry in pkg_list_install {
+        for pkg_name in entry.split_whitespace() {
+            let (outcome, reason) = if before.contains(pkg_name) {
+                (PackageOutcome::AlreadyPresent, None)
+            } else if after.contains(pkg_name) {
+                (PackageOutcome::Installed, None)
+            } else {
+                error_outcome(install_error, "not installed after the transaction")
+            };
+            report.packages.push(PackageReport {
+                package: String::from(pkg_name),
+                entry: entry.clone(),
+                install: true,
+                outcome,
+                reason,
+                pending: !after.contains(pkg_name),
+            });
+        }
+    }
+
+    report
+}
+
+/// History entries of the removals and installs of `report`.
+pub fn history_entries(
+    report: &UpdateReport,
+    started: i64,
+    duration: i64,
+    backend: TransactionBackend,
+) -> Vec<HistoryEntry> {
+    let cancelled = TransactionError::Cancelled.to_string();
+    [
+        (false, HistoryAction::Remove),
+        (true, HistoryAction::Install),
+    ]
+    .into_iter()
+    .filter_map(|(install, action)| {
+        let packages = report
+            .packages
+            .iter()
+            .filter(|pkg| pkg.install == install)
+            .collect::<Vec<_>>();
+        if packages.is_empty() {
+            return None;
+        }
+
+        let failure = packages
+            .iter()
+            .find(|pkg| pkg.outcome == PackageOutcome::Failed);
+        let (outcome, detail) = match failure {
+            Some(pkg) => (HistoryOutcome::Failed, pkg.reason.clone()),
+            None if packages
+                .iter()
+                .any(|pkg| pkg.reason.as_deref() == Some(cancelled.as_str())) =>
+            {
+                (HistoryOutcome::Cancelled, None)
+            }
+            None => (HistoryOutcome::Success, None),
+        };
+        Some(HistoryEntry {
+            timestamp: started,
+            duration,
+            action,
+            packages: packages.iter().map(|pkg| pkg.package.clone()).collect(),
+            backend: String::from(backend.name()),
+            outcome,
+            detail,
+            log_path: None,
+        })
+    })
+    .collect()
+}
+
+fn record_history(report: &UpdateReport, started: i64, backend: TransactionBackend) {
+    let duration = history::now() - started;
+    for entry in history_entries(report, started, duration, backend) {
+        history::record(entry);
+    }
+}
+
+/// Runs `hooks` for `stage`, through the system helper.
+type HookRunner = fn(Vec<(String, HookAction)>, HookStage) -> Vec<HookReport>;
+
+/// Same as `hooks::run_as_root`, the window keeps being drawn during the D-Bus calls.
+fn run_hooks_in_background(hooks: Vec<(String, HookAction)>, stage: HookStage) -> Vec<HookReport> {
+    if hooks.is_empty() {
+        return Vec::new();
+    }
+    utils::wait_for(move || hooks::run_as_root(hooks, stage))
+}
+
+/// Adds the hooks to the report, post-install ones only run for packages actually installed.
+/// Apps which are still there after their pre-remove hooks ran get those undone.
+fn with_post_install_hooks(
+    mut report: UpdateReport,
+    pre_remove: Vec<HookReport>,
+    after: &HashSet<String>,
+    catalog: &Catalog,
+    run_hooks: HookRunner,
+) -> UpdateReport {
+    let undo = run_hooks(
+        hooks::undo_pre_remove(&pre_remove, after),
+        HookStage::UndoPreRemove,
+    );
+    let post_install = run_hooks(
+        hooks::hooks_for(
+            catalog,
+            &report.installed_packages(),
+            HookStage::PostInstall,
+        ),
+        HookStage::PostInstall,
+    );
+    report.hooks = pre_remove;
+    report.hooks.extend(undo);
+    report.hooks.extend(post_install);
+    report
+}
+
+/// Installs or removes `pkg_list` through the system helper, the window keeps being drawn meanwhile.
+fn helper_transaction(pkg_list: &[String], install: bool) -> Option<TransactionError> {
+    let packages = transaction::split_targets(pkg_list);
+    let result = utils::wait_for(move || {
+        HelperClient::system()
+            .map_err(|err| err.to_string())
+            .and_then(|client| match install {
+                true => client.install(&packages),
+                false => client.remove(&packages),
+            })
+    });
+    match result {
+        Ok(()) => None,
+        Err(err) if system_helper::is_not_authorized(&err) => Some(TransactionError::Cancelled),
+        Err(err) => Some(TransactionError::Failed(err)),
+    }
+}
+
+/// Lets pamac-installer apply `pkg_list`, the window keeps being drawn while it runs.
+fn pamac_transaction(pkg_list: &[String], install: bool) -> Option<TransactionError> {
+    let mut args: Vec<String> = Vec::new();
+    if !install {
+        args.push(String::from("--remove"));
+    }
+    args.extend(transaction::split_targets(pkg_list));
+    let status = utils::wait_for(move || Exec::cmd("pamac-installer").args(&args).join());
+    match status {
+        Ok(ExitStatus::Exited(0)) => None,
+        Ok(ExitStatus::Exited(code)) => Some(TransactionError::Failed(format!(
+            "pamac-installer failed with exit code {code}"
+        ))),
+        Ok(_) => Some(TransactionError::Failed(String::from(
+            "pamac-installer did not complete",
+        ))),
+        Err(err) => Some(TransactionError::Failed(format!(
+            "unable to run pamac-installer: {err}"
+        ))),
+    }
 }
 
 impl AlpmHelper {
@@ -23,6 +335,7 @@ impl AlpmHelper {
         Self {
             pkg_list_install: Vec::new(),
             pkg_list_removal: Vec::new(),
+            backend: TransactionBackend::default(),
         }
     }
 
@@ -35,24 +348,97 @@ impl AlpmHelper {
         self.pkg_list_install.is_empty() && self.pkg_list_removal.is_empty()
     }
 
-    pub fn do_update(&self) -> AlpmHelperResult {
-        let mut result = AlpmHelperResult::Nothing;
-        if self.pkg_list_install.is_empty() && self.pkg_list_removal.is_empty() {
-            return result;
+    /// Applies the pending lists, the hooks `catalog` declares for the apps run around it.
+    pub fn do_update(&self, parent: Option<&gtk::Window>, catalog: &Catalog) -> UpdateReport {
+        if self.is_empty() {
+            return UpdateReport::default();
         }
 
-        if !self.pkg_list_removal.is_empty() && self.install_apps(&self.pkg_list_removal, false) {
-            result = AlpmHelperResult::Remove;
-        }
-        if !self.pkg_list_install.is_empty() && self.install_apps(&self.pkg_list_install, true) {
-            if result == AlpmHelperResult::Nothing {
-                result = AlpmHelperResult::Add;
-            } else {
-                result = AlpmHelperResult::Both;
+        let started = history::now();
+        let before = self.installed_targets();
+        let pre_remove = self.run_pre_remove_hooks(catalog, &before, run_hooks_in_background);
+        let (install_error, removal_error) = match self.backend {
+            TransactionBackend::Native => {
+                let error = progress_dialog::run_transaction(
+                    parent,
+                    &self.pkg_list_install,
+                    &self.pkg_list_removal,
+                )
+                .err();
+                (error.clone(), error)
             }
+            TransactionBackend::Terminal => {
+                // the exit code only tells about failures, the database tells what changed
+                let removal_error = self.install_apps(parent, &self.pkg_list_removal, false);
+                let install_error = self.install_apps(parent, &self.pkg_list_install, true);
+                (install_error, removal_error)
+            }
+        };
+        let after = self.installed_targets();
+
+        let report = build_report(
+            &self.pkg_list_install,
+            &self.pkg_list_removal,
+            &before,
+            &after,
+            install_error.as_ref(),
+            removal_error.as_ref(),
+        );
+        record_history(&report, started, self.backend);
+        with_post_install_hooks(report, pre_remove, &after, catalog, run_hooks_in_background)
+    }
+
+    /// Same as `do_update`, without a display: always goes through the privileged worker.
+    pub fn do_update_headless(&self, catalog: &Catalog) -> UpdateReport {
+        if self.is_empty() {
+            return UpdateReport::default();
         }
 
-        result
+        let started = history::now();
+        let before = self.installed_targets();
+        let pre_remove = self.run_pre_remove_hooks(catalog, &before, hooks::run_as_root);
+        let request = WorkerRequest {
+            install: self.pkg_list_install.clone(),
+            remove: self.pkg_list_removal.clone(),
+        };
+        let error = alpm_backend::run_headless(&request).err();
+        let after = self.installed_targets();
+
+        let report = build_report(
+            &self.pkg_list_install,
+            &self.pkg_list_removal,
+            &before,
+            &after,
+            error.as_ref(),
+            error.as_ref(),
+        );
+        record_history(&report, started, TransactionBackend::Native);
+        with_post_install_hooks(report, pre_remove, &after, catalog, hooks::run_as_root)
+    }
+
+    /// Runs the pre-remove hooks of the apps to remove which are `installed`.
+    fn run_pre_remove_hooks(
+        &self,
+        catalog: &Catalog,
+        installed: &HashSet<String>,
+        run_hooks: HookRunner,
+    ) -> Vec<HookReport> {
+        let targets = transaction::split_targets(&self.pkg_list_removal);
+        let packages = targets
+            .iter()
+            .map(String::as_str)
+            .filter(|pkg_name| installed.contains(*pkg_name))
+            .collect::<Vec<_>>();
+        run_hooks(
+            hooks::hooks_for(catalog, &packages, HookStage::PreRemove),
+            HookStage::PreRemove,
+        )
+    }
+
+    /// Keeps the entries which could not be applied, so they can be retried.
+    pub fn retain_pending(&mut self, report: &UpdateReport) {
+        self.pkg_list_install = report.pending_entries(true);
+        self.pkg_list_removal = report.pending_entries(false);
     }
 
     pub fn set_package(&mut self, pkg_name: &String, install: bool, installed: bool) {
@@ -87,57 +473,163 @@ impl AlpmHelper {
         self.pkg_list_removal.contains(pkg_name)
     }
 
-    fn install_apps(&self, pkg_list: &Vec<String>, install: bool) -> bool {
+    /// Returns the failure reported by the system helper or the command, if it applied the change.
+    fn install_apps(
+        &self,
+        parent: Option<&gtk::Window>,
+        pkg_list: &Vec<String>,
+        install: bool,
+    ) -> Option<TransactionError> {
         if pkg_list.is_empty() {
-            return false;
+            return None;
+        }
+
+        if Path::new("/sbin/pamac-installer").exists() {
+            return pamac_transaction(pkg_list, install);
         }
 
         let packages_do = pkg_list
             .iter()
             .map(|s| s.to_string() + " ")
             .collect::<String>();
-        if Path::new("/sbin/pamac-installer").exists() {
-            let arg = match install {
-                false => "--remove",
-                _ => "",
-            };
-            Exec::shell(format!("pamac-installer {arg} {packages_do}"))
-                .join()
-                .unwrap();
-        } else {
-            let (cmd, escalate) = match install {
-                true => match utils::get_pacman_wrapper() {
-                    PacmanWrapper::Pak => ("pak -Sy", false),
-                    PacmanWrapper::Yay => ("yay -Sy", false),
-                    PacmanWrapper::Paru => ("paru -Sy", false),
-                    _ => ("pacman -Sy", true),
-                },
-                false => match utils::get_pacman_wrapper() {
-                    PacmanWrapper::Pak => ("pak -R", false),
-                    PacmanWrapper::Yay => ("yay -R", false),
-                    PacmanWrapper::Paru => ("paru -R", false),
-                    _ => ("pacman -R", true),
-                },
-            };
-            let _ = utils::run_cmd_terminal(format!("{cmd} {packages_do}"), escalate);
+        // wrappers escalate by themselves, plain pacman goes through the system helper
+        let cmd = match (install, utils::get_pacman_wrapper()) {
+            (true, PacmanWrapper::Pak) => "pak -Sy",
+            (true, PacmanWrapper::Yay) => "yay -Sy",
+            (true, PacmanWrapper::Paru) => "paru -Sy",
+            (false, PacmanWrapper::Pak) => "pak -R",
+            (false, PacmanWrapper::Yay) => "yay -R",
+            (false, PacmanWrapper::Paru) => "paru -R",
+            (_, PacmanWrapper::Pacman) => return helper_transaction(pkg_list, install),
+        };
+        let outcome = terminal_dialog::run_cmd(parent, &format!("{cmd} {packages_do}"));
+        match outcome.exit_code {
+            Some(0) => None,
+            Some(code) => Some(TransactionError::Failed(format!(
+                "{cmd} failed with exit code {code}"
+            ))),
+            None => Some(TransactionError::Failed(format!("{cmd} did not complete"))),
         }
+    }
+
+    /// Requested packages currently in the local database.
+    fn installed_targets(&self) -> HashSet<String> {
+        let mut installed: HashSet<String> = HashSet::new();
+        let pacman = pacmanconf::Config::with_opts(None, Some("/etc/pacman.conf"), Some("/"));
+        let alpm = match pacman.map(|pacman| alpm_utils::alpm_with_conf(&pacman)) {
+            Ok(Ok(alpm)) => alpm,
+            _ => return installed,
+        };
 
-        match install {
-            true => self.app_installed(&pkg_list[0]),
-            false => !self.app_installed(&pkg_list[0]),
+        let localdb = alpm.localdb();
+        for pkg_name in transaction::split_targets(&self.pkg_list_removal) {
+            if localdb.pkg(pkg_name.as_str()).is_ok() {
+                installed.insert(pkg_name);
+            }
         }
+        for pkg_name in transaction::split_targets(&self.pkg_list_install) {
+            // virtual packages are installed when a provider is
+            if localdb.pkgs().find_satisfier(pkg_name.as_str()).is_some() {
+                installed.insert(pkg_name);
+            }
+        }
+
+        installed
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| String::from(*s)).collect()
+    }
+
+    fn outcomes(report: &UpdateReport) -> Vec<(&str, PackageOutcome)> {
+        report
+            .packages
+            .iter()
+            .map(|pkg| (pkg.package.as_str(), pkg.outcome))
+            .collect()
+    }
+
+    #[test]
+    fn report_outcomes() {
+        let install = names(&["firefox", "libreoffice-fresh libreoffice-fresh-fr", "vim"]);
+        let removal = names(&["chromium", "nano"]);
+        let before = names(&["vim", "chromium"]).into_iter().collect();
+        let after = names(&["vim", "firefox", "libreoffice-fresh"])
+            .into_iter()
+            .collect();
+
+        let report = build_report(&install, &removal, &before, &after, None, None);
+        assert_eq!(
+            outcomes(&report),
+            vec![
+                ("chromium", PackageOutcome::Removed),
+                ("nano", PackageOutcome::Skipped),
+                ("firefox", PackageOutcome::Installed),
+                ("libreoffice-fresh", PackageOutcome::Installed),
+                ("libreoffice-fresh-fr", PackageOutcome::Failed),
+                ("vim", PackageOutcome::AlreadyPresent),
+            ]
+        );
+        assert!(report.has_failures());
+
+        let mut alpm_helper = AlpmHelper::new();
+        alpm_helper.retain_pending(&report);
+        assert_eq!(
+            alpm_helper.pkg_list_install,
+            vec!["libreoffice-fresh libreoffice-fresh-fr"]
+        );
+        assert!(alpm_helper.pkg_list_removal.is_empty());
+
+        let entries = history_entries(&report, 1673758350, 12, TransactionBackend::Terminal);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.action, entry.packages.len(), entry.outcome))
+                .collect::<Vec<_>>(),
+            [
+                (HistoryAction::Remove, 2, HistoryOutcome::Success),
+                (HistoryAction::Install, 4, HistoryOutcome::Failed),
+            ]
+        );
+        assert_eq!(entries[1].backend, "terminal");
+        assert_eq!(
+            entries[1].detail.as_deref(),
+            Some("not installed after the transaction")
+        );
+    }
+
+    #[test]
+    fn report_cancelled() {
+        let install = names(&["firefox"]);
+        let removal = names(&["chromium"]);
+        let before = names(&["chromium"]).into_iter().collect();
+        let error = TransactionError::Cancelled;
 
-    fn app_installed(&self, pkg_names: &str) -> bool {
-        let pkg_name_vec = pkg_names
-            .split(' ')
-            .map(String::from)
-            .collect::<Vec<String>>();
-        let pkg_name = pkg_name_vec.first().unwrap();
+        let report = build_report(
+            &install,
+            &removal,
+            &before,
+            &before,
+            Some(&error),
+            Some(&error),
+        );
+        assert_eq!(
+            outcomes(&report),
+            vec![
+                ("chromium", PackageOutcome::Skipped),
+                ("firefox", PackageOutcome::Skipped),
+            ]
+        );
+        assert!(!report.has_failures());
 
-        let pacman =
-            pacmanconf::Config::with_opts(None, Some("/etc/pacman.conf"), Some("/")).unwrap();
-        let alpm = alpm_utils::alpm_with_conf(&pacman).unwrap();
-        matches!(alpm.localdb().pkg(pkg_name.as_bytes()), Ok(_))
+        let mut alpm_helper = AlpmHelper::new();
+        alpm_helper.retain_pending(&report);
+        assert_eq!(alpm_helper.pkg_list_install, vec!["firefox"]);
+        assert_eq!(alpm_helper.pkg_list_removal, vec!["chromium"]);
     }
 }
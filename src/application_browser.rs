@@ -1,28 +1,59 @@
 use crate::alpm_helper::*;
+use crate::catalog::Catalog;
+use crate::catalog_remote;
 use crate::config::PKGDATADIR;
+use crate::desktop_env::DesktopEnvironment;
+use crate::package_search::{self, PackageDetails, SyncPackage};
+use crate::profile::Profile;
+use crate::profile_dialog;
+use crate::settings;
+use crate::transaction;
 use crate::utils;
 
 use gio::prelude::*;
 use gtk::prelude::{
-    BoxExt, ButtonExt, CellRendererExt, CellRendererToggleExt, ComboBoxExt, ContainerExt, GridExt,
-    GtkListStoreExt, GtkListStoreExtManual, ScrolledWindowExt, ToggleButtonExt, TreeModelExt,
-    TreeStoreExt, TreeStoreExtManual, TreeViewColumnExt, TreeViewExt, WidgetExt,
+    BoxExt, ButtonExt, CellRendererExt, CellRendererPixbufExt, CellRendererToggleExt,
+    CheckMenuItemExt, ComboBoxExt, ComboBoxExtManual, ContainerExt, EntryExt, GridExt,
+    GtkListStoreExt, GtkListStoreExtManual, GtkMenuExt, GtkMenuItemExt, InfoBarExt, LabelExt,
+    MenuButtonExt, MenuShellExt, ScrolledWindowExt, SearchEntryExt, ToggleButtonExt, TreeModelExt,
+    TreeModelFilterExt, TreeStoreExt, TreeStoreExtManual, TreeViewColumnExt, TreeViewExt,
+    WidgetExt,
 };
 
 use once_cell::sync::Lazy;
 
-use std::fs;
-use std::sync::Mutex;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct ApplicationBrowser {
     pub alpm_handle: alpm::Alpm,
     pub alpm_helper: AlpmHelper,
     pub filter: bool,
+    pub all_desktops: bool,
+    pub all_repos: bool,
+    pub desktop_env: DesktopEnvironment,
     pub app_store: gtk::TreeStore,
+    pub app_filter: gtk::TreeModelFilter,
+    pub search_query: Rc<RefCell<String>>,
+    /// Last results of the sync databases search, with their query.
+    pub sync_results: Option<(String, Vec<SyncPackage>)>,
+    pub sync_search: Option<mpsc::Sender<SyncQuery>>,
+    pub sync_search_timeout: Rc<RefCell<Option<glib::SourceId>>>,
     pub group_store: gtk::ListStore,
+    pub group_combo: gtk::ComboBox,
+    pub group_combo_handler: glib::SignalHandlerId,
     pub group_tofilter: String,
-    pub groups: serde_json::Value,
+    pub catalog: Catalog,
+    /// Versions and sizes of the catalog packages, looked up again when the databases are reopened.
+    pub catalog_details: HashMap<String, PackageDetails>,
+    pub catalog_base: PathBuf,
+    pub catalog_infobar: gtk::InfoBar,
+    pub catalog_infobar_label: gtk::Label,
     pub tree_view: gtk::TreeView,
     pub app_browser_box: gtk::Box,
     pub button_box: gtk::Box,
@@ -43,6 +74,31 @@ const DESCRIPTION: u32 = 3;
 const ACTIVE: u32 = 4;
 const PACKAGE: u32 = 5;
 const INSTALLED: u32 = 6;
+const ORIGIN: u32 = 7;
+const SYNC_RESULT: u32 = 8;
+const INSTALLED_VERSION: u32 = 9;
+const SYNC_VERSION: u32 = 10;
+const REPOSITORY: u32 = 11;
+const DOWNLOAD_SIZE: u32 = 12;
+const INSTALLED_SIZE: u32 = 13;
+const UPDATE_AVAILABLE: u32 = 14;
+
+/// Columns which can be toggled from the header context menu: id, title, store column.
+const OPTIONAL_COLUMNS: [(&str, &str, u32); 5] = [
+    ("installed_version", "Installed", INSTALLED_VERSION),
+    ("sync_version", "Available", SYNC_VERSION),
+    ("repository", "Repository", REPOSITORY),
+    ("download_size", "Download size", DOWNLOAD_SIZE),
+    ("installed_size", "Installed size", INSTALLED_SIZE),
+];
+
+/// Shortest query searched in the sync databases.
+const MIN_SYNC_QUERY_LEN: usize = 2;
+/// Pause in typing after which the sync databases are searched.
+const SYNC_SEARCH_DELAY: Duration = Duration::from_millis(300);
+
+/// Query of the sync databases search, and the packages of the catalog.
+type SyncQuery = (String, HashSet<String>);
 
 // static mut G_APP_BROWSER: Rc<Mutex<ApplicationBrowser>> =
 // Rc::new(Mutex::new(ApplicationBrowser::new()));
@@ -58,40 +114,80 @@ impl ApplicationBrowser {
         app_browser_box.set_expand(true);
 
         let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        let saved_settings = settings::current();
         let advanced_button = gtk::ToggleButton::with_label("advanced");
         advanced_button.set_tooltip_text(Some("Toggle an extended selection of packages"));
+        advanced_button.set_active(saved_settings.advanced);
         advanced_button.connect_clicked(on_advanced_clicked);
+        let desktop_env = DesktopEnvironment::detect();
+        let all_desktops_button = gtk::ToggleButton::with_label("all desktops");
+        let all_desktops_tooltip = match desktop_env.is_known() {
+            true => format!(
+                "Show applications made for other desktop environments (current: {})",
+                desktop_env.names.join(", ")
+            ),
+            false => String::from("Show applications made for other desktop environments"),
+        };
+        all_desktops_button.set_tooltip_text(Some(&all_desktops_tooltip));
+        all_desktops_button.connect_clicked(on_all_desktops_clicked);
         // let download_button = gtk::Button::with_label("download");
         // download_button.set_tooltip_text(Some("Download the most recent selection of packages"));
         // download_button.connect_clicked(on_download_clicked);
         let reset_button = gtk::Button::with_label("reset");
         reset_button.set_tooltip_text(Some("Reset your current selections..."));
         reset_button.connect_clicked(on_reload_clicked);
+        let profiles_menu = gtk::Menu::new();
+        let save_profile_item = gtk::MenuItem::with_label("Save profile…");
+        save_profile_item.connect_activate(on_save_profile_activated);
+        profiles_menu.append(&save_profile_item);
+        let open_profile_item = gtk::MenuItem::with_label("Open profile…");
+        open_profile_item.connect_activate(on_open_profile_activated);
+        profiles_menu.append(&open_profile_item);
+        profiles_menu.show_all();
+        let profiles_button = gtk::MenuButton::new();
+        profiles_button.set_label("profiles");
+        profiles_button.set_tooltip_text(Some(
+            "Save your selection to a profile, or compare a profile with this system",
+        ));
+        profiles_button.set_popup(Some(&profiles_menu));
+        let all_repos_button = gtk::ToggleButton::with_label("all repositories");
+        all_repos_button.set_tooltip_text(Some(
+            "Also search every package of the repositories configured in pacman.conf",
+        ));
+        all_repos_button.connect_clicked(on_all_repos_clicked);
+        let search_entry = gtk::SearchEntry::new();
+        search_entry.set_placeholder_text(Some("Search applications"));
+        search_entry.set_tooltip_text(Some("Search by name, description or package"));
+        search_entry.connect_search_changed(on_search_changed);
         let update_system_btn = gtk::Button::with_label("UPDATE SYSTEM");
         update_system_btn.set_tooltip_text(Some("Apply your current selections to the system"));
         update_system_btn.connect_clicked(on_update_system_clicked);
         update_system_btn.set_sensitive(false);
 
         // Group filter
-        let data = fs::read_to_string(format!(
-            "{PKGDATADIR}/data/application_utility/default.json"
-        ))
-        .expect("Unable to read file");
-        let groups: serde_json::Value = serde_json::from_str(&data).expect("Unable to parse");
-        let group_store = load_groups_data(&groups);
+        let catalog_base = default_catalog_path();
+        let catalog = load_catalog(&catalog_base);
+        let (catalog_infobar, catalog_infobar_label) = create_catalog_infobar();
+        let group_store = load_groups_data(&catalog);
         let group_combo = utils::create_combo_with_model(&group_store);
-        group_combo.connect_changed(on_group_filter_changed);
+        let group_tofilter = restore_group(&group_combo, &catalog);
+        let group_combo_handler = group_combo.connect_changed(on_group_filter_changed);
 
         // Packing button box
         button_box.pack_start(&advanced_button, false, false, 10);
+        button_box.pack_start(&all_desktops_button, false, false, 10);
         button_box.pack_start(&group_combo, false, false, 10);
+        button_box.pack_start(&search_entry, false, false, 10);
+        button_box.pack_start(&all_repos_button, false, false, 10);
         button_box.pack_end(&update_system_btn, false, false, 10);
 
         button_box.pack_end(&reset_button, false, false, 10);
+        button_box.pack_end(&profiles_button, false, false, 10);
         // button_box.pack_end(&download_button, false, false, 10);
+        app_browser_box.pack_start(&catalog_infobar, false, false, 0);
         app_browser_box.pack_start(&button_box, false, false, 10);
 
-        let col_types: [glib::Type; 7] = [
+        let col_types: [glib::Type; 15] = [
             String::static_type(),
             String::static_type(),
             String::static_type(),
@@ -99,21 +195,54 @@ impl ApplicationBrowser {
             i32::static_type(),
             String::static_type(),
             i32::static_type(),
+            String::static_type(),
+            bool::static_type(),
+            String::static_type(),
+            String::static_type(),
+            String::static_type(),
+            String::static_type(),
+            String::static_type(),
+            bool::static_type(),
         ];
 
-        Self {
+        let app_store = gtk::TreeStore::new(&col_types);
+        let search_query = Rc::new(RefCell::new(String::new()));
+        let app_filter = gtk::TreeModelFilter::new(&app_store, None);
+        app_filter.set_visible_func(glib::clone!(@strong search_query => move |model, iter_a| {
+            app_row_visible(model, iter_a, &search_query.borrow())
+        }));
+
+        let mut app_browser = Self {
             alpm_handle: new_alpm().unwrap(),
             alpm_helper: AlpmHelper::new(),
-            filter: false,
-            app_store: gtk::TreeStore::new(&col_types),
+            filter: saved_settings.advanced,
+            all_desktops: false,
+            all_repos: false,
+            desktop_env,
+            app_store,
+            app_filter,
+            search_query,
+            sync_results: None,
+            sync_search: None,
+            sync_search_timeout: Rc::new(RefCell::new(None)),
             group_store,
-            groups,
-            group_tofilter: String::from("*"),
+            group_combo,
+            group_combo_handler,
+            catalog,
+            catalog_details: HashMap::new(),
+            catalog_base,
+            catalog_infobar,
+            catalog_infobar_label,
+            group_tofilter,
             tree_view: gtk::TreeView::new(),
             app_browser_box,
             button_box,
             update_system_btn,
-        }
+        };
+        app_browser.load_catalog_details();
+        app_browser.show_catalog_issues();
+
+        app_browser
     }
 
     pub fn default_impl() -> &'static Mutex<Self> {
@@ -125,105 +254,326 @@ impl ApplicationBrowser {
         let mut store_size: usize = 0;
 
         let localdb = self.alpm_handle.localdb();
+        let mut shown_packages: HashSet<String> = HashSet::new();
 
-        for group in self.groups.as_array().unwrap() {
-            if let Some(apps_map) = group.get("apps") {
-                let g_name = String::from(group["name"].as_str().unwrap());
-                let g_icon = String::from(group["icon"].as_str().unwrap());
-                let mut g_desc = String::from(group["description"].as_str().unwrap());
-                if g_desc.len() < 72 {
-                    g_desc += " ";
-                }
+        for group in &self.catalog.groups {
+            let mut g_desc = group.description.clone();
+            if g_desc.len() < 72 {
+                g_desc += " ";
+            }
 
-                if self.group_tofilter != "*" && self.group_tofilter != g_name {
+            if self.group_tofilter != "*" && self.group_tofilter != group.name {
+                continue;
+            }
+            if !group.filter.is_empty() && !self.filter {
+                continue;
+            }
+            if !self.desktop_allowed(&group.desktop) {
+                continue;
+            }
+
+            let index = self.app_store.insert_with_values(
+                None,
+                None,
+                &[
+                    (GROUP, &None::<String>),
+                    (ICON, &group.icon),
+                    (APPLICATION, &group.name),
+                    (DESCRIPTION, &g_desc),
+                    (ACTIVE, &-1_i32),
+                    (PACKAGE, &None::<String>),
+                    (INSTALLED, &-1_i32),
+                    (ORIGIN, &dropin_origin(&group.origin)),
+                    (SYNC_RESULT, &false),
+                    (INSTALLED_VERSION, &String::new()),
+                    (SYNC_VERSION, &String::new()),
+                    (REPOSITORY, &String::new()),
+                    (DOWNLOAD_SIZE, &String::new()),
+                    (INSTALLED_SIZE, &String::new()),
+                    (UPDATE_AVAILABLE, &false),
+                ],
+            );
+            store_size += 1;
+
+            for app in &group.apps {
+                let mut status = localdb.pkg(app.pkg.as_str()).is_ok();
+
+                if !app.filter.is_empty() && !self.filter {
                     continue;
                 }
-                if group["filter"].as_array().is_some() && !self.filter {
+                if !self.desktop_allowed(&app.desktop) {
                     continue;
                 }
 
-                let index = self.app_store.insert_with_values(
-                    None,
+                // Restore user checks, pending lists hold the app with its extra packages
+                let alpm_packages = app.packages().join(" ");
+                if !status && self.alpm_helper.to_install(&alpm_packages) {
+                    status = true;
+                }
+                if status && self.alpm_helper.to_remove(&alpm_packages) {
+                    status = false;
+                }
+
+                shown_packages.insert(app.pkg.clone());
+                let details = self
+                    .catalog_details
+                    .get(&app.pkg)
+                    .cloned()
+                    .unwrap_or_default();
+
+                self.app_store.insert_with_values(
+                    Some(&index),
                     None,
                     &[
                         (GROUP, &None::<String>),
-                        (ICON, &g_icon),
-                        (APPLICATION, &g_name),
-                        (DESCRIPTION, &g_desc),
-                        (ACTIVE, &-1_i32),
-                        (PACKAGE, &None::<String>),
-                        (INSTALLED, &-1_i32),
+                        (ICON, &app.icon),
+                        (APPLICATION, &app.name),
+                        (DESCRIPTION, &app.description),
+                        (ACTIVE, &status),
+                        (PACKAGE, &alpm_packages),
+                        (INSTALLED, &status),
+                        (ORIGIN, &dropin_origin(&app.origin)),
+                        (SYNC_RESULT, &false),
+                        (
+                            INSTALLED_VERSION,
+                            &details.installed_version.clone().unwrap_or_default(),
+                        ),
+                        (
+                            SYNC_VERSION,
+                            &details.sync_version.clone().unwrap_or_default(),
+                        ),
+                        (REPOSITORY, &details.repo.clone().unwrap_or_default()),
+                        (DOWNLOAD_SIZE, &format_size(details.download_size)),
+                        (INSTALLED_SIZE, &format_size(details.installed_size)),
+                        (UPDATE_AVAILABLE, &details.update_available()),
                     ],
                 );
-                store_size += 1;
+            }
+        }
 
-                for app in apps_map.as_array().unwrap() {
-                    let app_name = String::from(app["pkg"].as_str().unwrap());
-                    let mut status = localdb.pkg(app_name).is_ok();
+        if self.all_repos {
+            store_size += self.load_sync_results(&shown_packages);
+        }
 
-                    if app["filter"].as_array().is_some() && !self.filter {
-                        continue;
-                    }
+        store_size
+    }
 
-                    // Restore user checks
-                    if !status
-                        && self
-                            .alpm_helper
-                            .to_install(&String::from(app["pkg"].as_str().unwrap()))
-                    {
-                        status = true;
-                    }
-                    if status
-                        && self
-                            .alpm_helper
-                            .to_remove(&String::from(app["pkg"].as_str().unwrap()))
-                    {
-                        status = false;
-                    }
+    /// Adds the packages of the sync databases matching the search,
+    /// except those already listed in the catalog groups.
+    fn load_sync_results(&self, shown_packages: &HashSet<String>) -> usize {
+        // searched by the sync search thread, see `request_sync_search`
+        let (query, results) = match &self.sync_results {
+            Some((query, results)) => (query, results),
+            None => return 0,
+        };
+        let results = results
+            .iter()
+            .filter(|pkg| !shown_packages.contains(&pkg.name))
+            .collect::<Vec<_>>();
+        if results.is_empty() {
+            return 0;
+        }
 
-                    let mut alpm_packages_vec = vec![String::from(app["pkg"].as_str().unwrap())];
-                    {
-                        let alpm_packages_temp = app["extra"].as_array().unwrap();
-                        for alpm_package in alpm_packages_temp {
-                            alpm_packages_vec.push(alpm_package.as_str().unwrap().to_owned());
-                        }
-                    }
+        let index = self.app_store.insert_with_values(
+            None,
+            None,
+            &[
+                (GROUP, &None::<String>),
+                (ICON, &String::from("system-software-install")),
+                (APPLICATION, &String::from("Repositories")),
+                (
+                    DESCRIPTION,
+                    &format!("{} packages matching \"{query}\"", results.len()),
+                ),
+                (ACTIVE, &-1_i32),
+                (PACKAGE, &None::<String>),
+                (INSTALLED, &-1_i32),
+                (ORIGIN, &String::new()),
+                (SYNC_RESULT, &true),
+                (INSTALLED_VERSION, &String::new()),
+                (SYNC_VERSION, &String::new()),
+                (REPOSITORY, &String::new()),
+                (DOWNLOAD_SIZE, &String::new()),
+                (INSTALLED_SIZE, &String::new()),
+                (UPDATE_AVAILABLE, &false),
+            ],
+        );
 
-                    let alpm_packages = alpm_packages_vec.join(" ");
-
-                    self.app_store.insert_with_values(
-                        Some(&index),
-                        None,
-                        &[
-                            (GROUP, &None::<String>),
-                            (ICON, &String::from(app["icon"].as_str().unwrap())),
-                            (APPLICATION, &String::from(app["name"].as_str().unwrap())),
-                            (
-                                DESCRIPTION,
-                                &String::from(app["description"].as_str().unwrap()),
-                            ),
-                            (ACTIVE, &status),
-                            (PACKAGE, &alpm_packages),
-                            (INSTALLED, &status),
-                        ],
-                    );
-                }
+        for pkg in results {
+            let mut status = pkg.installed;
+            // Restore user checks
+            if !status && self.alpm_helper.to_install(&pkg.name) {
+                status = true;
+            }
+            if status && self.alpm_helper.to_remove(&pkg.name) {
+                status = false;
             }
+
+            let details = package_search::package_details(&self.alpm_handle, &pkg.name);
+            self.app_store.insert_with_values(
+                Some(&index),
+                None,
+                &[
+                    (GROUP, &None::<String>),
+                    (ICON, &String::from("package-x-generic")),
+                    (APPLICATION, &pkg.name),
+                    (DESCRIPTION, &pkg.description),
+                    (ACTIVE, &status),
+                    (PACKAGE, &pkg.name),
+                    (INSTALLED, &pkg.installed),
+                    (ORIGIN, &String::new()),
+                    (SYNC_RESULT, &true),
+                    (
+                        INSTALLED_VERSION,
+                        &details.installed_version.clone().unwrap_or_default(),
+                    ),
+                    (SYNC_VERSION, &pkg.version),
+                    (REPOSITORY, &pkg.repo),
+                    (DOWNLOAD_SIZE, &format_size(pkg.download_size)),
+                    (INSTALLED_SIZE, &format_size(pkg.installed_size)),
+                    (UPDATE_AVAILABLE, &details.update_available()),
+                ],
+            );
         }
 
-        store_size
+        1
+    }
+
+    /// Searches the sync databases for the current query off the main thread.
+    /// The store is reloaded once the results are in.
+    fn request_sync_search(&mut self) {
+        let query = self.search_query.borrow().clone();
+        if query.chars().count() < MIN_SYNC_QUERY_LEN {
+            if self.sync_results.take().is_some() {
+                self.app_store.clear();
+                self.load_app_data();
+                self.refresh_view();
+            }
+            return;
+        }
+        if self
+            .sync_results
+            .as_ref()
+            .is_some_and(|(results_query, _)| *results_query == query)
+        {
+            return;
+        }
+
+        let curated = self
+            .catalog
+            .groups
+            .iter()
+            .flat_map(|group| group.apps.iter().map(|app| app.pkg.clone()))
+            .collect::<HashSet<String>>();
+        let sync_search = self.sync_search.get_or_insert_with(spawn_sync_search);
+        if sync_search.send((query, curated)).is_err() {
+            // the thread could not open the databases, it is started again next time
+            self.sync_search = None;
+        }
+    }
+
+    fn parent_window(&self) -> Option<gtk::Window> {
+        self.app_browser_box
+            .toplevel()
+            .and_then(|toplevel| toplevel.downcast::<gtk::Window>().ok())
+    }
+
+    fn desktop_allowed(&self, constraints: &[String]) -> bool {
+        self.all_desktops || self.desktop_env.matches(constraints)
+    }
+
+    fn show_catalog_issues(&self) {
+        let details = self
+            .catalog
+            .issues
+            .iter()
+            .map(|issue| {
+                eprintln!("catalog: {issue}");
+                issue.to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        self.catalog_infobar_label
+            .set_text(&match self.catalog.issues.len() {
+                1 => String::from("1 invalid catalog entry was skipped"),
+                count => format!("{count} invalid catalog entries were skipped"),
+            });
+        self.catalog_infobar_label.set_tooltip_text(Some(&details));
+
+        // a dismissed notice shows up again when the issues change
+        let hidden = self.catalog.is_valid()
+            || settings::current().is_dismissed(&catalog_notice_id(&details));
+        self.catalog_infobar.set_no_show_all(hidden);
+        if hidden {
+            self.catalog_infobar.hide();
+        } else {
+            self.catalog_infobar.show_all();
+        }
+    }
+
+    fn reload_groups(&mut self) {
+        // The handler locks the app browser, which is already held here.
+        self.group_combo.block_signal(&self.group_combo_handler);
+        self.group_store = load_groups_data(&self.catalog);
+        self.group_combo.set_model(Some(&self.group_store));
+        self.group_tofilter = restore_group(&self.group_combo, &self.catalog);
+        self.group_combo.unblock_signal(&self.group_combo_handler);
+    }
+
+    /// Switches to another base catalog, keeping the pending selections.
+    /// Looks up the catalog packages in the databases, once rather than for each reload of the store.
+    fn load_catalog_details(&mut self) {
+        let pkg_names = self
+            .catalog
+            .groups
+            .iter()
+            .flat_map(|group| &group.apps)
+            .map(|app| app.pkg.as_str());
+        self.catalog_details = package_search::packages_details(&self.alpm_handle, pkg_names);
+    }
+
+    pub fn set_catalog_base(&mut self, catalog_base: PathBuf) {
+        self.catalog = load_catalog(&catalog_base);
+        self.catalog_base = catalog_base;
+        self.load_catalog_details();
+        self.show_catalog_issues();
+
+        self.reload_groups();
+        self.app_store.clear();
+        self.load_app_data();
+        self.refresh_view();
+    }
+
+    /// Re-applies the search to the freshly loaded store.
+    fn refresh_view(&self) {
+        // Groups are filtered when inserted, before their apps are.
+        self.app_filter.refilter();
+        self.tree_view.set_model(Some(&self.app_filter));
+        if self.group_tofilter != "*" || !self.search_query.borrow().is_empty() {
+            self.tree_view.expand_all();
+        }
     }
 
     pub fn reload_app_data(&mut self, refresh: bool) {
         self.alpm_helper.clear();
+        self.reload_store(refresh);
+    }
+
+    /// Reloads the view, keeping the pending changes checked.
+    fn reload_store(&mut self, refresh: bool) {
         self.app_store.clear();
 
         if refresh {
             self.alpm_handle = new_alpm().unwrap();
-            self.group_store = load_groups_data(&self.groups);
+            self.load_catalog_details();
+            // the search thread has its own handle, a new one is started with fresh databases
+            self.sync_search = None;
+            self.sync_results = None;
+            self.reload_groups();
         }
         self.load_app_data();
-        self.tree_view.set_model(Some(&self.app_store));
+        self.refresh_view();
         self.update_system_btn
             .set_sensitive(!self.alpm_helper.is_empty());
     }
@@ -233,7 +583,7 @@ impl ApplicationBrowser {
         let app_store_size = self.load_app_data();
 
         // create a tree view with the model store
-        self.tree_view = gtk::TreeView::with_model(&self.app_store);
+        self.tree_view = gtk::TreeView::with_model(&self.app_filter);
         self.tree_view.set_activate_on_single_click(true);
         self.tree_view.set_has_tooltip(true);
         self.tree_view
@@ -270,6 +620,35 @@ impl ApplicationBrowser {
         desc_column.set_resizable(true);
         self.tree_view.append_column(&desc_column);
 
+        // optional columns, toggled from the header context menu
+        let column_visibility = load_column_visibility();
+        let columns_menu = gtk::Menu::new();
+        for (id, title, store_column) in OPTIONAL_COLUMNS {
+            let renderer = gtk::CellRendererText::new();
+            let column = create_column(title, &renderer, "text", store_column);
+            if store_column == INSTALLED_VERSION {
+                // update available badge
+                let badge_renderer = gtk::CellRendererPixbuf::new();
+                badge_renderer.set_icon_name(Some("software-update-available"));
+                column.pack_start(&badge_renderer, false);
+                column.add_attribute(&badge_renderer, "visible", UPDATE_AVAILABLE as i32);
+            }
+            column.set_resizable(true);
+            column.set_visible(column_visibility[id]);
+            self.tree_view.append_column(&column);
+
+            let menu_item = gtk::CheckMenuItem::with_label(title);
+            menu_item.set_active(column.is_visible());
+            menu_item.connect_toggled(glib::clone!(@weak column => move |menu_item| {
+                column.set_visible(menu_item.is_active());
+                settings::update(|settings| {
+                    settings.columns.insert(String::from(id), menu_item.is_active());
+                });
+            }));
+            columns_menu.append(&menu_item);
+        }
+        columns_menu.show_all();
+
         // column model: install column
         let install_renderer = gtk::CellRendererToggle::new();
         install_renderer.connect_toggled(on_app_toggle);
@@ -284,6 +663,24 @@ impl ApplicationBrowser {
         install_column.set_fixed_width(40);
         self.tree_view.append_column(&install_column);
 
+        // right click on any header shows the columns menu
+        self.tree_view.set_headers_clickable(true);
+        for column in self.tree_view.columns() {
+            let header_button = match column.button() {
+                Some(header_button) => header_button,
+                None => continue,
+            };
+            header_button.connect_button_press_event(
+                glib::clone!(@strong columns_menu => move |_, event_btn| {
+                    if event_btn.button() == 3 {
+                        columns_menu.popup_at_pointer(Some(event_btn));
+                        return gtk::glib::signal::Inhibit(true);
+                    }
+                    gtk::glib::signal::Inhibit(false)
+                }),
+            );
+        }
+
         app_store_size
     }
 
@@ -351,21 +748,141 @@ fn on_group_filter_changed(combo: &gtk::ComboBox) {
         let group_gobj = model.value(&tree_iter, 0);
         let group = group_gobj.get::<&str>().unwrap();
         app_browser.group_tofilter = String::from(group);
+        settings::update(|settings| {
+            settings.group = Some(String::from(group)).filter(|group| group != "*");
+        });
         app_browser.app_store.clear();
         app_browser.load_app_data();
-        app_browser
-            .tree_view
-            .set_model(Some(&app_browser.app_store));
-        if group != "*" {
-            app_browser.tree_view.expand_all();
+        app_browser.refresh_view();
+    }
+}
+
+fn on_search_changed(entry: &gtk::SearchEntry) {
+    let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+    *app_browser.search_query.borrow_mut() = entry.text().trim().to_lowercase();
+    if app_browser.all_repos {
+        // the sync databases are searched once the user stops typing,
+        // results of the previous query are shown until then
+        if let Some(source_id) = app_browser.sync_search_timeout.borrow_mut().take() {
+            source_id.remove();
+        }
+        let sync_search_timeout = app_browser.sync_search_timeout.clone();
+        let source_id = glib::timeout_add_local_once(SYNC_SEARCH_DELAY, move || {
+            sync_search_timeout.borrow_mut().take();
+            let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+            app_browser.request_sync_search();
+        });
+        *app_browser.sync_search_timeout.borrow_mut() = Some(source_id);
+    }
+    app_browser.refresh_view();
+}
+
+fn on_all_repos_clicked(button: &gtk::ToggleButton) {
+    let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+    app_browser.all_repos = button.is_active();
+    if !app_browser.all_repos {
+        app_browser.sync_results = None;
+    }
+    app_browser.app_store.clear();
+    app_browser.load_app_data();
+    app_browser.refresh_view();
+    if app_browser.all_repos {
+        app_browser.request_sync_search();
+    }
+}
+
+/// Starts a thread searching the sync databases with its own libalpm handle.
+/// Only the latest of the queries sent while it was busy is searched.
+fn spawn_sync_search() -> mpsc::Sender<SyncQuery> {
+    let (query_tx, query_rx) = mpsc::channel::<SyncQuery>();
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+    std::thread::spawn(move || {
+        let alpm_handle = match new_alpm() {
+            Ok(alpm_handle) => alpm_handle,
+            Err(err) => {
+                eprintln!("unable to search the sync databases: {err}");
+                return;
+            }
+        };
+        while let Ok(mut sync_query) = query_rx.recv() {
+            while let Ok(newer_query) = query_rx.try_recv() {
+                sync_query = newer_query;
+            }
+            let (query, curated) = sync_query;
+            let results = package_search::search_sync_dbs(&alpm_handle, &query, &curated);
+            if tx.send((query, results)).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx.attach(None, |(query, results): (String, Vec<SyncPackage>)| {
+        let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+        // dropped when the search changed in the meantime
+        if app_browser.all_repos && *app_browser.search_query.borrow() == query {
+            app_browser.sync_results = Some((query, results));
+            app_browser.app_store.clear();
+            app_browser.load_app_data();
+            app_browser.refresh_view();
+        }
+        glib::Continue(true)
+    });
+
+    query_tx
+}
+
+/// Search matches an app on its name, description or packages.
+/// Apps of a matching group and groups of a matching app stay visible.
+fn app_row_visible(model: &gtk::TreeModel, iter_a: &gtk::TreeIter, query: &str) -> bool {
+    if query.is_empty() {
+        return true;
+    }
+    // already matched by the sync database search
+    if model.value(iter_a, SYNC_RESULT as i32).get::<bool>() == Ok(true) {
+        return true;
+    }
+
+    let row_matches = |iter_a: &gtk::TreeIter| {
+        [APPLICATION, DESCRIPTION, PACKAGE].iter().any(|column| {
+            model
+                .value(iter_a, *column as i32)
+                .get::<String>()
+                .is_ok_and(|text| text.to_lowercase().contains(query))
+        })
+    };
+    if row_matches(iter_a) {
+        return true;
+    }
+
+    if let Some(parent) = model.iter_parent(iter_a) {
+        return row_matches(&parent);
+    }
+    if let Some(child) = model.iter_children(Some(iter_a)) {
+        loop {
+            if row_matches(&child) {
+                return true;
+            }
+            if !model.iter_next(&child) {
+                break;
+            }
         }
     }
+
+    false
 }
 
 fn on_advanced_clicked(button: &gtk::ToggleButton) {
     let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
     let is_active = button.is_active();
     app_browser.filter = is_active;
+    settings::update(|settings| settings.advanced = is_active);
+    app_browser.reload_app_data(false);
+}
+
+fn on_all_desktops_clicked(button: &gtk::ToggleButton) {
+    let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+    app_browser.all_desktops = button.is_active();
     app_browser.reload_app_data(false);
 }
 
@@ -381,6 +898,7 @@ fn on_query_tooltip_tree_view(
     let tooltip_context = treeview.tooltip_context(&mut x, &mut y, keyboard_tip);
     if let Some((model_tmp, path, iter_a)) = tooltip_context {
         let model = model_tmp.unwrap();
+        let mut lines: Vec<String> = Vec::new();
         let value = model.value(&iter_a, INSTALLED as i32).get::<i32>().unwrap();
         if value == 1 {
             let mut msg = String::from("Installed");
@@ -388,7 +906,14 @@ fn on_query_tooltip_tree_view(
             if active == 0 {
                 msg.push_str(" , to remove");
             }
-            tooltip.set_markup(Some(msg.as_str()));
+            lines.push(msg);
+        }
+        let origin = model.value(&iter_a, ORIGIN as i32).get::<String>().unwrap();
+        if !origin.is_empty() {
+            lines.push(format!("Source: {}", glib::markup_escape_text(&origin)));
+        }
+        if !lines.is_empty() {
+            tooltip.set_markup(Some(lines.join("\n").as_str()));
             treeview.set_tooltip_row(tooltip, &path);
             return true;
         }
@@ -409,10 +934,9 @@ fn on_button_press_event_tree_view(
             }
 
             let (path, ..) = path_info.unwrap();
-            let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
-            let app_store = app_browser.app_store.clone();
-            let iter_a = app_store.iter(&path.clone().unwrap()).unwrap();
-            let value_gobj = app_store.value(&iter_a, PACKAGE as i32);
+            let model = treeview.model().unwrap();
+            let iter_a = model.iter(&path.clone().unwrap()).unwrap();
+            let value_gobj = model.value(&iter_a, PACKAGE as i32);
 
             if value_gobj.get::<&str>().is_err() {
                 if treeview.row_expanded(&path.clone().unwrap()) {
@@ -430,7 +954,12 @@ fn on_button_press_event_tree_view(
 fn on_app_toggle(_cell: &gtk::CellRendererToggle, path: gtk::TreePath) {
     let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
     let app_store = app_browser.app_store.clone();
-    let iter_a = app_store.iter(&path).unwrap();
+    // the view shows the search filter, not the store
+    let store_path = match app_browser.app_filter.convert_path_to_child_path(&path) {
+        Some(store_path) => store_path,
+        None => return,
+    };
+    let iter_a = app_store.iter(&store_path).unwrap();
     let value_gobj = app_store.value(&iter_a, PACKAGE as i32);
 
     // a group has no package attached and we don't install groups
@@ -465,28 +994,294 @@ fn on_app_toggle(_cell: &gtk::CellRendererToggle, path: gtk::TreePath) {
     }
 }
 
-fn on_update_system_clicked(_: &gtk::Button) {
+fn on_update_system_clicked(button: &gtk::Button) {
+    let parent = button
+        .toplevel()
+        .and_then(|toplevel| toplevel.downcast::<gtk::Window>().ok());
+    apply_pending(parent.as_ref());
+}
+
+/// Checks `entries` for installation then applies the pending changes.
+pub fn install_entries(parent: Option<&gtk::Window>, entries: &[String]) {
+    {
+        let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+        let localdb = app_browser.alpm_handle.localdb();
+        let missing = entries
+            .iter()
+            .filter(|entry| {
+                let pkg_name = entry.split_whitespace().next().unwrap_or_default();
+                localdb.pkg(pkg_name).is_err()
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+
+        for entry in missing {
+            if !app_browser.alpm_helper.to_install(&entry) {
+                app_browser.alpm_helper.pkg_list_install.push(entry);
+            }
+        }
+    }
+    apply_pending(parent);
+}
+
+/// Applies the pending changes. The app browser is not locked while dialogs run:
+/// their nested main loop dispatches callbacks which lock it as well.
+fn apply_pending(parent: Option<&gtk::Window>) {
+    let (alpm_helper, catalog, preview) = {
+        let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+        if app_browser.alpm_helper.is_empty() {
+            return;
+        }
+        // let the user review the resolved transaction first
+        let preview = transaction::resolve(
+            &app_browser.alpm_handle,
+            &app_browser.alpm_helper.pkg_list_install,
+            &app_browser.alpm_helper.pkg_list_removal,
+        );
+        (
+            app_browser.alpm_helper.clone(),
+            app_browser.catalog.clone(),
+            preview,
+        )
+    };
+    if !transaction::confirm_transaction(parent, &preview) {
+        let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+        app_browser.reload_store(false);
+        return;
+    }
+
+    let report = alpm_helper.do_update(parent, &catalog);
+    if report.is_empty() {
+        return;
+    }
+    transaction::show_update_report(parent, &report);
+
+    // reload json for view new apps installed, failed ones stay checked for a retry
     let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
-    if app_browser.alpm_helper.do_update() != AlpmHelperResult::Nothing {
-        // reload json for view new apps installed
-        app_browser.reload_app_data(true);
+    app_browser.alpm_helper.retain_pending(&report);
+    app_browser.reload_store(true);
+}
+
+fn on_save_profile_activated(_item: &gtk::MenuItem) {
+    let parent = unsafe { G_APP_BROWSER.lock().unwrap().parent_window() };
+    let path = match profile_dialog::choose_file(parent.as_ref(), true) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let profile = {
+        let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+        let localdb = app_browser.alpm_handle.localdb();
+        Profile::capture(
+            &name,
+            &app_browser.catalog,
+            |pkg_name| localdb.pkg(pkg_name).is_ok(),
+            &app_browser.alpm_helper.pkg_list_install,
+            &app_browser.alpm_helper.pkg_list_removal,
+        )
+    };
+    if let Err(err) = profile.save(&path) {
+        profile_dialog::show_error(
+            parent.as_ref(),
+            &format!("Unable to save {}: {err}", path.display()),
+        );
     }
 }
 
-fn load_groups_data(groups: &serde_json::Value) -> gtk::ListStore {
+fn on_open_profile_activated(_item: &gtk::MenuItem) {
+    let parent = unsafe { G_APP_BROWSER.lock().unwrap().parent_window() };
+    let path = match profile_dialog::choose_file(parent.as_ref(), false) {
+        Some(path) => path,
+        None => return,
+    };
+    let profile = match Profile::load(&path) {
+        Ok(profile) => profile,
+        Err(err) => {
+            profile_dialog::show_error(
+                parent.as_ref(),
+                &format!("Unable to open {}: {err}", path.display()),
+            );
+            return;
+        }
+    };
+
+    let diff = {
+        let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+        let localdb = app_browser.alpm_handle.localdb();
+        profile.diff(&app_browser.catalog, |pkg_name| {
+            localdb.pkg(pkg_name).is_ok()
+        })
+    };
+    if !profile_dialog::compare_profile(parent.as_ref(), &profile, &diff) {
+        return;
+    }
+
+    // the profile replaces the current selection
+    let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+    app_browser.alpm_helper.pkg_list_install = diff.to_install;
+    app_browser.alpm_helper.pkg_list_removal = diff.to_remove;
+    app_browser.reload_store(false);
+}
+
+fn load_groups_data(catalog: &Catalog) -> gtk::ListStore {
     // not use data set for the moment
     let store = gtk::ListStore::new(&[String::static_type()]);
     store.set(&store.append(), &[(0, &String::from("*"))]);
 
-    for group in groups.as_array().unwrap() {
-        let g_name = String::from(group["name"].as_str().unwrap());
-        store.set(&store.append(), &[(0, &g_name)]);
+    for group in &catalog.groups {
+        store.set(&store.append(), &[(0, &group.name)]);
     }
 
     store
 }
 
-fn create_column(
+fn format_size(size: i64) -> String {
+    match size > 0 {
+        true => glib::format_size(size as u64).to_string(),
+        false => String::new(),
+    }
+}
+
+/// Visibility of the optional columns, only the installed version is shown by default.
+fn load_column_visibility() -> HashMap<String, bool> {
+    let mut visibility = OPTIONAL_COLUMNS
+        .iter()
+        .map(|(id, ..)| (String::from(*id), *id == "installed_version"))
+        .collect::<HashMap<String, bool>>();
+
+    for (id, visible) in settings::current().columns {
+        if visibility.contains_key(&id) {
+            visibility.insert(id, visible);
+        }
+    }
+
+    visibility
+}
+
+/// Selects the group filter of the last session, if the catalog still has it.
+fn restore_group(group_combo: &gtk::ComboBox, catalog: &Catalog) -> String {
+    let saved_group = settings::current().group;
+    match catalog
+        .groups
+        .iter()
+        .position(|group| Some(&group.name) == saved_group.as_ref())
+    {
+        Some(index) => {
+            group_combo.set_active(Some(index as u32 + 1));
+            catalog.groups[index].name.clone()
+        }
+        None => {
+            group_combo.set_active(Some(0));
+            String::from("*")
+        }
+    }
+}
+
+pub fn default_catalog_path() -> PathBuf {
+    PathBuf::from(format!(
+        "{PKGDATADIR}/data/application_utility/default.json"
+    ))
+}
+
+/// Loads the base catalog with the system and user drop-in directories on top.
+pub fn load_catalog(base: &Path) -> Catalog {
+    let dropin_dirs = [
+        PathBuf::from("/etc/vaamos-menu/catalog.d"),
+        glib::user_config_dir().join("vaamos-menu/catalog.d"),
+    ];
+    Catalog::load_layered(base, &dropin_dirs)
+}
+
+pub fn set_transaction_backend(backend: TransactionBackend) {
+    let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+    app_browser.alpm_helper.backend = backend;
+}
+
+/// Replaces the bundled catalog by the snapshot fetched from `url`.
+/// The last cached snapshot is used until the download completes, or if it fails.
+pub fn refresh_remote_catalog(url: String, keyring: PathBuf) {
+    let cache_dir = catalog_remote::default_cache_dir();
+    if let Some(cached_catalog) = catalog_remote::cached_catalog(&cache_dir, &keyring) {
+        let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+        app_browser.set_catalog_base(cached_catalog);
+    }
+
+    // Create context channel.
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
+    // Spawn download in separate thread.
+    std::thread::spawn(move || {
+        tx.send(catalog_remote::refresh_catalog(&url, &keyring, &cache_dir))
+            .expect("Couldn't send data to channel");
+    });
+
+    rx.attach(None, move |result| {
+        match result {
+            Ok(catalog_path) => {
+                let app_browser = unsafe { &mut G_APP_BROWSER.lock().unwrap() };
+                app_browser.set_catalog_base(catalog_path);
+            }
+            Err(err) => eprintln!("catalog refresh failed: {err}"),
+        }
+        glib::Continue(false)
+    });
+}
+
+/// Origin shown to the user, entries of the bundled catalog have none.
+fn dropin_origin(origin: &str) -> String {
+    match default_catalog_path().to_str() == Some(origin) {
+        true => String::new(),
+        false => String::from(origin),
+    }
+}
+
+/// Prefix of the dismissed notices recording a set of catalog issues.
+const CATALOG_NOTICE: &str = "catalog_issues:";
+
+fn catalog_notice_id(details: &str) -> String {
+    format!(
+        "{CATALOG_NOTICE}{}",
+        catalog_remote::sha256_hex(details.as_bytes())
+    )
+}
+
+/// Bar warning about catalog entries that were skipped because they are invalid.
+fn create_catalog_infobar() -> (gtk::InfoBar, gtk::Label) {
+    let infobar = gtk::InfoBar::new();
+    infobar.set_message_type(gtk::MessageType::Warning);
+    infobar.set_show_close_button(true);
+    infobar.connect_response(|infobar, response| {
+        infobar.hide();
+        if response != gtk::ResponseType::Close {
+            return;
+        }
+        let details = infobar
+            .content_area()
+            .children()
+            .first()
+            .and_then(|label| label.tooltip_text())
+            .map(|details| details.to_string())
+            .unwrap_or_default();
+        let notice = catalog_notice_id(&details);
+        settings::update(|settings| {
+            settings
+                .dismissed_notices
+                .retain(|x| !x.starts_with(CATALOG_NOTICE));
+            settings.dismissed_notices.push(notice);
+        });
+    });
+
+    let label = gtk::Label::new(None);
+    infobar.content_area().add(&label);
+
+    (infobar, label)
+}
+
+pub fn create_column(
     title: &str,
     cell: &impl IsA<gtk::CellRenderer>,
     attr: &str,
@@ -0,0 +1,474 @@
+use crate::hooks::HookAction;
+use crate::system_helper::{valid_package_name, valid_unit_name};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CatalogApp {
+    pub name: String,
+    pub pkg: String,
+    #[serde(default = "default_icon")]
+    pub icon: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub extra: Vec<String>,
+    #[serde(default)]
+    pub filter: Vec<String>,
+    #[serde(default)]
+    pub desktop: Vec<String>,
+    /// Systemd units enabled once the app is installed.
+    #[serde(default)]
+    pub units: Vec<String>,
+    #[serde(default)]
+    pub post_install: Vec<HookAction>,
+    #[serde(default)]
+    pub pre_remove: Vec<HookAction>,
+    /// File which provided the entry, or last overrode it.
+    #[serde(skip)]
+    pub origin: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CatalogGroup {
+    pub name: String,
+    pub icon: String,
+    pub description: String,
+    pub filter: Vec<String>,
+    pub desktop: Vec<String>,
+    pub apps: Vec<CatalogApp>,
+    #[serde(skip)]
+    pub origin: String,
+}
+
+/// Group as found in a catalog layer.
+/// Unset fields keep the value of a group with the same name from a previous layer.
+#[derive(Deserialize)]
+struct CatalogGroupLayer {
+    name: String,
+    icon: Option<String>,
+    description: Option<String>,
+    filter: Option<Vec<String>>,
+    desktop: Option<Vec<String>>,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    apps: Vec<serde_json::Value>,
+}
+
+/// App as found in a catalog layer, matched by `pkg` against the apps of its group.
+#[derive(Deserialize)]
+struct CatalogAppLayer {
+    pkg: String,
+    name: Option<String>,
+    icon: Option<String>,
+    description: Option<String>,
+    extra: Option<Vec<String>>,
+    filter: Option<Vec<String>>,
+    desktop: Option<Vec<String>>,
+    units: Option<Vec<String>>,
+    post_install: Option<Vec<HookAction>>,
+    pre_remove: Option<Vec<HookAction>>,
+    #[serde(default)]
+    hidden: bool,
+}
+
+/// Invalid catalog entry, `path` points to the entry in the json document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogIssue {
+    pub origin: String,
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Catalog {
+    pub groups: Vec<CatalogGroup>,
+    pub issues: Vec<CatalogIssue>,
+}
+
+fn default_icon() -> String {
+    String::from("package-x-generic")
+}
+
+impl fmt::Display for CatalogIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.origin.is_empty() {
+            write!(f, "{}: ", self.origin)?;
+        }
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl CatalogApp {
+    /// Main package followed by the extra packages.
+    pub fn packages(&self) -> Vec<String> {
+        let mut packages = vec![self.pkg.clone()];
+        packages.extend(self.extra.iter().cloned());
+        packages
+    }
+}
+
+impl Catalog {
+    /// Loads the catalog, never fails: problems are recorded in `issues`.
+    pub fn load(path: &Path) -> Self {
+        let mut catalog = Self::default();
+        catalog.merge_file(path);
+        catalog
+    }
+
+    /// Loads `base`, then every `*.json` file of the drop-in directories on top of it.
+    /// Directories are applied in the given order, files sorted by name.
+    pub fn load_layered(base: &Path, dropin_dirs: &[PathBuf]) -> Self {
+        let mut catalog = Self::load(base);
+        for dropin_dir in dropin_dirs {
+            for layer in layer_files(dropin_dir) {
+                catalog.merge_file(&layer);
+            }
+        }
+
+        catalog
+    }
+
+    pub fn merge_file(&mut self, path: &Path) {
+        let origin = path.display().to_string();
+        match fs::read_to_string(path) {
+            Ok(data) => self.merge_json(&data, &origin),
+            Err(err) => self.issue(&origin, "$", &format!("unable to read: {err}")),
+        }
+    }
+
+    pub fn merge_json(&mut self, data: &str, origin: &str) {
+        match serde_json::from_str::<serde_json::Value>(data) {
+            Ok(document) => self.merge_value(&document, origin),
+            Err(err) => self.issue(origin, "$", &format!("invalid json: {err}")),
+        }
+    }
+
+    /// Applies a catalog layer:
+    /// - groups and apps not known yet are added,
+    /// - fields set on known groups and apps override the previous value,
+    /// - `"hidden": true` removes a known group or app.
+    pub fn merge_value(&mut self, document: &serde_json::Value, origin: &str) {
+        let groups = match document.as_array() {
+            Some(groups) => groups,
+            None => {
+                self.issue(origin, "$", "expected an array of groups");
+                return;
+            }
+        };
+
+        for (group_index, group_value) in groups.iter().enumerate() {
+            let group_path = format!("$[{group_index}]");
+            let layer = match CatalogGroupLayer::deserialize(group_value) {
+                Ok(layer) => layer,
+                Err(err) => {
+                    self.issue(origin, &group_path, &err.to_string());
+                    continue;
+                }
+            };
+            if layer.name.trim().is_empty() {
+                self.issue(origin, &format!("{group_path}.name"), "empty group name");
+                continue;
+            }
+
+            let position = self.groups.iter().position(|g| g.name == layer.name);
+            let group_idx = match (position, layer.hidden) {
+                (Some(position), true) => {
+                    self.groups.remove(position);
+                    continue;
+                }
+                (None, true) => continue,
+                (Some(position), false) => position,
+                (None, false) => {
+                    self.groups.push(CatalogGroup {
+                        name: layer.name.clone(),
+                        icon: default_icon(),
+                        ..Default::default()
+                    });
+                    self.groups.len() - 1
+                }
+            };
+
+            let group = &mut self.groups[group_idx];
+            // a layer only adding apps to the group leaves it where it was defined
+            let changes_group = position.is_none()
+                || layer.icon.is_some()
+                || layer.description.is_some()
+                || layer.filter.is_some()
+                || layer.desktop.is_some();
+            if changes_group {
+                group.origin = String::from(origin);
+            }
+            if let Some(icon) = layer.icon {
+                group.icon = icon;
+            }
+            if let Some(description) = layer.description {
+                group.description = description;
+            }
+            if let Some(filter) = layer.filter {
+                group.filter = filter;
+            }
+            if let Some(desktop) = layer.desktop {
+                group.desktop = desktop;
+            }
+
+            for (app_index, app_value) in layer.apps.iter().enumerate() {
+                let app_path = format!("{group_path}.apps[{app_index}]");
+                if let Err((path, message)) =
+                    merge_app(&mut self.groups[group_idx], app_value, origin)
+                {
+                    let path = match path {
+                        Some(field) => format!("{app_path}.{field}"),
+                        None => app_path,
+                    };
+                    self.issue(origin, &path, &message);
+                }
+            }
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn issue(&mut self, origin: &str, path: &str, message: &str) {
+        self.issues.push(CatalogIssue {
+            origin: String::from(origin),
+            path: String::from(path),
+            message: String::from(message),
+        });
+    }
+}
+
+/// Lists the catalog layers of a drop-in directory, sorted by file name.
+pub fn layer_files(dropin_dir: &Path) -> Vec<PathBuf> {
+    let mut layers = match fs::read_dir(dropin_dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<PathBuf>>(),
+        Err(_) => Vec::new(),
+    };
+    layers.sort();
+
+    layers
+}
+
+type MergeError = (Option<&'static str>, String);
+
+fn merge_app(
+    group: &mut CatalogGroup,
+    app_value: &serde_json::Value,
+    origin: &str,
+) -> Result<(), MergeError> {
+    let layer = CatalogAppLayer::deserialize(app_value).map_err(|err| (None, err.to_string()))?;
+    let position = group.apps.iter().position(|app| app.pkg == layer.pkg);
+
+    match (position, layer.hidden) {
+        (Some(position), true) => {
+            group.apps.remove(position);
+        }
+        (None, true) => (),
+        (Some(position), false) => {
+            let mut app = group.apps[position].clone();
+            app.origin = String::from(origin);
+            if let Some(name) = layer.name {
+                app.name = name;
+            }
+            if let Some(icon) = layer.icon {
+                app.icon = icon;
+            }
+            if let Some(description) = layer.description {
+                app.description = description;
+            }
+            if let Some(extra) = layer.extra {
+                app.extra = extra;
+            }
+            if let Some(filter) = layer.filter {
+                app.filter = filter;
+            }
+            if let Some(desktop) = layer.desktop {
+                app.desktop = desktop;
+            }
+            if let Some(units) = layer.units {
+                app.units = units;
+            }
+            if let Some(post_install) = layer.post_install {
+                app.post_install = post_install;
+            }
+            if let Some(pre_remove) = layer.pre_remove {
+                app.pre_remove = pre_remove;
+            }
+            validate_app(&app).map_err(|(field, message)| (Some(field), String::from(message)))?;
+            group.apps[position] = app;
+        }
+        (None, false) => {
+            let mut app =
+                CatalogApp::deserialize(app_value).map_err(|err| (None, err.to_string()))?;
+            app.origin = String::from(origin);
+            validate_app(&app).map_err(|(field, message)| (Some(field), String::from(message)))?;
+            group.apps.push(app);
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_app(app: &CatalogApp) -> Result<(), (&'static str, &'static str)> {
+    if app.name.trim().is_empty() {
+        return Err(("name", "empty application name"));
+    }
+    if !valid_package_name(&app.pkg) {
+        return Err(("pkg", "invalid package name"));
+    }
+    if !app.extra.iter().all(|pkg| valid_package_name(pkg)) {
+        return Err(("extra", "invalid package name"));
+    }
+    if !app.units.iter().all(|unit| valid_unit_name(unit)) {
+        return Err(("units", "invalid unit name"));
+    }
+    if !app.post_install.iter().all(|hook| hook.validate().is_ok()) {
+        return Err(("post_install", "invalid hook argument"));
+    }
+    if !app.pre_remove.iter().all(|hook| hook.validate().is_ok()) {
+        return Err(("pre_remove", "invalid hook argument"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn from_json(data: &str) -> Catalog {
+        let mut catalog = Catalog::default();
+        catalog.merge_json(data, "");
+        catalog
+    }
+
+    #[test]
+    fn defaults_optional_fields() {
+        let catalog =
+            from_json(r#"[{"name": "Browsers", "apps": [{"name": "Firefox", "pkg": "firefox"}]}]"#);
+        assert!(catalog.is_valid());
+        let app = &catalog.groups[0].apps[0];
+        assert_eq!(app.icon, "package-x-generic");
+        assert!(app.extra.is_empty() && app.filter.is_empty() && app.desktop.is_empty());
+        assert_eq!(app.packages(), vec!["firefox"]);
+    }
+
+    #[test]
+    fn reports_every_bad_entry() {
+        let catalog = from_json(
+            r#"[
+                {"name": "Browsers", "apps": [
+                    {"name": "Firefox", "pkg": "firefox"},
+                    {"name": "Broken"},
+                    {"name": "Bad", "pkg": "bad pkg"},
+                    {"name": "Printing", "pkg": "cups", "units": ["cups; reboot"]},
+                    {"name": "Hidden", "pkg": "firefox", "extra": [".hidden"]}
+                ]},
+                {"icon": "office"},
+                {"name": "Office", "apps": [{"name": "LibreOffice", "pkg": 3}]}
+            ]"#,
+        );
+        let paths = catalog
+            .issues
+            .iter()
+            .map(|issue| issue.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "$[0].apps[1]",
+                "$[0].apps[2].pkg",
+                "$[0].apps[3].units",
+                "$[0].apps[4].extra",
+                "$[1]",
+                "$[2].apps[0]"
+            ]
+        );
+        assert_eq!(catalog.groups.len(), 2);
+        assert_eq!(catalog.groups[0].apps.len(), 1);
+        assert!(catalog.groups[1].apps.is_empty());
+    }
+
+    #[test]
+    fn merges_layers() {
+        let mut catalog = from_json(
+            r#"[
+                {"name": "Browsers", "description": "Web", "apps": [
+                    {"name": "Firefox", "pkg": "firefox"},
+                    {"name": "Chromium", "pkg": "chromium"}
+                ]},
+                {"name": "Games", "apps": [{"name": "Steam", "pkg": "steam"}]}
+            ]"#,
+        );
+        catalog.merge_json(
+            r#"[
+                {"name": "Browsers", "apps": [
+                    {"pkg": "firefox", "description": "Our browser"},
+                    {"pkg": "chromium", "hidden": true},
+                    {"name": "Falkon", "pkg": "falkon"},
+                    {"pkg": "missing-name"}
+                ]},
+                {"name": "Games", "hidden": true},
+                {"name": "Board tools", "apps": [{"name": "Flasher", "pkg": "vaaman-flasher"}]}
+            ]"#,
+            "/etc/vaamos-menu/catalog.d/10-board.json",
+        );
+
+        let names = catalog
+            .groups
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Browsers", "Board tools"]);
+
+        let browsers = &catalog.groups[0];
+        assert_eq!(browsers.description, "Web");
+        // only apps were added, the group stays with the bundled catalog
+        assert_eq!(browsers.origin, "");
+        assert_eq!(
+            catalog.groups[1].origin,
+            "/etc/vaamos-menu/catalog.d/10-board.json"
+        );
+        let pkgs = browsers
+            .apps
+            .iter()
+            .map(|app| app.pkg.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(pkgs, vec!["firefox", "falkon"]);
+        assert_eq!(browsers.apps[0].name, "Firefox");
+        assert_eq!(browsers.apps[0].description, "Our browser");
+        assert_eq!(
+            browsers.apps[0].origin,
+            "/etc/vaamos-menu/catalog.d/10-board.json"
+        );
+
+        assert_eq!(catalog.issues.len(), 1);
+        assert_eq!(catalog.issues[0].path, "$[0].apps[3]");
+    }
+
+    #[test]
+    fn bundled_catalog_is_valid() {
+        let catalog = Catalog::load(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/data/application_utility/default.json"
+        )));
+        assert_eq!(catalog.issues, vec![]);
+        assert!(!catalog.groups.is_empty());
+    }
+
+    #[test]
+    fn reports_invalid_document() {
+        assert_eq!(from_json("{").issues[0].path, "$");
+        assert_eq!(from_json("{}").issues[0].path, "$");
+    }
+}
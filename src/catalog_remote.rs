@@ -0,0 +1,320 @@
+use crate::catalog::Catalog;
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Debug)]
+pub enum RemoteCatalogError {
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    /// No signing key is installed, remote catalogs cannot be trusted.
+    MissingKeyring(PathBuf),
+    BadSignature(String),
+    Invalid(String),
+}
+
+impl fmt::Display for RemoteCatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(err) => write!(f, "download failed: {err}"),
+            Self::Io(err) => write!(f, "unable to write the cache: {err}"),
+            Self::MissingKeyring(path) => write!(f, "no signing key in {}", path.display()),
+            Self::BadSignature(reason) => write!(f, "bad signature: {reason}"),
+            Self::Invalid(reason) => write!(f, "invalid catalog: {reason}"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for RemoteCatalogError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Http(err)
+    }
+}
+
+impl From<std::io::Error> for RemoteCatalogError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+pub fn default_cache_dir() -> PathBuf {
+    glib::user_cache_dir().join("vaamos-menu")
+}
+
+/// Location of the cached snapshot inside `cache_dir`.
+pub fn cached_catalog_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("catalog.json")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn signature_path(catalog_path: &Path) -> PathBuf {
+    with_suffix(catalog_path, ".sig")
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    glib::compute_checksum_for_data(glib::ChecksumType::Sha256, data)
+        .map(|checksum| checksum.to_string())
+        .unwrap_or_default()
+}
+
+/// Checks the detached OpenPGP signature of `data_path` against the keys of `keyring`, with gpgv.
+pub fn verify_signature(
+    keyring: &Path,
+    data_path: &Path,
+    signature_path: &Path,
+) -> Result<(), RemoteCatalogError> {
+    if !keyring.is_file() {
+        return Err(RemoteCatalogError::MissingKeyring(keyring.to_path_buf()));
+    }
+    let output = Command::new("gpgv")
+        .arg("--keyring")
+        .arg(keyring)
+        .arg(signature_path)
+        .arg(data_path)
+        .output()
+        .map_err(|err| RemoteCatalogError::BadSignature(format!("unable to run gpgv: {err}")))?;
+
+    match output.status.success() {
+        true => Ok(()),
+        false => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().last().unwrap_or("not signed by a known key");
+            Err(RemoteCatalogError::BadSignature(String::from(
+                reason.trim_start_matches("gpgv: "),
+            )))
+        }
+    }
+}
+
+/// Returns the cached snapshot, if it is still signed by a key of `keyring`.
+pub fn cached_catalog(cache_dir: &Path, keyring: &Path) -> Option<PathBuf> {
+    let catalog_path = cached_catalog_path(cache_dir);
+    if !catalog_path.is_file() {
+        return None;
+    }
+
+    match verify_signature(keyring, &catalog_path, &signature_path(&catalog_path)) {
+        Ok(_) => Some(catalog_path),
+        Err(err) => {
+            eprintln!("ignoring the cached catalog: {err}");
+            None
+        }
+    }
+}
+
+/// Downloads the catalog and its detached signature from `url`,
+/// and stores them into `cache_dir` once verified against `keyring`.
+/// Blocking: must not run on the GTK main thread.
+pub fn refresh_catalog(
+    url: &str,
+    keyring: &Path,
+    cache_dir: &Path,
+) -> Result<PathBuf, RemoteCatalogError> {
+    if !keyring.is_file() {
+        return Err(RemoteCatalogError::MissingKeyring(keyring.to_path_buf()));
+    }
+    let client = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+
+    let data = client.get(url).send()?.error_for_status()?.bytes()?;
+    let signature = client
+        .get(format!("{url}.sig"))
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+
+    // gpgv reads files, the download is only moved into place once verified
+    fs::create_dir_all(cache_dir)?;
+    let catalog_path = cached_catalog_path(cache_dir);
+    let (tmp_catalog, tmp_signature) = (
+        with_suffix(&catalog_path, ".tmp"),
+        with_suffix(&signature_path(&catalog_path), ".tmp"),
+    );
+    fs::write(&tmp_catalog, &data)?;
+    fs::write(&tmp_signature, &signature)?;
+    let verified = verify_signature(keyring, &tmp_catalog, &tmp_signature).and_then(|_| {
+        // Entries are validated later, only reject documents which are not a catalog at all.
+        let document = serde_json::from_slice::<serde_json::Value>(&data)
+            .map_err(|err| RemoteCatalogError::Invalid(err.to_string()))?;
+        let mut catalog = Catalog::default();
+        catalog.merge_value(&document, url);
+        match catalog.groups.is_empty() {
+            true => Err(RemoteCatalogError::Invalid(
+                catalog
+                    .issues
+                    .first()
+                    .map_or_else(|| String::from("no groups"), |issue| issue.message.clone()),
+            )),
+            false => Ok(()),
+        }
+    });
+    if let Err(err) = verified {
+        let _ = fs::remove_file(&tmp_catalog);
+        let _ = fs::remove_file(&tmp_signature);
+        return Err(err);
+    }
+
+    // the signature last: until it is in place the snapshot fails its check and is not used
+    fs::rename(&tmp_catalog, &catalog_path)?;
+    fs::rename(&tmp_signature, signature_path(&catalog_path))?;
+
+    Ok(catalog_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    const CATALOG: &str = r#"[{"name": "Tools", "apps": [{"name": "Vim", "pkg": "vim"}]}]"#;
+
+    /// Serves `responses` (path, body) over plain HTTP, one request per connection.
+    fn serve(responses: Vec<(&'static str, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0_u8; 1024];
+                let len = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..len]);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let response = match responses.iter().find(|(p, _)| *p == path) {
+                    Some((_, body)) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    ),
+                    None => String::from(
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    ),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        format!("http://{address}")
+    }
+
+    fn gpg(gnupg_home: &Path, args: &[&str]) -> Vec<u8> {
+        let output = Command::new("gpg")
+            .env("GNUPGHOME", gnupg_home)
+            .args([
+                "--batch",
+                "--quiet",
+                "--pinentry-mode",
+                "loopback",
+                "--passphrase",
+                "",
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    }
+
+    /// A keyring with a new key in `dir`, and a function signing with it.
+    fn signing_key(dir: &Path) -> (PathBuf, impl Fn(&str) -> String) {
+        let gnupg_home = dir.join("gnupg");
+        fs::create_dir_all(&gnupg_home).unwrap();
+        gpg(
+            &gnupg_home,
+            &[
+                "--quick-gen-key",
+                "Catalog <catalog@example.org>",
+                "ed25519",
+                "sign",
+                "never",
+            ],
+        );
+        let keyring = dir.join("keyring.gpg");
+        fs::write(&keyring, gpg(&gnupg_home, &["--export"])).unwrap();
+
+        let sign = move |data: &str| {
+            let data_path = gnupg_home.join("data");
+            fs::write(&data_path, data).unwrap();
+            let signature = gpg(
+                &gnupg_home,
+                &[
+                    "--armor",
+                    "--detach-sign",
+                    "--output",
+                    "-",
+                    data_path.to_str().unwrap(),
+                ],
+            );
+            String::from_utf8(signature).unwrap()
+        };
+        (keyring, sign)
+    }
+
+    #[test]
+    fn checksum_format() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn refresh_and_cache() {
+        let installed = |program: &str| Command::new(program).arg("--version").output().is_ok();
+        if !installed("gpg") || !installed("gpgv") {
+            eprintln!("gpg or gpgv not found, skipping");
+            return;
+        }
+
+        let keys = TempDir::new("keys");
+        let key_dir = keys.path();
+        let (keyring, sign) = signing_key(key_dir);
+        let (_, sign_other) = signing_key(&key_dir.join("other"));
+        let base_url = serve(vec![
+            ("/catalog.json", String::from(CATALOG)),
+            ("/catalog.json.sig", sign(CATALOG)),
+            ("/tampered.json", String::from(CATALOG)),
+            ("/tampered.json.sig", sign("[]")),
+            ("/foreign.json", String::from(CATALOG)),
+            ("/foreign.json.sig", sign_other(CATALOG)),
+            ("/unsigned.json", String::from(CATALOG)),
+        ]);
+        let cache = TempDir::new("refresh");
+        let cache_dir = cache.path();
+
+        for name in ["tampered", "foreign"] {
+            let result = refresh_catalog(&format!("{base_url}/{name}.json"), &keyring, cache_dir);
+            assert!(matches!(result, Err(RemoteCatalogError::BadSignature(_))));
+            assert!(cached_catalog(cache_dir, &keyring).is_none());
+        }
+
+        let result = refresh_catalog(&format!("{base_url}/unsigned.json"), &keyring, cache_dir);
+        assert!(matches!(result, Err(RemoteCatalogError::Http(_))));
+        let result = refresh_catalog(
+            &format!("{base_url}/catalog.json"),
+            &key_dir.join("missing.gpg"),
+            cache_dir,
+        );
+        assert!(matches!(result, Err(RemoteCatalogError::MissingKeyring(_))));
+
+        let path =
+            refresh_catalog(&format!("{base_url}/catalog.json"), &keyring, cache_dir).unwrap();
+        assert_eq!(cached_catalog(cache_dir, &keyring), Some(path.clone()));
+        assert_eq!(fs::read_to_string(&path).unwrap(), CATALOG);
+
+        // A modified cache is ignored.
+        fs::write(&path, "[]").unwrap();
+        assert!(cached_catalog(cache_dir, &keyring).is_none());
+    }
+}
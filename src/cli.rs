@@ -0,0 +1,415 @@
+use crate::alpm_helper::{AlpmHelper, UpdateReport};
+use crate::application_browser;
+use crate::catalog::Catalog;
+use crate::catalog_remote;
+use crate::package_search;
+use crate::preferences::Preferences;
+use crate::profile::{Profile, ProfileDiff};
+use crate::system_helper::{self, HelperClient};
+use crate::utils::{self, PacmanWrapper};
+
+use serde_json::json;
+use subprocess::{Exec, Redirection};
+
+use std::path::{Path, PathBuf};
+
+pub const EXIT_SUCCESS: i32 = 0;
+/// The command ran, but what it had to do failed.
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+const COMMANDS: [&str; 5] = ["apps", "profile", "tweaks", "autostart", "catalog"];
+
+const USAGE: &str = "Usage:
+  vaamos-menu apps list [--installed]
+  vaamos-menu apps install|remove <app or package>...
+  vaamos-menu profile save|diff|apply <file>
+  vaamos-menu tweaks update|orphans|clear-cache|unlock-db
+  vaamos-menu autostart on|off
+  vaamos-menu catalog validate [file...]
+
+Results are printed on stdout as JSON.";
+
+/// Runs a command line action, `None` when `args` do not name one and the window must open.
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = args.get(1)?;
+    if command == "--help" || command == "help" {
+        println!("{USAGE}");
+        return Some(EXIT_SUCCESS);
+    }
+    if !COMMANDS.contains(&command.as_str()) {
+        return None;
+    }
+
+    let action = args.get(2).map_or("", String::as_str);
+    let operands = args.get(3..).unwrap_or_default();
+    let result = match (command.as_str(), action) {
+        ("apps", "list") => apps_list(operands),
+        ("apps", "install") => apps_update(operands, true),
+        ("apps", "remove") => apps_update(operands, false),
+        ("profile", action) if operands.len() == 1 => run_profile(action, &operands[0]),
+        ("tweaks", tweak) if operands.is_empty() => run_tweak(tweak),
+        ("autostart", "on") if operands.is_empty() => autostart(true),
+        ("autostart", "off") if operands.is_empty() => autostart(false),
+        ("catalog", "validate") => catalog_validate(operands),
+        _ => Err(CliError::Usage(format!(
+            "unknown command: {}",
+            args[1..].join(" ")
+        ))),
+    };
+
+    Some(match result {
+        Ok((output, exit_code)) => {
+            println!("{output:#}");
+            exit_code
+        }
+        Err(CliError::Usage(reason)) => {
+            eprintln!("{reason}\n\n{USAGE}");
+            EXIT_USAGE
+        }
+        Err(CliError::Failed(reason)) => {
+            println!("{:#}", json!({ "success": false, "error": reason }));
+            EXIT_FAILURE
+        }
+    })
+}
+
+enum CliError {
+    Usage(String),
+    Failed(String),
+}
+
+type CliResult = Result<(serde_json::Value, i32), CliError>;
+
+fn read_preferences() -> Preferences {
+    let (preferences, issues) = Preferences::load();
+    for issue in issues {
+        eprintln!("preferences: {issue}");
+    }
+    preferences
+}
+
+/// The catalog shown in the window, with the remote snapshot if one is configured.
+fn load_catalog() -> Catalog {
+    let preferences = read_preferences();
+    let mut catalog_base = application_browser::default_catalog_path();
+    if !preferences.catalog_url.is_empty() {
+        let cached = catalog_remote::cached_catalog(
+            &catalog_remote::default_cache_dir(),
+            Path::new(&preferences.catalog_keyring),
+        );
+        if let Some(cached) = cached {
+            catalog_base = cached;
+        }
+    }
+
+    application_browser::load_catalog(&catalog_base)
+}
+
+fn new_alpm() -> Result<alpm::Alpm, CliError> {
+    let pacman = pacmanconf::Config::with_opts(None, Some("/etc/pacman.conf"), Some("/"))
+        .map_err(|err| CliError::Failed(format!("unable to read pacman.conf: {err}")))?;
+    alpm_utils::alpm_with_conf(&pacman)
+        .map_err(|err| CliError::Failed(format!("unable to initialize alpm: {err}")))
+}
+
+fn apps_list(operands: &[String]) -> CliResult {
+    let installed_only = match operands {
+        [] => false,
+        [flag] if flag == "--installed" => true,
+        _ => {
+            return Err(CliError::Usage(format!(
+                "unknown option: {}",
+                operands.join(" ")
+            )))
+        }
+    };
+    let catalog = load_catalog();
+    let alpm_handle = new_alpm()?;
+    let localdb = alpm_handle.localdb();
+
+    let mut apps: Vec<serde_json::Value> = Vec::new();
+    for group in &catalog.groups {
+        for app in &group.apps {
+            let installed = localdb.pkg(app.pkg.as_str()).is_ok();
+            if installed_only && !installed {
+                continue;
+            }
+            apps.push(json!({
+                "group": group.name,
+                "name": app.name,
+                "pkg": app.pkg,
+                "packages": app.packages(),
+                "description": app.description,
+                "installed": installed,
+            }));
+        }
+    }
+
+    Ok((json!(apps), EXIT_SUCCESS))
+}
+
+/// Pending list entries for `targets`, catalog apps by name or package, or plain packages.
+fn resolve_targets(catalog: &Catalog, targets: &[String]) -> Result<Vec<String>, CliError> {
+    let mut entries: Vec<String> = Vec::new();
+    for target in targets {
+        let app = catalog
+            .groups
+            .iter()
+            .flat_map(|group| &group.apps)
+            .find(|app| app.pkg == *target || app.name.eq_ignore_ascii_case(target));
+        let entry = match app {
+            Some(app) => app.packages().join(" "),
+            None if system_helper::valid_package_name(target) => target.clone(),
+            None => return Err(CliError::Usage(format!("invalid package name: {target}"))),
+        };
+        if !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+fn report_json(report: &UpdateReport) -> serde_json::Value {
+    let packages = report
+        .packages
+        .iter()
+        .map(|pkg| {
+            json!({
+                "package": pkg.package,
+                "outcome": pkg.outcome.id(),
+                "reason": pkg.reason,
+            })
+        })
+        .collect::<Vec<_>>();
+    let hooks = report
+        .hooks
+        .iter()
+        .map(|hook| {
+            let (action, argument) = hook.action.parts();
+            json!({
+                "package": hook.package,
+                "stage": hook.stage.id(),
+                "action": action,
+                "argument": argument,
+                "success": hook.error.is_none(),
+                "reason": hook.error,
+            })
+        })
+        .collect::<Vec<_>>();
+    let success = !report.packages.iter().any(|pkg| pkg.pending)
+        && !report.hooks.iter().any(|hook| hook.error.is_some());
+
+    json!({ "success": success, "packages": packages, "hooks": hooks })
+}
+
+fn apps_update(targets: &[String], install: bool) -> CliResult {
+    if targets.is_empty() {
+        return Err(CliError::Usage(String::from("no app or package given")));
+    }
+    let catalog = load_catalog();
+
+    let mut alpm_helper = AlpmHelper::new();
+    let entries = resolve_targets(&catalog, targets)?;
+    match install {
+        true => alpm_helper.pkg_list_install = entries,
+        false => alpm_helper.pkg_list_removal = entries,
+    }
+
+    let report = alpm_helper.do_update_headless(&catalog);
+    let output = report_json(&report);
+    let exit_code = match output["success"].as_bool() {
+        Some(true) => EXIT_SUCCESS,
+        _ => EXIT_FAILURE,
+    };
+
+    Ok((output, exit_code))
+}
+
+fn diff_json(diff: &ProfileDiff) -> serde_json::Value {
+    json!({
+        "to_install": diff.to_install,
+        "to_remove": diff.to_remove,
+        "not_in_profile": diff.not_in_profile,
+    })
+}
+
+/// Profiles saved from the command line only hold the installed apps, nothing is pending.
+fn run_profile(action: &str, file: &str) -> CliResult {
+    let path = PathBuf::from(file);
+    let catalog = load_catalog();
+    let alpm_handle = new_alpm()?;
+    let localdb = alpm_handle.localdb();
+    let is_installed = |pkg_name: &str| localdb.pkg(pkg_name).is_ok();
+
+    if action == "save" {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let profile = Profile::capture(&name, &catalog, is_installed, &[], &[]);
+        profile
+            .save(&path)
+            .map_err(|err| CliError::Failed(format!("{file}: {err}")))?;
+        return Ok((
+            json!({ "success": true, "installed": profile.installed }),
+            EXIT_SUCCESS,
+        ));
+    }
+
+    let profile = Profile::load(&path).map_err(|err| CliError::Failed(format!("{file}: {err}")))?;
+    let diff = profile.diff(&catalog, is_installed);
+    match action {
+        "diff" => Ok((diff_json(&diff), EXIT_SUCCESS)),
+        "apply" => {
+            let mut alpm_helper = AlpmHelper::new();
+            alpm_helper.pkg_list_install = diff.to_install.clone();
+            alpm_helper.pkg_list_removal = diff.to_remove.clone();
+
+            let report = alpm_helper.do_update_headless(&catalog);
+            let mut output = report_json(&report);
+            let exit_code = match output["success"].as_bool() {
+                Some(true) => EXIT_SUCCESS,
+                _ => EXIT_FAILURE,
+            };
+            output["diff"] = diff_json(&diff);
+            Ok((output, exit_code))
+        }
+        _ => Err(CliError::Usage(format!("unknown profile action: {action}"))),
+    }
+}
+
+/// Wrappers escalate by themselves, plain pacman goes through the system helper.
+fn run_tweak(tweak: &str) -> CliResult {
+    let wrapper = utils::get_pacman_wrapper();
+    let wrapper_cmd = match wrapper {
+        PacmanWrapper::Pak => "pak",
+        PacmanWrapper::Yay => "yay",
+        PacmanWrapper::Paru => "paru",
+        PacmanWrapper::Pacman => "",
+    };
+
+    let mut packages: Vec<String> = Vec::new();
+    let result = match tweak {
+        "unlock-db" => HelperClient::system()
+            .map_err(|err| err.to_string())
+            .and_then(|client| client.remove_db_lock()),
+        "orphans" => {
+            packages = package_search::orphan_packages(&new_alpm()?);
+            match (packages.is_empty(), &wrapper) {
+                (true, _) => Ok(()),
+                (false, PacmanWrapper::Pacman) => HelperClient::system()
+                    .map_err(|err| err.to_string())
+                    .and_then(|client| client.remove(&packages)),
+                // like the system helper, only the listed orphans: not the dependencies
+                // they leave behind, the next run lists those
+                (false, _) => run_wrapper(wrapper_cmd, "-R", &packages),
+            }
+        }
+        "update" | "clear-cache" => {
+            let option = match tweak {
+                "update" => "-Syu",
+                _ => "-Sc",
+            };
+            match wrapper {
+                PacmanWrapper::Pacman => HelperClient::system()
+                    .map_err(|err| err.to_string())
+                    .and_then(|client| match tweak {
+                        "update" => client.sync_upgrade(),
+                        _ => client.clear_cache(),
+                    }),
+                _ => run_wrapper(wrapper_cmd, option, &[]),
+            }
+        }
+        _ => return Err(CliError::Usage(format!("unknown tweak: {tweak}"))),
+    };
+
+    let mut output = json!({
+        "tweak": tweak,
+        "success": result.is_ok(),
+        "error": result.as_ref().err(),
+    });
+    if tweak == "orphans" {
+        output["packages"] = json!(packages);
+    }
+    let exit_code = match result {
+        Ok(_) => EXIT_SUCCESS,
+        Err(_) => EXIT_FAILURE,
+    };
+
+    Ok((output, exit_code))
+}
+
+/// Runs an AUR helper in the current terminal, its output goes to stderr.
+fn run_wrapper(wrapper_cmd: &str, option: &str, packages: &[String]) -> Result<(), String> {
+    let exit_status = Exec::cmd(wrapper_cmd)
+        .arg(option)
+        .args(packages)
+        // stdout is kept for the JSON result
+        .stdout(Redirection::Merge)
+        .join()
+        .map_err(|err| format!("unable to run {wrapper_cmd}: {err}"))?;
+
+    match exit_status.success() {
+        true => Ok(()),
+        false => Err(format!("{wrapper_cmd} exited with {exit_status:?}")),
+    }
+}
+
+fn autostart(enable: bool) -> CliResult {
+    let preferences = read_preferences();
+    let autostart_path = utils::fix_path(&preferences.autostart_path);
+
+    utils::set_autostart(&autostart_path, &preferences.desktop_path, enable)
+        .map_err(|err| CliError::Failed(format!("{autostart_path}: {err}")))?;
+
+    Ok((
+        json!({ "success": true, "autostart": enable }),
+        EXIT_SUCCESS,
+    ))
+}
+
+fn catalog_validate(files: &[String]) -> CliResult {
+    let catalog = match files {
+        [] => load_catalog(),
+        _ => {
+            let mut catalog = Catalog::default();
+            for file in files {
+                catalog.merge_file(&PathBuf::from(file));
+            }
+            catalog
+        }
+    };
+
+    let issues = catalog
+        .issues
+        .iter()
+        .map(|issue| {
+            json!({
+                "origin": issue.origin,
+                "path": issue.path,
+                "message": issue.message,
+            })
+        })
+        .collect::<Vec<_>>();
+    let app_count = catalog
+        .groups
+        .iter()
+        .map(|group| group.apps.len())
+        .sum::<usize>();
+    let exit_code = match catalog.is_valid() {
+        true => EXIT_SUCCESS,
+        false => EXIT_FAILURE,
+    };
+
+    Ok((
+        json!({
+            "valid": catalog.is_valid(),
+            "groups": catalog.groups.len(),
+            "apps": app_count,
+            "issues": issues,
+        }),
+        exit_code,
+    ))
+}
@@ -1,16 +1,24 @@
+use crate::preferences::Preferences;
+
 #[derive(Clone, Debug)]
 #[repr(C)]
 pub struct MenuWindow {
     pub builder: gtk::Builder,
     pub window: gtk::Window,
-    pub preferences: serde_json::Value,
+    pub preferences: Preferences,
+    /// Running from the installation media.
+    pub live_session: bool,
 }
 
-#[derive(Clone, Debug)]
+/// State of the queried units, as reported by systemd.
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub struct SystemdUnits {
+    /// Units with a unit file systemd could load.
     pub loaded_units: Vec<String>,
     pub enabled_units: Vec<String>,
+    /// Units currently running, or waiting on their socket or timer.
+    pub active_units: Vec<String>,
 }
 
 impl SystemdUnits {
@@ -18,8 +26,21 @@ impl SystemdUnits {
         Self {
             loaded_units: Vec::new(),
             enabled_units: Vec::new(),
+            active_units: Vec::new(),
         }
     }
+
+    pub fn is_loaded(&self, unit: &str) -> bool {
+        self.loaded_units.iter().any(|x| x == unit)
+    }
+
+    pub fn is_enabled(&self, unit: &str) -> bool {
+        self.enabled_units.iter().any(|x| x == unit)
+    }
+
+    pub fn is_active(&self, unit: &str) -> bool {
+        self.active_units.iter().any(|x| x == unit)
+    }
 }
 
 impl Default for SystemdUnits {
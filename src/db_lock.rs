@@ -0,0 +1,182 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub const DB_LOCK_PATH: &str = "/var/lib/pacman/db.lck";
+
+/// Programs which take the database lock while they run, by process name.
+const LOCKING_PROGRAMS: &[&str] = &[
+    "pacman",
+    "yay",
+    "paru",
+    "pak",
+    "pamac",
+    "pamac-daemon",
+    "pamac-manager",
+    "pamac-installer",
+];
+
+/// `alpm_backend::WORKER_ARG`, the menu runs its libalpm worker with it.
+const ALPM_WORKER_ARG: &str = "--alpm-worker";
+
+/// A process which may be using the package database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub name: String,
+    /// It has the lock file open, others are only known to take it.
+    pub has_lock_open: bool,
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pid {})", self.name, self.pid)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LockState {
+    Absent,
+    /// Left behind by a process which is gone, it can be removed.
+    Stale,
+    Held(Vec<LockHolder>),
+}
+
+fn has_open_file(process_dir: &Path, lock_path: &Path) -> bool {
+    // only readable for processes of the same user, or by root
+    fs::read_dir(process_dir.join("fd"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| fs::read_link(entry.path()).ok())
+                .any(|target| target == lock_path)
+        })
+        .unwrap_or(false)
+}
+
+/// Processes under `proc_root` using the lock at `lock_path`, except `own_pid`.
+pub fn lock_holders(proc_root: &Path, lock_path: &Path, own_pid: u32) -> Vec<LockHolder> {
+    let entries = match fs::read_dir(proc_root) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("unable to read {}: {err}", proc_root.display());
+            return Vec::new();
+        }
+    };
+
+    let mut holders = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            if pid == own_pid {
+                return None;
+            }
+            let process_dir = entry.path();
+            let name = fs::read_to_string(process_dir.join("comm")).ok()?;
+            let name = String::from(name.trim_end());
+            let cmdline = fs::read(process_dir.join("cmdline")).unwrap_or_default();
+            let is_worker = cmdline
+                .split(|byte| *byte == 0)
+                .any(|arg| arg == ALPM_WORKER_ARG.as_bytes());
+
+            let has_lock_open = has_open_file(&process_dir, lock_path);
+            match has_lock_open || is_worker || LOCKING_PROGRAMS.contains(&name.as_str()) {
+                true => Some(LockHolder {
+                    pid,
+                    name,
+                    has_lock_open,
+                }),
+                false => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    holders.sort_by_key(|holder| holder.pid);
+    holders
+}
+
+/// Whether the lock at `lock_path` exists, and if any process under `proc_root` may hold it.
+pub fn check(lock_path: &Path, proc_root: &Path) -> LockState {
+    if !lock_path.exists() {
+        return LockState::Absent;
+    }
+    let holders = lock_holders(proc_root, lock_path, std::process::id());
+    match holders.is_empty() {
+        true => LockState::Stale,
+        false => LockState::Held(holders),
+    }
+}
+
+/// The holders, one per line.
+pub fn describe(holders: &[LockHolder]) -> String {
+    holders
+        .iter()
+        .map(|holder| holder.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    fn fake_process(proc_root: &Path, pid: u32, name: &str, args: &[&str], fds: &[&Path]) {
+        let process_dir = proc_root.join(pid.to_string());
+        fs::create_dir_all(process_dir.join("fd")).unwrap();
+        fs::write(process_dir.join("comm"), format!("{name}\n")).unwrap();
+        fs::write(process_dir.join("cmdline"), args.join("\0")).unwrap();
+        for (fd, target) in fds.iter().enumerate() {
+            std::os::unix::fs::symlink(target, process_dir.join("fd").join(fd.to_string()))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn holders_in_fake_proc() {
+        let temp_dir = TempDir::new("dblock");
+        let root = temp_dir.path();
+        let proc_root = root.join("proc");
+        let lock_path = root.join("db.lck");
+        fs::create_dir_all(&proc_root).unwrap();
+        fs::create_dir_all(proc_root.join("self")).unwrap();
+
+        fake_process(
+            &proc_root,
+            1,
+            "systemd",
+            &["/sbin/init"],
+            &[Path::new("/dev/null")],
+        );
+        fake_process(&proc_root, 300, "bash", &["bash"], &[]);
+        assert_eq!(check(&lock_path, &proc_root), LockState::Absent);
+
+        fs::write(&lock_path, "").unwrap();
+        assert_eq!(check(&lock_path, &proc_root), LockState::Stale);
+
+        fake_process(&proc_root, 4200, "yay", &["yay", "-Syu"], &[]);
+        fake_process(&proc_root, 4210, "alpm-tool", &["alpm-tool"], &[&lock_path]);
+        fake_process(
+            &proc_root,
+            4100,
+            "vaamos-menu",
+            &["/usr/bin/vaamos-menu", "--alpm-worker"],
+            &[],
+        );
+        fake_process(&proc_root, 4000, "vaamos-menu", &["vaamos-menu"], &[]);
+        let holders = lock_holders(&proc_root, &lock_path, 4200);
+        assert_eq!(
+            holders
+                .iter()
+                .map(|holder| (holder.pid, holder.has_lock_open))
+                .collect::<Vec<_>>(),
+            [(4100, false), (4210, true)]
+        );
+        assert_eq!(
+            describe(&holders),
+            "vaamos-menu (pid 4100)\nalpm-tool (pid 4210)"
+        );
+        assert!(
+            matches!(check(&lock_path, &proc_root), LockState::Held(holders) if holders.len() == 3)
+        );
+    }
+}
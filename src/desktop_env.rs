@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::Path;
+
+const SESSION_DIRS: [&str; 2] = ["/usr/share/xsessions", "/usr/share/wayland-sessions"];
+
+/// Known aliases, mapped to the identifiers used in the app catalog.
+const DESKTOP_ALIASES: [(&str, &str); 12] = [
+    ("plasma", "kde"),
+    ("plasmawayland", "kde"),
+    ("plasmax11", "kde"),
+    ("kde-plasma", "kde"),
+    ("gnome-xorg", "gnome"),
+    ("gnome-wayland", "gnome"),
+    ("ubuntu", "gnome"),
+    ("x-cinnamon", "cinnamon"),
+    ("cinnamon-wayland", "cinnamon"),
+    ("xfce4", "xfce"),
+    ("budgie-desktop", "budgie"),
+    ("lxqt-wayland", "lxqt"),
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DesktopEnvironment {
+    /// Normalized identifiers of the running session, e.g. `["gnome"]`.
+    pub names: Vec<String>,
+}
+
+impl DesktopEnvironment {
+    /// Detects the running desktop environment.
+    /// Falls back to the installed session files when the environment does not say.
+    pub fn detect() -> Self {
+        let xdg_current_desktop = std::env::var("XDG_CURRENT_DESKTOP").ok();
+        let desktop_session = std::env::var("DESKTOP_SESSION").ok();
+        let session_dirs = SESSION_DIRS.iter().map(Path::new).collect::<Vec<_>>();
+
+        Self::detect_from(
+            xdg_current_desktop.as_deref(),
+            desktop_session.as_deref(),
+            &session_dirs,
+        )
+    }
+
+    pub fn detect_from(
+        xdg_current_desktop: Option<&str>,
+        desktop_session: Option<&str>,
+        session_dirs: &[&Path],
+    ) -> Self {
+        let mut names: Vec<String> = Vec::new();
+
+        if let Some(xdg_current_desktop) = xdg_current_desktop {
+            for name in xdg_current_desktop.split(':') {
+                push_unique(&mut names, name);
+            }
+        }
+        if names.is_empty() {
+            if let Some(desktop_session) = desktop_session {
+                // DESKTOP_SESSION might be a path to the session file.
+                let session = desktop_session.rsplit('/').next().unwrap_or_default();
+                push_unique(&mut names, session.trim_end_matches(".desktop"));
+            }
+        }
+        if names.is_empty() {
+            // Only trust the session files when a single desktop is installed.
+            let installed = installed_sessions(session_dirs);
+            if installed.len() == 1 {
+                names = installed;
+            }
+        }
+
+        Self { names }
+    }
+
+    pub fn is_known(&self) -> bool {
+        !self.names.is_empty()
+    }
+
+    /// Returns whether an entry with the given `desktop` constraints is valid for this session.
+    ///
+    /// Positive entries (`"kde"`) require the session to be one of them,
+    /// negated entries (`"!gnome"`) exclude the session.
+    /// An unknown session matches everything, except for positive-only lists.
+    pub fn matches(&self, constraints: &[String]) -> bool {
+        let mut has_positive = false;
+        let mut positive_match = false;
+
+        for constraint in constraints {
+            let constraint = constraint.trim();
+            if let Some(negated) = constraint.strip_prefix('!') {
+                if self.names.contains(&normalize_desktop_name(negated)) {
+                    return false;
+                }
+            } else if !constraint.is_empty() {
+                has_positive = true;
+                if self.names.contains(&normalize_desktop_name(constraint)) {
+                    positive_match = true;
+                }
+            }
+        }
+
+        !has_positive || positive_match
+    }
+}
+
+pub fn normalize_desktop_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    DESKTOP_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, canonical)| String::from(*canonical))
+}
+
+fn push_unique(names: &mut Vec<String>, name: &str) {
+    if name.trim().is_empty() {
+        return;
+    }
+    let name = normalize_desktop_name(name);
+    if !names.contains(&name) {
+        names.push(name);
+    }
+}
+
+fn installed_sessions(session_dirs: &[&Path]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for session_dir in session_dirs {
+        let entries = match fs::read_dir(session_dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "desktop") {
+                if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    push_unique(&mut names, stem);
+                }
+            }
+        }
+    }
+    names.sort();
+
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn constraints(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| String::from(*s)).collect()
+    }
+
+    #[test]
+    fn detect_from_env() {
+        let de = DesktopEnvironment::detect_from(Some("ubuntu:GNOME"), Some("plasma"), &[]);
+        assert_eq!(de.names, vec!["gnome"]);
+
+        let de = DesktopEnvironment::detect_from(None, Some("/usr/share/xsessions/plasma"), &[]);
+        assert_eq!(de.names, vec!["kde"]);
+
+        let de = DesktopEnvironment::detect_from(None, None, &[Path::new("/nonexistent")]);
+        assert!(!de.is_known());
+    }
+
+    #[test]
+    fn match_constraints() {
+        let gnome = DesktopEnvironment::detect_from(Some("GNOME"), None, &[]);
+        let kde = DesktopEnvironment::detect_from(Some("KDE"), None, &[]);
+        let unknown = DesktopEnvironment::default();
+
+        assert!(gnome.matches(&[]));
+        assert!(!gnome.matches(&constraints(&["!gnome"])));
+        assert!(kde.matches(&constraints(&["!gnome"])));
+        assert!(kde.matches(&constraints(&["plasma", "xfce"])));
+        assert!(!gnome.matches(&constraints(&["kde", "xfce"])));
+        assert!(!kde.matches(&constraints(&["kde", "!kde"])));
+        assert!(unknown.matches(&constraints(&["!gnome"])));
+        assert!(!unknown.matches(&constraints(&["kde"])));
+    }
+}
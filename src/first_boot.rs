@@ -0,0 +1,219 @@
+use crate::catalog::Catalog;
+use crate::system_helper;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WizardStep {
+    Language,
+    Autostart,
+    Bundles,
+    Update,
+    Done,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct PresetLanguage {
+    pub id: String,
+    pub name: String,
+}
+
+/// A recommended set of catalog apps, installed together.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Bundle {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub apps: Vec<String>,
+    /// Checked when the wizard opens.
+    #[serde(default)]
+    pub default: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Presets {
+    #[serde(default = "default_steps")]
+    pub steps: Vec<WizardStep>,
+    #[serde(default)]
+    pub languages: Vec<PresetLanguage>,
+    #[serde(default)]
+    pub bundles: Vec<Bundle>,
+}
+
+fn default_steps() -> Vec<WizardStep> {
+    vec![
+        WizardStep::Language,
+        WizardStep::Autostart,
+        WizardStep::Bundles,
+        WizardStep::Update,
+        WizardStep::Done,
+    ]
+}
+
+/// Remembers that the wizard ran for this user.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct FirstBootState {
+    completed: bool,
+}
+
+pub fn state_path() -> PathBuf {
+    glib::user_config_dir().join("vaamos-menu/first_boot.json")
+}
+
+pub fn is_completed(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|data| serde_json::from_str::<FirstBootState>(&data).ok())
+        .is_some_and(|state| state.completed)
+}
+
+pub fn mark_completed(path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let data = serde_json::to_string_pretty(&FirstBootState { completed: true })?;
+    fs::write(path, data)
+}
+
+impl Presets {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(&data).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let mut presets = serde_json::from_str::<Self>(data).map_err(|err| err.to_string())?;
+
+        for (index, bundle) in presets.bundles.iter().enumerate() {
+            if bundle.id.is_empty() || bundle.name.is_empty() {
+                return Err(format!("bundle {index} needs an id and a name"));
+            }
+            if presets.bundles[..index].iter().any(|x| x.id == bundle.id) {
+                return Err(format!("duplicate bundle {}", bundle.id));
+            }
+            if let Some(pkg_name) = bundle
+                .apps
+                .iter()
+                .find(|pkg| !system_helper::valid_package_name(pkg))
+            {
+                return Err(format!("bundle {}: invalid package {pkg_name}", bundle.id));
+            }
+        }
+        // the last page applies the choices
+        presets.steps.retain(|step| *step != WizardStep::Done);
+        presets.steps.push(WizardStep::Done);
+
+        Ok(presets)
+    }
+
+    /// Languages offered for the available page `locales`, named after the presets when listed there.
+    pub fn languages_for(&self, locales: &[String]) -> Vec<PresetLanguage> {
+        locales
+            .iter()
+            .map(|locale| {
+                self.languages
+                    .iter()
+                    .find(|language| language.id == *locale)
+                    .cloned()
+                    .unwrap_or_else(|| PresetLanguage {
+                        id: locale.clone(),
+                        name: locale.clone(),
+                    })
+            })
+            .collect()
+    }
+
+    /// Pending list entries for the chosen bundles, catalog apps come with their extra packages.
+    pub fn bundle_entries(&self, bundle_ids: &[String], catalog: &Catalog) -> Vec<String> {
+        let mut entries: Vec<String> = Vec::new();
+        let bundles = self
+            .bundles
+            .iter()
+            .filter(|bundle| bundle_ids.contains(&bundle.id));
+        for pkg_name in bundles.flat_map(|bundle| &bundle.apps) {
+            let entry = catalog
+                .groups
+                .iter()
+                .flat_map(|group| &group.apps)
+                .find(|app| app.pkg == *pkg_name)
+                .map_or_else(|| pkg_name.clone(), |app| app.packages().join(" "));
+            if !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn parse_presets() {
+        let presets = Presets::parse(
+            r#"{
+                "steps": ["done", "bundles"],
+                "bundles": [
+                    {"id": "dev", "name": "Development", "apps": ["git", "vim"], "default": true},
+                    {"id": "web", "name": "Web", "apps": ["firefox", "git"]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(presets.steps, [WizardStep::Bundles, WizardStep::Done]);
+        assert!(presets.bundles[0].default && !presets.bundles[1].default);
+
+        let mut catalog = Catalog::default();
+        catalog.merge_json(
+            r#"[{"name": "Tools", "apps": [{"name": "Git", "pkg": "git", "extra": ["git-lfs"]}]}]"#,
+            "test",
+        );
+        let ids = [String::from("dev"), String::from("web")];
+        assert_eq!(
+            presets.bundle_entries(&ids, &catalog),
+            ["git git-lfs", "vim", "firefox"]
+        );
+
+        assert_eq!(Presets::parse("{}").unwrap().steps, default_steps());
+        assert!(Presets::parse(r#"{"steps": ["reboot"]}"#).is_err());
+        assert!(
+            Presets::parse(r#"{"bundles": [{"id": "x", "name": "X", "apps": ["a b"]}]}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn available_languages() {
+        let presets =
+            Presets::parse(r#"{"languages": [{"id": "fr", "name": "Français"}]}"#).unwrap();
+        let locales = [String::from("en"), String::from("fr")];
+        assert_eq!(
+            presets.languages_for(&locales),
+            [
+                PresetLanguage {
+                    id: String::from("en"),
+                    name: String::from("en"),
+                },
+                PresetLanguage {
+                    id: String::from("fr"),
+                    name: String::from("Français"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn completion_state() {
+        let dir = TempDir::new("first-boot");
+        let path = dir.path().join("state/first_boot.json");
+        assert!(!is_completed(&path));
+        mark_completed(&path).unwrap();
+        assert!(is_completed(&path));
+    }
+}
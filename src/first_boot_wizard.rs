@@ -0,0 +1,294 @@
+use crate::application_browser::{self, ApplicationBrowser};
+use crate::config::PKGDATADIR;
+use crate::first_boot::{self, PresetLanguage, Presets, WizardStep};
+use crate::pages;
+
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+pub const FIRST_BOOT_ARG: &str = "--first-boot";
+
+/// Choices made along the steps, applied from the last page.
+#[derive(Debug, Default)]
+struct WizardChoices {
+    language: Option<String>,
+    autostart: Option<bool>,
+    bundles: Vec<String>,
+    update: bool,
+}
+
+pub fn presets_path() -> PathBuf {
+    PathBuf::from(format!(
+        "{PKGDATADIR}/data/application_utility/presets.json"
+    ))
+}
+
+fn page_box() -> gtk::Box {
+    let page = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    page.set_border_width(12);
+    page
+}
+
+fn intro_label(text: &str) -> gtk::Label {
+    let label = gtk::Label::new(Some(text));
+    label.set_line_wrap(true);
+    label.set_xalign(0.0);
+    label
+}
+
+fn create_language_page(
+    languages: &[PresetLanguage],
+    current: &str,
+    choices: &Rc<RefCell<WizardChoices>>,
+) -> gtk::Box {
+    let page = page_box();
+    page.pack_start(
+        &intro_label("Choose the language of the menu."),
+        false,
+        false,
+        0,
+    );
+
+    let combo = gtk::ComboBoxText::new();
+    for language in languages {
+        combo.append(Some(&language.id), &language.name);
+    }
+    combo.connect_changed(glib::clone!(@strong choices => move |combo| {
+        choices.borrow_mut().language = combo.active_id().map(|id| id.to_string());
+    }));
+    combo.set_active_id(Some(current));
+    page.pack_start(&combo, false, false, 0);
+
+    page
+}
+
+fn create_autostart_page(choices: &Rc<RefCell<WizardChoices>>) -> gtk::Box {
+    let page = page_box();
+    page.pack_start(
+        &intro_label("The menu can open each time you log in, to keep its tips and tools at hand."),
+        false,
+        false,
+        0,
+    );
+
+    let autostart = crate::is_autostart_enabled();
+    choices.borrow_mut().autostart = Some(autostart);
+    let check_btn = gtk::CheckButton::with_label("Open the menu at login");
+    check_btn.set_active(autostart);
+    check_btn.connect_toggled(glib::clone!(@strong choices => move |check_btn| {
+        choices.borrow_mut().autostart = Some(check_btn.is_active());
+    }));
+    page.pack_start(&check_btn, false, false, 0);
+
+    page
+}
+
+fn create_bundles_page(presets: &Presets, choices: &Rc<RefCell<WizardChoices>>) -> gtk::Box {
+    let page = page_box();
+    page.pack_start(
+        &intro_label(
+            "Pick the app bundles to install, more apps are available in the app browser.",
+        ),
+        false,
+        false,
+        0,
+    );
+
+    for bundle in &presets.bundles {
+        let label = gtk::Label::new(None);
+        label.set_markup(&format!(
+            "<b>{}</b>\n{}\n<small>{}</small>",
+            glib::markup_escape_text(&bundle.name),
+            glib::markup_escape_text(&bundle.description),
+            glib::markup_escape_text(&bundle.apps.join(", "))
+        ));
+        label.set_line_wrap(true);
+        label.set_xalign(0.0);
+
+        let check_btn = gtk::CheckButton::new();
+        check_btn.add(&label);
+        let bundle_id = bundle.id.clone();
+        check_btn.connect_toggled(glib::clone!(@strong choices => move |check_btn| {
+            let bundles = &mut choices.borrow_mut().bundles;
+            bundles.retain(|id| *id != bundle_id);
+            if check_btn.is_active() {
+                bundles.push(bundle_id.clone());
+            }
+        }));
+        check_btn.set_active(bundle.default);
+        page.pack_start(&check_btn, false, false, 0);
+    }
+
+    page
+}
+
+fn create_update_page(choices: &Rc<RefCell<WizardChoices>>) -> gtk::Box {
+    let page = page_box();
+    page.pack_start(
+        &intro_label("Installing the latest updates first is recommended on a new system."),
+        false,
+        false,
+        0,
+    );
+
+    choices.borrow_mut().update = true;
+    let check_btn = gtk::CheckButton::with_label("Update the system");
+    check_btn.set_active(true);
+    check_btn.connect_toggled(glib::clone!(@strong choices => move |check_btn| {
+        choices.borrow_mut().update = check_btn.is_active();
+    }));
+    page.pack_start(&check_btn, false, false, 0);
+
+    page
+}
+
+fn summary_markup(
+    presets: &Presets,
+    languages: &[PresetLanguage],
+    choices: &WizardChoices,
+) -> String {
+    let mut markup = String::from("<b>Ready to apply your choices</b>\n\n");
+
+    if let Some(language) = languages
+        .iter()
+        .find(|language| Some(&language.id) == choices.language.as_ref())
+    {
+        let _ = writeln!(
+            markup,
+            "Language: {}",
+            glib::markup_escape_text(&language.name)
+        );
+    }
+    if let Some(autostart) = choices.autostart {
+        let _ = writeln!(
+            markup,
+            "Open at login: {}",
+            if autostart { "yes" } else { "no" }
+        );
+    }
+    let bundles = presets
+        .bundles
+        .iter()
+        .filter(|bundle| choices.bundles.contains(&bundle.id))
+        .map(|bundle| bundle.name.as_str())
+        .collect::<Vec<_>>();
+    if !bundles.is_empty() {
+        let _ = writeln!(
+            markup,
+            "Bundles: {}",
+            glib::markup_escape_text(&bundles.join(", "))
+        );
+    }
+    if choices.update {
+        markup.push_str("The system will be updated\n");
+    }
+
+    markup
+}
+
+fn apply_choices(assistant: &gtk::Assistant, presets: &Presets, choices: &WizardChoices) {
+    let parent = assistant.clone().upcast::<gtk::Window>();
+
+    if let Some(language) = &choices.language {
+        crate::set_menu_ui(language);
+    }
+    if let Some(autostart) = choices.autostart {
+        crate::set_autostart(autostart);
+    }
+    // bundles are installed against the updated system
+    if choices.update {
+        pages::update_system(Some(&parent));
+    }
+    if !choices.bundles.is_empty() {
+        let catalog = ApplicationBrowser::default_impl()
+            .lock()
+            .unwrap()
+            .catalog
+            .clone();
+        let entries = presets.bundle_entries(&choices.bundles, &catalog);
+        application_browser::install_entries(Some(&parent), &entries);
+    }
+}
+
+/// Runs the first boot assistant, its steps and bundles come from the presets file.
+pub fn show_wizard(parent: Option<&gtk::Window>) {
+    let presets = match Presets::load(&presets_path()) {
+        Ok(presets) => Rc::new(presets),
+        Err(err) => {
+            eprintln!("first boot presets: {err}");
+            return;
+        }
+    };
+
+    let assistant = gtk::Assistant::new();
+    assistant.set_title("Welcome");
+    assistant.set_transient_for(parent);
+    assistant.set_modal(true);
+    assistant.set_default_size(560, 420);
+
+    let locales = crate::available_locales();
+    let languages = Rc::new(presets.languages_for(&locales));
+    let choices = Rc::new(RefCell::new(WizardChoices::default()));
+    let summary_label = intro_label("");
+    summary_label.set_valign(gtk::Align::Start);
+    for step in &presets.steps {
+        let (page, title, page_type) = match step {
+            WizardStep::Language if languages.len() > 1 => (
+                create_language_page(&languages, &crate::current_locale(&locales), &choices),
+                "Language",
+                gtk::AssistantPageType::Content,
+            ),
+            WizardStep::Autostart => (
+                create_autostart_page(&choices),
+                "Autostart",
+                gtk::AssistantPageType::Content,
+            ),
+            WizardStep::Bundles if !presets.bundles.is_empty() => (
+                create_bundles_page(&presets, &choices),
+                "Apps",
+                gtk::AssistantPageType::Content,
+            ),
+            WizardStep::Update => (
+                create_update_page(&choices),
+                "System update",
+                gtk::AssistantPageType::Content,
+            ),
+            WizardStep::Done => {
+                let page = page_box();
+                page.pack_start(&summary_label, true, true, 0);
+                (page, "Done", gtk::AssistantPageType::Confirm)
+            }
+            _ => continue,
+        };
+        assistant.append_page(&page);
+        assistant.set_page_title(&page, title);
+        assistant.set_page_type(&page, page_type);
+        assistant.set_page_complete(&page, true);
+    }
+
+    assistant.connect_prepare(
+        glib::clone!(@strong presets, @strong languages, @strong choices, @weak summary_label => move |_, _| {
+            summary_label.set_markup(&summary_markup(&presets, &languages, &choices.borrow()));
+        }),
+    );
+    assistant.connect_apply(
+        glib::clone!(@strong presets, @strong choices => move |assistant| {
+            apply_choices(assistant, &presets, &choices.borrow());
+        }),
+    );
+    // skipping the wizard also counts, it only opens again when asked for
+    let finish = |assistant: &gtk::Assistant| {
+        if let Err(err) = first_boot::mark_completed(&first_boot::state_path()) {
+            eprintln!("unable to save the first boot state: {err}");
+        }
+        assistant.close();
+    };
+    assistant.connect_close(finish);
+    assistant.connect_cancel(finish);
+
+    assistant.show_all();
+}
@@ -0,0 +1,303 @@
+use crate::terminal;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const PACMAN_LOG_PATH: &str = "/var/log/pacman.log";
+
+/// What the menu did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryAction {
+    Install,
+    Remove,
+    SystemUpdate,
+    ReinstallAll,
+    ClearCache,
+    RemoveOrphans,
+    RemoveDbLock,
+}
+
+impl HistoryAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Install => "Install",
+            Self::Remove => "Remove",
+            Self::SystemUpdate => "System update",
+            Self::ReinstallAll => "Reinstall all packages",
+            Self::ClearCache => "Clear package cache",
+            Self::RemoveOrphans => "Remove orphans",
+            Self::RemoveDbLock => "Remove db lock",
+        }
+    }
+
+    /// Run from the tweaks page, as opposed to a change of the app selection.
+    pub fn is_tweak(&self) -> bool {
+        !matches!(self, Self::Install | Self::Remove)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryOutcome {
+    Success,
+    Failed,
+    Cancelled,
+}
+
+impl HistoryOutcome {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Success => "Success",
+            Self::Failed => "Failed",
+            Self::Cancelled => "Cancelled",
+        }
+    }
+}
+
+/// A line of the history journal.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    /// Start of the operation, in seconds since the epoch.
+    pub timestamp: i64,
+    /// How long it took, in seconds.
+    #[serde(default)]
+    pub duration: i64,
+    pub action: HistoryAction,
+    #[serde(default)]
+    pub packages: Vec<String>,
+    /// `native` or `terminal` for transactions, `system_helper` or `terminal` for tweaks.
+    pub backend: String,
+    pub outcome: HistoryOutcome,
+    /// Why the operation failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Transcript of the terminal the operation ran in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_path: Option<PathBuf>,
+}
+
+/// Entries shown on the history page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryFilter {
+    All,
+    Packages,
+    Tweaks,
+    Failures,
+}
+
+impl HistoryFilter {
+    pub fn from_id(id: &str) -> Self {
+        match id {
+            "packages" => Self::Packages,
+            "tweaks" => Self::Tweaks,
+            "failures" => Self::Failures,
+            _ => Self::All,
+        }
+    }
+}
+
+impl HistoryEntry {
+    /// Whether the entry is kept by `filter` and mentions `search`, in its packages or action.
+    pub fn matches(&self, filter: HistoryFilter, search: &str) -> bool {
+        let kept = match filter {
+            HistoryFilter::All => true,
+            HistoryFilter::Packages => !self.action.is_tweak(),
+            HistoryFilter::Tweaks => self.action.is_tweak(),
+            HistoryFilter::Failures => self.outcome != HistoryOutcome::Success,
+        };
+        let search = search.trim().to_lowercase();
+        kept && (search.is_empty()
+            || self.action.label().to_lowercase().contains(&search)
+            || self.packages.iter().any(|pkg| pkg.contains(&search)))
+    }
+}
+
+pub fn history_path() -> PathBuf {
+    terminal::state_dir().join("history.jsonl")
+}
+
+/// Seconds since the epoch.
+pub fn now() -> i64 {
+    glib::real_time() / 1_000_000
+}
+
+pub fn append(path: &Path, entry: &HistoryEntry) -> std::io::Result<()> {
+    if let Some(state_dir) = path.parent() {
+        fs::create_dir_all(state_dir)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+}
+
+/// Adds `entry` to the journal, a failure is only reported on stderr.
+pub fn record(entry: HistoryEntry) {
+    let path = history_path();
+    if let Err(err) = append(&path, &entry) {
+        eprintln!("unable to record in {}: {err}", path.display());
+    }
+}
+
+/// Entries of the journal, oldest first. Lines which cannot be read are skipped.
+pub fn load(path: &Path) -> Vec<HistoryEntry> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str::<HistoryEntry>(line) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                None
+            }
+        })
+        .collect()
+}
+
+/// A package change recorded by libalpm in pacman.log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PacmanEvent {
+    /// In seconds since the epoch.
+    pub timestamp: i64,
+    /// installed, removed, upgraded, downgraded or reinstalled.
+    pub action: String,
+    pub package: String,
+    /// The version, or the old and new ones of an upgrade.
+    pub version: String,
+}
+
+/// `2023-01-15T10:22:33+0530`, or `2019-01-01 10:00` in local time before pacman 5.2.
+fn parse_log_time(text: &str) -> Option<i64> {
+    let iso = match text.contains('T') {
+        true => String::from(text),
+        false => format!("{}:00", text.replacen(' ', "T", 1)),
+    };
+    let local = glib::TimeZone::local();
+    glib::DateTime::from_iso8601(&iso, Some(&local))
+        .ok()
+        .map(|time| time.to_unix())
+}
+
+/// Package changes of pacman.log, other lines are skipped.
+pub fn parse_pacman_log(data: &str) -> Vec<PacmanEvent> {
+    data.lines()
+        .filter_map(|line| {
+            let (time, rest) = line.strip_prefix('[')?.split_once("] ")?;
+            let rest = rest.strip_prefix("[ALPM] ")?;
+            let (action, rest) = rest.split_once(' ')?;
+            if !matches!(
+                action,
+                "installed" | "removed" | "upgraded" | "downgraded" | "reinstalled"
+            ) {
+                return None;
+            }
+            let (package, version) = rest.split_once(" (")?;
+            Some(PacmanEvent {
+                timestamp: parse_log_time(time)?,
+                action: String::from(action),
+                package: String::from(package),
+                version: String::from(version.strip_suffix(')')?),
+            })
+        })
+        .collect()
+}
+
+/// Package changes which happened while `entry` ran, dependencies included.
+pub fn events_for<'a>(entry: &HistoryEntry, events: &'a [PacmanEvent]) -> Vec<&'a PacmanEvent> {
+    // pacman.log is written to the second
+    let (start, end) = (entry.timestamp - 1, entry.timestamp + entry.duration + 1);
+    events
+        .iter()
+        .filter(|event| event.timestamp >= start && event.timestamp <= end)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    fn entry(action: HistoryAction, packages: &[&str], outcome: HistoryOutcome) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: 1673758350,
+            duration: 10,
+            action,
+            packages: packages.iter().map(|pkg| String::from(*pkg)).collect(),
+            backend: String::from("native"),
+            outcome,
+            detail: None,
+            log_path: None,
+        }
+    }
+
+    #[test]
+    fn journal() {
+        let dir = TempDir::new("history");
+        let path = dir.path().join("history.jsonl");
+        assert!(load(&path).is_empty());
+
+        let install = entry(
+            HistoryAction::Install,
+            &["firefox", "vim"],
+            HistoryOutcome::Success,
+        );
+        let mut orphans = entry(HistoryAction::RemoveOrphans, &[], HistoryOutcome::Failed);
+        orphans.backend = String::from("terminal");
+        orphans.log_path = Some(PathBuf::from("/tmp/1673758350-pacman.log"));
+        append(&path, &install).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"timestamp\": 1, \"action\": \"reboot\"}\n")
+            .unwrap();
+        append(&path, &orphans).unwrap();
+        assert_eq!(load(&path), [install.clone(), orphans.clone()]);
+
+        assert!(install.matches(HistoryFilter::Packages, "fire"));
+        assert!(!install.matches(HistoryFilter::Tweaks, ""));
+        assert!(!install.matches(HistoryFilter::Failures, ""));
+        assert!(orphans.matches(HistoryFilter::from_id("failures"), "Orphans"));
+        assert!(!orphans.matches(HistoryFilter::All, "vim"));
+    }
+
+    #[test]
+    fn pacman_log() {
+        let log = "\
+[2023-01-15T10:22:28+0530] [PACMAN] Running 'pacman -S firefox vim'
+[2023-01-15T10:22:30+0530] [ALPM] transaction started
+[2023-01-15T10:22:31+0530] [ALPM] installed firefox (108.0.2-1)
+[2023-01-15T10:22:31+0530] [ALPM] upgraded vim (9.0.1000-1 -> 9.0.1182-1)
+[2023-01-15T10:22:32+0530] [ALPM-SCRIPTLET] installed something (1.0-1)
+[2023-01-15T10:40:00+0530] [ALPM] removed nano (7.1-1)
+";
+        let events = parse_pacman_log(log);
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (event.action.as_str(), event.package.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("installed", "firefox"),
+                ("upgraded", "vim"),
+                ("removed", "nano")
+            ]
+        );
+        assert_eq!(events[0].timestamp, 1673758351);
+        assert_eq!(events[1].version, "9.0.1000-1 -> 9.0.1182-1");
+
+        let install = entry(
+            HistoryAction::Install,
+            &["firefox"],
+            HistoryOutcome::Success,
+        );
+        assert_eq!(events_for(&install, &events).len(), 2);
+        assert!(parse_log_time("2019-01-01 10:00").is_some());
+    }
+}
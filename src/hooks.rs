@@ -0,0 +1,353 @@
+use crate::catalog::Catalog;
+use crate::config::PKGDATADIR;
+use crate::system_helper::{valid_unit_name, HelperClient};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Follow-up step declared by a catalog app, run as root by the system helper.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum HookAction {
+    EnableUnit {
+        unit: String,
+    },
+    DisableUnit {
+        unit: String,
+    },
+    /// Adds the user running the menu to `group`.
+    AddUserToGroup {
+        group: String,
+    },
+    /// Runs a script shipped in the helpers directory, see `helpers_dir`.
+    RunHelper {
+        helper: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookStage {
+    /// After the app was installed by a successful transaction.
+    PostInstall,
+    /// Before the app is removed.
+    PreRemove,
+    /// Reverting the pre-remove hooks of an app which is still installed, its removal failed.
+    UndoPreRemove,
+}
+
+impl HookStage {
+    /// Stable identifier, for machine readable output.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::PostInstall => "post_install",
+            Self::PreRemove => "pre_remove",
+            Self::UndoPreRemove => "undo_pre_remove",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HookReport {
+    /// Package of the app which declared the hook.
+    pub package: String,
+    pub stage: HookStage,
+    pub action: HookAction,
+    pub error: Option<String>,
+}
+
+/// Scripts which `run_helper` hooks may name, nothing outside of it is run.
+pub fn helpers_dir() -> PathBuf {
+    PathBuf::from(format!("{PKGDATADIR}/hooks"))
+}
+
+/// Groups `add_user_to_group` hooks may add the user to, the others may grant admin rights.
+pub const ALLOWED_GROUPS: &[&str] = &[
+    "adbusers",
+    "audio",
+    "input",
+    "kvm",
+    "lp",
+    "plugdev",
+    "scanner",
+    "uucp",
+    "vboxusers",
+    "video",
+    "wireshark",
+];
+
+fn valid_group_name(group: &str) -> bool {
+    ALLOWED_GROUPS.contains(&group)
+}
+
+fn valid_helper_name(helper: &str) -> bool {
+    !helper.is_empty()
+        && !helper.starts_with('-')
+        && helper
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-".contains(c))
+}
+
+impl HookAction {
+    /// Action and argument, as passed to the system helper.
+    pub fn parts(&self) -> (&'static str, &str) {
+        match self {
+            Self::EnableUnit { unit } => ("enable_unit", unit),
+            Self::DisableUnit { unit } => ("disable_unit", unit),
+            Self::AddUserToGroup { group } => ("add_user_to_group", group),
+            Self::RunHelper { helper } => ("run_helper", helper),
+        }
+    }
+
+    pub fn from_parts(action: &str, argument: &str) -> Option<Self> {
+        let argument = String::from(argument);
+        match action {
+            "enable_unit" => Some(Self::EnableUnit { unit: argument }),
+            "disable_unit" => Some(Self::DisableUnit { unit: argument }),
+            "add_user_to_group" => Some(Self::AddUserToGroup { group: argument }),
+            "run_helper" => Some(Self::RunHelper { helper: argument }),
+            _ => None,
+        }
+    }
+
+    /// Action undoing this one, only units can be switched back.
+    pub fn reverse(&self) -> Option<Self> {
+        match self {
+            Self::EnableUnit { unit } => Some(Self::DisableUnit { unit: unit.clone() }),
+            Self::DisableUnit { unit } => Some(Self::EnableUnit { unit: unit.clone() }),
+            Self::AddUserToGroup { .. } | Self::RunHelper { .. } => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = match self {
+            Self::EnableUnit { unit } | Self::DisableUnit { unit } => valid_unit_name(unit),
+            Self::AddUserToGroup { group } => valid_group_name(group),
+            Self::RunHelper { helper } => valid_helper_name(helper),
+        };
+        match valid {
+            true => Ok(()),
+            false => {
+                let (action, argument) = self.parts();
+                Err(format!("invalid argument for {action}: {argument}"))
+            }
+        }
+    }
+}
+
+impl fmt::Display for HookAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EnableUnit { unit } => write!(f, "enable {unit}"),
+            Self::DisableUnit { unit } => write!(f, "disable {unit}"),
+            Self::AddUserToGroup { group } => write!(f, "add the user to the {group} group"),
+            Self::RunHelper { helper } => write!(f, "run {helper}"),
+        }
+    }
+}
+
+/// Hooks of the catalog apps among `packages` for `stage`, by package.
+/// The `units` of an app come first, as `enable_unit` hooks.
+pub fn hooks_for(
+    catalog: &Catalog,
+    packages: &[&str],
+    stage: HookStage,
+) -> Vec<(String, HookAction)> {
+    let mut hooks: Vec<(String, HookAction)> = Vec::new();
+    let apps = catalog
+        .groups
+        .iter()
+        .flat_map(|group| &group.apps)
+        .filter(|app| packages.contains(&app.pkg.as_str()));
+    for app in apps {
+        let actions = match stage {
+            HookStage::PostInstall => app
+                .units
+                .iter()
+                .map(|unit| HookAction::EnableUnit { unit: unit.clone() })
+                .chain(app.post_install.iter().cloned())
+                .collect::<Vec<_>>(),
+            HookStage::PreRemove => app.pre_remove.clone(),
+            // built from what the pre-remove hooks did, see `undo_pre_remove`
+            HookStage::UndoPreRemove => Vec::new(),
+        };
+        for action in actions {
+            // an app listed in several groups has its hooks run once
+            if !hooks
+                .iter()
+                .any(|hook| hook.0 == app.pkg && hook.1 == action)
+            {
+                hooks.push((app.pkg.clone(), action));
+            }
+        }
+    }
+    hooks
+}
+
+/// Runs `hooks` one after the other, a failure does not stop the next ones.
+pub fn run_hooks<F>(hooks: Vec<(String, HookAction)>, stage: HookStage, run: F) -> Vec<HookReport>
+where
+    F: Fn(&HookAction) -> Result<(), String>,
+{
+    hooks
+        .into_iter()
+        .map(|(package, action)| {
+            let error = run(&action).err();
+            HookReport {
+                package,
+                stage,
+                action,
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Hooks reverting the pre-remove ones which succeeded for packages still `installed`,
+/// the transaction failed or was cancelled. Latest first, like an undo.
+pub fn undo_pre_remove(
+    pre_remove: &[HookReport],
+    installed: &HashSet<String>,
+) -> Vec<(String, HookAction)> {
+    pre_remove
+        .iter()
+        .rev()
+        .filter(|report| report.error.is_none() && installed.contains(&report.package))
+        .filter_map(|report| {
+            report
+                .action
+                .reverse()
+                .map(|action| (report.package.clone(), action))
+        })
+        .collect()
+}
+
+/// Runs `hooks` through the system helper.
+pub fn run_as_root(hooks: Vec<(String, HookAction)>, stage: HookStage) -> Vec<HookReport> {
+    if hooks.is_empty() {
+        return Vec::new();
+    }
+
+    let client = HelperClient::system().map_err(|err| err.to_string());
+    run_hooks(hooks, stage, |action| {
+        client
+            .as_ref()
+            .map_err(String::clone)
+            .and_then(|client| client.run_hook(action))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hooks_of_apps() {
+        let mut catalog = Catalog::default();
+        catalog.merge_json(
+            r#"[{"name": "Tools", "apps": [
+                {"name": "Printing", "pkg": "cups", "units": ["cups.service"],
+                 "post_install": [{"action": "add_user_to_group", "group": "lp"}],
+                 "pre_remove": [{"action": "disable_unit", "unit": "cups.service"}]},
+                {"name": "Docker", "pkg": "docker",
+                 "post_install": [{"action": "run_helper", "helper": "docker-setup"}]},
+                {"name": "Bad", "pkg": "bad", "post_install": [{"action": "run_helper", "helper": "../sh"}]},
+                {"name": "Unknown", "pkg": "unknown", "post_install": [{"action": "reboot"}]}
+            ]}]"#,
+            "",
+        );
+        let paths = catalog
+            .issues
+            .iter()
+            .map(|issue| issue.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["$[0].apps[2].post_install", "$[0].apps[3]"]);
+
+        assert_eq!(
+            hooks_for(&catalog, &["cups", "vim"], HookStage::PostInstall),
+            [
+                (
+                    String::from("cups"),
+                    HookAction::EnableUnit {
+                        unit: String::from("cups.service")
+                    }
+                ),
+                (
+                    String::from("cups"),
+                    HookAction::AddUserToGroup {
+                        group: String::from("lp")
+                    }
+                ),
+            ]
+        );
+
+        let hooks = hooks_for(&catalog, &["cups", "docker"], HookStage::PreRemove);
+        assert_eq!(hooks.len(), 1);
+        let reports = run_hooks(hooks, HookStage::PreRemove, |action| match action {
+            HookAction::DisableUnit { .. } => Err(String::from("unit is masked")),
+            _ => Ok(()),
+        });
+        assert_eq!(reports[0].error.as_deref(), Some("unit is masked"));
+        assert_eq!(reports[0].action.to_string(), "disable cups.service");
+
+        // a failed step did nothing to undo
+        let installed = HashSet::from([String::from("cups")]);
+        assert!(undo_pre_remove(&reports, &installed).is_empty());
+
+        let hooks = vec![
+            (
+                String::from("cups"),
+                HookAction::DisableUnit {
+                    unit: String::from("cups.service"),
+                },
+            ),
+            (
+                String::from("cups"),
+                HookAction::RunHelper {
+                    helper: String::from("cups-cleanup"),
+                },
+            ),
+        ];
+        let reports = run_hooks(hooks, HookStage::PreRemove, |_| Ok(()));
+        assert_eq!(
+            undo_pre_remove(&reports, &installed),
+            [(
+                String::from("cups"),
+                HookAction::EnableUnit {
+                    unit: String::from("cups.service")
+                }
+            )]
+        );
+        assert!(undo_pre_remove(&reports, &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn validate_arguments() {
+        let action = HookAction::from_parts("add_user_to_group", "video").unwrap();
+        assert_eq!(action.parts(), ("add_user_to_group", "video"));
+        assert!(action.validate().is_ok());
+        assert!(HookAction::from_parts("add_user_to_group", "Video; id")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(HookAction::from_parts("add_user_to_group", "docker")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(HookAction::from_parts("add_user_to_group", "wheel")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(HookAction::from_parts("run_helper", "/bin/sh")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(HookAction::from_parts("enable_unit", "--now")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert_eq!(HookAction::from_parts("reboot", ""), None);
+    }
+}
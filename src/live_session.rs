@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Kernel parameters set by archiso and other live media.
+const CMDLINE_MARKERS: &[&str] = &[
+    "archisobasedir=",
+    "archisolabel=",
+    "archisosearchuuid=",
+    "boot=live",
+];
+
+/// Created by the archiso initcpio hooks when booting the image.
+const ARCHISO_MARKERS: &[&str] = &["run/archiso/airootfs", "run/archiso/bootmnt"];
+
+/// Variables a graphical program needs, pkexec clears the environment.
+const SESSION_VARS: &[&str] = &[
+    "DISPLAY",
+    "XAUTHORITY",
+    "WAYLAND_DISPLAY",
+    "XDG_RUNTIME_DIR",
+    "XDG_SESSION_TYPE",
+    "QT_QPA_PLATFORM",
+];
+
+/// What gave the live session away.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LiveMarker {
+    LivePath(PathBuf),
+    Cmdline(String),
+    Archiso(PathBuf),
+}
+
+fn under_root(root: &Path, path: &str) -> PathBuf {
+    root.join(path.trim_start_matches('/'))
+}
+
+/// Looks for a live session on the system mounted at `root`.
+pub fn detect(root: &Path, live_path: &str) -> Option<LiveMarker> {
+    if !live_path.is_empty() {
+        let path = under_root(root, live_path);
+        if path.exists() {
+            return Some(LiveMarker::LivePath(path));
+        }
+    }
+
+    let cmdline = fs::read_to_string(root.join("proc/cmdline")).unwrap_or_default();
+    if let Some(param) = cmdline.split_whitespace().find(|param| {
+        CMDLINE_MARKERS
+            .iter()
+            .any(|marker| param.starts_with(marker))
+    }) {
+        return Some(LiveMarker::Cmdline(String::from(param)));
+    }
+
+    ARCHISO_MARKERS
+        .iter()
+        .map(|marker| under_root(root, marker))
+        .find(|path| path.exists())
+        .map(LiveMarker::Archiso)
+}
+
+pub fn is_live_session(live_path: &str) -> bool {
+    detect(Path::new("/"), live_path).is_some()
+}
+
+/// Command starting the graphical `program` as root, through pkexec unless already root.
+/// Only the session variables are passed on.
+pub fn graphical_root_command(
+    program: &str,
+    is_root: bool,
+    getenv: impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    if is_root {
+        return vec![String::from(program)];
+    }
+
+    let mut command = vec![String::from("pkexec"), String::from("env")];
+    command.extend(
+        SESSION_VARS
+            .iter()
+            .filter_map(|name| getenv(name).map(|value| format!("{name}={value}"))),
+    );
+    command.push(String::from(program));
+    command
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn detect_markers() {
+        let temp_dir = TempDir::new("live");
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("proc")).unwrap();
+        let live_path = "/run/archiso/bootmnt/arch";
+
+        fs::write(
+            root.join("proc/cmdline"),
+            "BOOT_IMAGE=/boot/vmlinuz-linux root=UUID=1234 rw quiet",
+        )
+        .unwrap();
+        assert_eq!(detect(root, live_path), None);
+
+        fs::write(
+            root.join("proc/cmdline"),
+            "initrd=/arch/boot/initramfs-linux.img archisobasedir=arch archisolabel=VAAMOS_2023",
+        )
+        .unwrap();
+        assert_eq!(
+            detect(root, live_path),
+            Some(LiveMarker::Cmdline(String::from("archisobasedir=arch")))
+        );
+
+        fs::create_dir_all(root.join("run/archiso/bootmnt/arch")).unwrap();
+        assert_eq!(
+            detect(root, live_path),
+            Some(LiveMarker::LivePath(root.join("run/archiso/bootmnt/arch")))
+        );
+
+        fs::write(root.join("proc/cmdline"), "quiet").unwrap();
+        assert_eq!(
+            detect(root, ""),
+            Some(LiveMarker::Archiso(root.join("run/archiso/bootmnt")))
+        );
+    }
+
+    #[test]
+    fn installer_privileges() {
+        let getenv = |name: &str| match name {
+            "DISPLAY" => Some(String::from(":0")),
+            "XAUTHORITY" => Some(String::from("/home/live/.Xauthority")),
+            _ => None,
+        };
+        assert_eq!(
+            graphical_root_command("/usr/bin/calamares", true, getenv),
+            ["/usr/bin/calamares"]
+        );
+        assert_eq!(
+            graphical_root_command("/usr/bin/calamares", false, getenv),
+            [
+                "pkexec",
+                "env",
+                "DISPLAY=:0",
+                "XAUTHORITY=/home/live/.Xauthority",
+                "/usr/bin/calamares"
+            ]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Used when nothing matches the requested locale.
+pub const FALLBACK_LOCALE: &str = "en";
+
+/// Locales to try for `locale`, most specific first: `pt_BR.UTF-8` gives `pt_BR`, `pt`, `en`.
+pub fn locale_fallbacks(locale: &str) -> Vec<String> {
+    let mut fallbacks: Vec<String> = Vec::new();
+    let name = locale.split(['.', '@']).next().unwrap_or_default().trim();
+
+    if !name.is_empty() && name != "C" && name != "POSIX" {
+        fallbacks.push(String::from(name));
+        if let Some((language, _)) = name.split_once('_') {
+            fallbacks.push(String::from(language));
+        }
+    }
+    if !fallbacks.iter().any(|x| x == FALLBACK_LOCALE) {
+        fallbacks.push(String::from(FALLBACK_LOCALE));
+    }
+    fallbacks
+}
+
+/// The locale picked from the environment, like gettext would.
+pub fn default_locale() -> String {
+    glib::language_names()
+        .first()
+        .map_or_else(|| String::from(FALLBACK_LOCALE), |name| name.to_string())
+}
+
+fn sub_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_dir())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+fn file_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Pages found in any locale directory of `pages_dir`.
+pub fn page_names(pages_dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for dir in sub_dirs(pages_dir) {
+        let pages = fs::read_dir(&dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.is_file())
+                    .filter_map(|path| file_name(&path))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for name in pages {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+/// Path of the page in the closest locale which has it.
+pub fn page_path(pages_dir: &Path, name: &str, locale: &str) -> Option<PathBuf> {
+    locale_fallbacks(locale)
+        .into_iter()
+        .map(|locale| pages_dir.join(locale).join(name))
+        .find(|path| path.is_file())
+}
+
+/// Locales with translated pages or a message catalog for `domain`.
+pub fn available_locales(pages_dir: &Path, locale_dir: &Path, domain: &str) -> Vec<String> {
+    let mut locales = sub_dirs(pages_dir)
+        .iter()
+        .filter_map(|dir| file_name(dir))
+        .collect::<Vec<_>>();
+    let catalogs = sub_dirs(locale_dir)
+        .into_iter()
+        .filter(|dir| dir.join(format!("LC_MESSAGES/{domain}.mo")).is_file())
+        .filter_map(|dir| file_name(&dir));
+    for locale in catalogs {
+        if !locales.contains(&locale) {
+            locales.push(locale);
+        }
+    }
+    if !locales.iter().any(|x| x == FALLBACK_LOCALE) {
+        locales.push(String::from(FALLBACK_LOCALE));
+    }
+
+    locales.sort();
+    locales
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn fallbacks() {
+        assert_eq!(locale_fallbacks("pt_BR.UTF-8"), ["pt_BR", "pt", "en"]);
+        assert_eq!(locale_fallbacks("de_DE@euro"), ["de_DE", "de", "en"]);
+        assert_eq!(locale_fallbacks("fr"), ["fr", "en"]);
+        assert_eq!(locale_fallbacks("en_GB"), ["en_GB", "en"]);
+        assert_eq!(locale_fallbacks("C"), ["en"]);
+        assert_eq!(locale_fallbacks(""), ["en"]);
+    }
+
+    #[test]
+    fn pages_fall_back_per_page() {
+        let temp_dir = TempDir::new("locale");
+        let root = temp_dir.path();
+        let pages_dir = root.join("pages");
+        for (locale, page) in [
+            ("en", "readme"),
+            ("en", "release"),
+            ("pt", "readme"),
+            ("pt_BR", "involved"),
+        ] {
+            fs::create_dir_all(pages_dir.join(locale)).unwrap();
+            fs::write(pages_dir.join(locale).join(page), locale).unwrap();
+        }
+        let locale_dir = root.join("locale");
+        fs::create_dir_all(locale_dir.join("de/LC_MESSAGES")).unwrap();
+        fs::write(locale_dir.join("de/LC_MESSAGES/vaamos-menu.mo"), "").unwrap();
+        fs::create_dir_all(locale_dir.join("fr/LC_MESSAGES")).unwrap();
+
+        assert_eq!(page_names(&pages_dir), ["involved", "readme", "release"]);
+        let page_locale = |name: &str, locale: &str| {
+            page_path(&pages_dir, name, locale).and_then(|path| fs::read_to_string(path).ok())
+        };
+        assert_eq!(page_locale("readme", "pt_BR.UTF-8").as_deref(), Some("pt"));
+        assert_eq!(page_locale("release", "pt_BR").as_deref(), Some("en"));
+        assert_eq!(page_locale("involved", "pt_BR").as_deref(), Some("pt_BR"));
+        assert_eq!(page_locale("involved", "pt_PT").as_deref(), None);
+        assert_eq!(
+            available_locales(&pages_dir, &locale_dir, "vaamos-menu"),
+            ["de", "en", "pt", "pt_BR"]
+        );
+    }
+}
@@ -2,20 +2,48 @@
 #![feature(string_remove_matches)]
 #![allow(non_upper_case_globals)]
 
+mod alpm_backend;
 mod alpm_helper;
 mod application_browser;
+mod catalog;
+mod catalog_remote;
+mod cli;
 mod config;
 mod data_types;
+mod db_lock;
+mod desktop_env;
+mod first_boot;
+mod first_boot_wizard;
+mod history;
+mod hooks;
+mod live_session;
+mod locale;
+mod orphans_dialog;
+mod package_search;
 mod pages;
+mod preferences;
+mod profile;
+mod profile_dialog;
+mod progress_dialog;
+mod services;
+mod settings;
+mod system_helper;
+mod terminal;
+mod terminal_dialog;
+#[cfg(test)]
+mod test_utils;
+mod transaction;
 mod utils;
+mod vte_terminal;
 
 use config::{APP_ID, GETTEXT_PACKAGE, LOCALEDIR, PKGDATADIR, VERSION};
 use data_types::*;
 use gettextrs::LocaleCategory;
 use gtk::{gio, glib, Builder, Window};
 use once_cell::sync::Lazy;
+use preferences::{PreferenceIssue, Preferences};
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use utils::*;
 
@@ -27,7 +55,8 @@ use gdk_pixbuf::Pixbuf;
 use serde_json::json;
 use std::{fs, str};
 
-static mut g_save_json: Lazy<Mutex<serde_json::Value>> = Lazy::new(|| Mutex::new(json!(null)));
+/// Untranslated texts of the UI, read once from the glade file.
+static mut g_default_texts: Lazy<Mutex<serde_json::Value>> = Lazy::new(|| Mutex::new(json!(null)));
 static mut g_menu_window: Option<Arc<MenuWindow>> = None;
 
 fn show_about_dialog() {
@@ -63,6 +92,20 @@ fn show_about_dialog() {
 }
 
 fn main() {
+    // privileged modes, they must not touch the display
+    let args = std::env::args().collect::<Vec<String>>();
+    match args.get(1).map(String::as_str) {
+        Some(alpm_backend::WORKER_ARG) => {
+            let request = args.get(2).map_or("", String::as_str);
+            std::process::exit(alpm_backend::run_worker(request));
+        }
+        Some(system_helper::HELPER_ARG) => std::process::exit(system_helper::run_daemon()),
+        _ => {}
+    }
+    if let Some(exit_code) = cli::run(&args) {
+        std::process::exit(exit_code);
+    }
+
     gettextrs::setlocale(LocaleCategory::LcAll, "");
     gettextrs::bindtextdomain(GETTEXT_PACKAGE, LOCALEDIR).expect("Unable to bind the text domain.");
     gettextrs::textdomain(GETTEXT_PACKAGE).expect("Unable to switch to the text domain.");
@@ -76,24 +119,54 @@ fn main() {
         Default::default(), // Using default flags
     );
 
-    application.connect_activate(|application| {
-        build_ui(application);
+    // GApplication does not know about our own flag
+    let show_first_boot = args
+        .iter()
+        .any(|arg| arg == first_boot_wizard::FIRST_BOOT_ARG);
+    let gtk_args = args
+        .iter()
+        .filter(|arg| *arg != first_boot_wizard::FIRST_BOOT_ARG)
+        .cloned()
+        .collect::<Vec<String>>();
+
+    application.connect_activate(move |application| {
+        build_ui(application, show_first_boot);
     });
 
     // Run the application and start the event loop
-    application.run();
+    application.run_with_args(&gtk_args);
 }
 
-fn build_ui(application: &gtk::Application) {
-    let data = fs::read_to_string(format!("{PKGDATADIR}/data/preferences.json"))
-        .expect("Unable to read file");
-    let preferences: serde_json::Value = serde_json::from_str(&data).expect("Unable to parse");
+fn build_ui(application: &gtk::Application, show_first_boot: bool) {
+    // Wrong settings are reported in the window, their defaults are used instead
+    let (preferences, mut issues) = Preferences::load();
+    issues.extend(preferences::check_paths(&preferences));
+    if alpm_helper::TransactionBackend::from_name(&preferences.transaction_backend).is_none() {
+        issues.push(PreferenceIssue {
+            origin: String::from("preferences"),
+            key: Some(String::from("transaction_backend")),
+            message: format!(
+                "unknown backend {}, expected native or terminal",
+                preferences.transaction_backend
+            ),
+        });
+    }
+    if terminal::TerminalMode::from_name(&preferences.terminal).is_none() {
+        issues.push(PreferenceIssue {
+            origin: String::from("preferences"),
+            key: Some(String::from("terminal")),
+            message: format!(
+                "unknown terminal {}, expected embedded or external",
+                preferences.terminal
+            ),
+        });
+    }
 
     // Import Css
     let provider = gtk::CssProvider::new();
-    provider
-        .load_from_path(preferences["style_path"].as_str().unwrap())
-        .expect("Failed to load CSS");
+    if let Err(err) = provider.load_from_path(&preferences.style_path) {
+        eprintln!("unable to load {}: {err}", preferences.style_path);
+    }
     gtk::StyleContext::add_provider_for_screen(
         &gdk::Screen::default().expect("Error initializing gtk css provider."),
         &provider,
@@ -101,7 +174,21 @@ fn build_ui(application: &gtk::Application) {
     );
 
     // Init window
-    let builder: Builder = Builder::from_file(preferences["ui_path"].as_str().unwrap());
+    let builder = match load_builder(&preferences.ui_path) {
+        Some(builder) => builder,
+        None => {
+            let dialog = gtk::MessageDialog::new(
+                Window::NONE,
+                gtk::DialogFlags::MODAL,
+                gtk::MessageType::Error,
+                gtk::ButtonsType::Close,
+                &format!("Unable to load the interface from {}", preferences.ui_path),
+            );
+            dialog.run();
+            dialog.close();
+            return;
+        }
+    };
     builder.connect_signals(|_builder, handler_name| {
         match handler_name {
             // handler_name as defined in the glade file => handler function as defined above
@@ -119,21 +206,51 @@ fn build_ui(application: &gtk::Application) {
         .object("window")
         .expect("Could not get the object window");
     main_window.set_application(Some(application));
+    terminal_dialog::add_view_log_action(application);
+
+    // Restore the window size of the last session
+    if let Some(geometry) = settings::current().window {
+        if geometry.width > 0 && geometry.height > 0 {
+            main_window.set_default_size(geometry.width, geometry.height);
+        }
+        if geometry.maximized {
+            main_window.maximize();
+        }
+    }
+    main_window.connect_delete_event(|window, _| {
+        let (width, height) = window.size();
+        let maximized = window.is_maximized();
+        settings::update(|settings| {
+            // keep the unmaximized size to restore to
+            let previous = settings.window.unwrap_or_default();
+            settings.window = Some(match maximized {
+                true => settings::WindowGeometry {
+                    maximized,
+                    ..previous
+                },
+                false => settings::WindowGeometry {
+                    width,
+                    height,
+                    maximized,
+                },
+            });
+        });
+        gtk::Inhibit(false)
+    });
 
     unsafe {
         g_menu_window = Some(Arc::new(MenuWindow {
             window: main_window.clone(),
             builder: builder.clone(),
             preferences: preferences.clone(),
+            live_session: live_session::is_live_session(&preferences.live_path),
         }));
     };
 
+    show_preference_issues(&builder, &issues);
+
     // Load images
-    let logo_path = format!(
-        "{}/{}.svg",
-        preferences["logo_path"].as_str().unwrap(),
-        APP_ID
-    );
+    let logo_path = format!("{}/{}.svg", preferences.logo_path, APP_ID);
     if Path::new(&logo_path).exists() {
         let logo = Pixbuf::from_file(logo_path).unwrap();
         main_window.set_icon(Some(&logo));
@@ -158,10 +275,8 @@ fn build_ui(application: &gtk::Application) {
         btn.set_image(Some(&image));
     }
 
-    // Create pages
-    let pages = format!("{PKGDATADIR}/data/pages/en");
-
-    for page in fs::read_dir(pages).unwrap() {
+    // Create pages, from every locale as some may only be translated
+    for page in locale::page_names(&pages_dir()) {
         let scrolled_window =
             gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
 
@@ -189,32 +304,80 @@ fn build_ui(application: &gtk::Application) {
         scrolled_window.show_all();
 
         let stack: gtk::Stack = builder.object("stack").unwrap();
-        let child_name = format!(
-            "{}page",
-            page.unwrap().path().file_name().unwrap().to_str().unwrap()
-        );
-        stack.add_named(&scrolled_window, &child_name);
+        stack.add_named(&scrolled_window, &format!("{page}page"));
+    }
+
+    // Language selector
+    let locales = available_locales();
+    let languages_combo = gtk::ComboBoxText::new();
+    languages_combo.set_tooltip_text(Some("Language"));
+    for locale in &locales {
+        languages_combo.append(Some(locale), locale);
     }
+    languages_combo.connect_changed(|combo| {
+        let active_id = combo.active_id();
+        let current = settings::current().locale;
+        if let Some(locale) = active_id.filter(|id| current.as_deref() != Some(id.as_str())) {
+            set_menu_ui(&locale);
+        }
+    });
+    builder.expose_object("languages", &languages_combo);
+    let headerbar: gtk::HeaderBar = builder.object("headerbar").unwrap();
+    headerbar.pack_end(&languages_combo);
+    languages_combo.show();
+
+    set_menu_ui(&current_locale(&locales));
 
     // Set autostart switcher state
-    let autostart = Path::new(&fix_path(preferences["autostart_path"].as_str().unwrap())).exists();
+    let autostart = is_autostart_enabled();
     let autostart_switch: gtk::Switch = match builder.object("autostart") {
         Some(switch) => switch,
         None => gtk::Switch::new(),
     };
     autostart_switch.set_active(autostart);
 
-    // Live systems
-    let installlabel: gtk::Label = builder.object("installlabel").unwrap();
-    installlabel.set_visible(false);
+    // Live systems offer the installer, the session is gone after a reboot so autostart is pointless
+    let live_session = is_live_session();
+    for name in ["installlabel", "install"] {
+        if let Some(widget) = builder.object::<gtk::Widget>(name) {
+            widget.set_visible(live_session);
+        }
+    }
+    for name in ["autostartlabel", "autostart"] {
+        if let Some(widget) = builder.object::<gtk::Widget>(name) {
+            widget.set_visible(!live_session);
+        }
+    }
 
-    let install: gtk::Button = builder.object("install").unwrap();
-    install.set_visible(false);
     pages::create_appbrowser_page(&builder);
     pages::create_tweaks_page(&builder);
+    pages::create_services_page(&builder);
+    pages::create_history_page(&builder);
+
+    if let Some(backend) =
+        alpm_helper::TransactionBackend::from_name(&preferences.transaction_backend)
+    {
+        application_browser::set_transaction_backend(backend);
+    }
+
+    // Refresh the app catalog from the network
+    if !preferences.catalog_url.is_empty() {
+        application_browser::refresh_remote_catalog(
+            preferences.catalog_url.clone(),
+            PathBuf::from(&preferences.catalog_keyring),
+        );
+    }
 
     // Show the UI
     main_window.show();
+
+    if show_first_boot
+        || (preferences.first_boot_wizard
+            && !live_session
+            && !first_boot::is_completed(&first_boot::state_path()))
+    {
+        first_boot_wizard::show_wizard(Some(&main_window));
+    }
 }
 
 /// Returns the best locale, based on user's preferences.
@@ -223,9 +386,7 @@ fn set_menu_ui(use_locale: &str) {
     gettextrs::textdomain(GETTEXT_PACKAGE).expect("Unable to switch to the text domain.");
     glib::setenv("LANGUAGE", use_locale, true).expect("Unable to change env variable.");
 
-    unsafe {
-        g_save_json.lock().unwrap()["locale"] = json!(use_locale);
-    }
+    settings::update(|settings| settings.locale = Some(String::from(use_locale)));
 
     // Real-time locale changing
     let elts: HashMap<String, serde_json::Value> = serde_json::from_str(&serde_json::to_string(&json!({
@@ -233,7 +394,7 @@ fn set_menu_ui(use_locale: &str) {
         "tooltip_text": ["about", "github", "software", "forum", "wiki"],
     })).unwrap()).unwrap();
 
-    let mut default_texts = json!(null);
+    let default_texts = unsafe { &mut g_default_texts.lock().unwrap() };
     for method in elts.iter() {
         if default_texts.get(method.0).is_none() {
             default_texts[method.0] = json![null];
@@ -242,46 +403,29 @@ fn set_menu_ui(use_locale: &str) {
         for elt in elts[method.0].as_array().unwrap() {
             let elt_value = elt.as_str().unwrap();
             unsafe {
-                let item: gtk::Widget = g_menu_window
-                    .clone()
-                    .unwrap()
-                    .builder
-                    .object(elt_value)
-                    .unwrap();
+                let item: gtk::Widget =
+                    match g_menu_window.clone().unwrap().builder.object(elt_value) {
+                        Some(item) => item,
+                        None => continue,
+                    };
                 if default_texts[method.0].get(elt_value).is_none() {
                     let item_buf = item.property::<String>(method.0.as_str());
                     default_texts[method.0][elt_value] = json!(item_buf);
                 }
-                if method.0 == "tooltip_text" {
-                    item.set_property(
-                        method.0,
-                        &gettextrs::gettext(default_texts[method.0][elt_value].as_str().unwrap()),
-                    );
-                }
+                item.set_property(
+                    method.0,
+                    &gettextrs::gettext(default_texts[method.0][elt_value].as_str().unwrap()),
+                );
             }
         }
     }
 
     unsafe {
-        // Change content of pages
-        let pages = format!("{PKGDATADIR}/data/pages/en");
-        for page in fs::read_dir(pages).unwrap() {
-            let stack: gtk::Stack = g_menu_window
-                .clone()
-                .unwrap()
-                .builder
-                .object("stack")
-                .unwrap();
-            let child = stack.child_by_name(&format!(
-                "{}page",
-                page.as_ref()
-                    .unwrap()
-                    .path()
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-            ));
+        // Change content of pages, missing translations fall back page by page
+        let builder = &g_menu_window.clone().unwrap().builder;
+        let stack: gtk::Stack = builder.object("stack").unwrap();
+        for page in locale::page_names(&pages_dir()) {
+            let child = stack.child_by_name(&format!("{page}page"));
             if child.is_none() {
                 eprintln!("child not found");
                 continue;
@@ -303,46 +447,194 @@ fn set_menu_ui(use_locale: &str) {
                 .children();
 
             let label = &third_child[0].clone().downcast::<gtk::Label>().unwrap();
-            label.set_markup(
-                get_page(page.unwrap().path().file_name().unwrap().to_str().unwrap()).as_str(),
-            );
+            label.set_markup(get_page(&page, use_locale).as_str());
+        }
+
+        if let Some(combo) = builder.object::<gtk::ComboBoxText>("languages") {
+            if combo.active_id().as_deref() != Some(use_locale) {
+                combo.set_active_id(Some(use_locale));
+            }
         }
     }
 }
 
+fn is_autostart_enabled() -> bool {
+    let autostart_path =
+        unsafe { fix_path(&g_menu_window.clone().unwrap().preferences.autostart_path) };
+    Path::new(&autostart_path).exists()
+}
+
+fn is_live_session() -> bool {
+    unsafe { g_menu_window.clone().unwrap().live_session }
+}
+
+/// Where package and tweak commands run, the embedded terminal unless set otherwise.
+fn terminal_mode() -> terminal::TerminalMode {
+    let preferences = unsafe { g_menu_window.clone().unwrap().preferences.clone() };
+    terminal::TerminalMode::from_name(&preferences.terminal).unwrap_or_default()
+}
+
 fn set_autostart(autostart: bool) {
-    let autostart_path: String;
-    let desktop_path: String;
-    unsafe {
-        autostart_path = fix_path(
-            g_menu_window.clone().unwrap().preferences["autostart_path"]
-                .as_str()
-                .unwrap(),
-        );
-        desktop_path = g_menu_window.clone().unwrap().preferences["desktop_path"]
-            .as_str()
-            .unwrap()
-            .to_string();
+    let preferences = unsafe { g_menu_window.clone().unwrap().preferences.clone() };
+    let autostart_path = fix_path(&preferences.autostart_path);
+    if let Err(err) = utils::set_autostart(&autostart_path, &preferences.desktop_path, autostart) {
+        eprintln!("unable to change autostart: {err}");
     }
-    let config_dir = Path::new(&autostart_path).parent().unwrap();
-    if !config_dir.exists() {
-        fs::create_dir_all(config_dir).unwrap();
+}
+
+/// Starts the installer of the live system, the button stays disabled while it runs.
+fn launch_installer(button: &gtk::Widget) {
+    let installer_path = unsafe {
+        g_menu_window
+            .clone()
+            .unwrap()
+            .preferences
+            .installer_path
+            .clone()
+    };
+    let show_error = |button: &gtk::Widget, text: &str| {
+        let parent = button
+            .toplevel()
+            .and_then(|toplevel| toplevel.downcast::<Window>().ok());
+        let dialog = gtk::MessageDialog::new(
+            parent.as_ref(),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Error,
+            gtk::ButtonsType::Close,
+            text,
+        );
+        dialog.run();
+        dialog.close();
+    };
+
+    if !Path::new(&installer_path).is_file() {
+        show_error(
+            button,
+            &format!("The installer {installer_path} was not found."),
+        );
+        return;
     }
-    if autostart && !check_regular_file(autostart_path.as_str()) {
-        std::os::unix::fs::symlink(desktop_path, autostart_path).unwrap();
-    } else if !autostart && check_regular_file(autostart_path.as_str()) {
-        std::fs::remove_file(autostart_path).unwrap();
+
+    // calamares needs root, the display variables are passed through pkexec
+    let command = live_session::graphical_root_command(
+        &installer_path,
+        glib::user_name() == "root",
+        |name| glib::getenv(name).and_then(|value| value.into_string().ok()),
+    );
+
+    button.set_sensitive(false);
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    std::thread::spawn(move || {
+        let result = subprocess::Exec::cmd(&command[0])
+            .args(&command[1..])
+            .join()
+            .map_err(|err| err.to_string());
+        tx.send(result).expect("Couldn't send data to channel");
+    });
+
+    rx.attach(
+        None,
+        glib::clone!(@weak button => @default-return glib::Continue(false), move |result| {
+            button.set_sensitive(true);
+            match result {
+                Ok(subprocess::ExitStatus::Exited(0)) => {}
+                // the authentication was dismissed
+                Ok(subprocess::ExitStatus::Exited(126)) => {}
+                Ok(subprocess::ExitStatus::Exited(127)) => {
+                    show_error(&button, "Not authorized to start the installer.")
+                }
+                Ok(status) => eprintln!("{installer_path} exited with {status:?}"),
+                Err(err) => {
+                    show_error(&button, &format!("Unable to start the installer: {err}"))
+                }
+            }
+            glib::Continue(false)
+        }),
+    );
+}
+
+/// Loads the window, falling back to the bundled interface if `ui_path` is unusable.
+fn load_builder(ui_path: &str) -> Option<Builder> {
+    let default_ui_path = Preferences::default().ui_path;
+    for path in [ui_path, default_ui_path.as_str()] {
+        let builder = Builder::new();
+        match builder.add_from_file(path) {
+            Ok(_) => return Some(builder),
+            Err(err) => eprintln!("unable to load {path}: {err}"),
+        }
+        if path == default_ui_path {
+            break;
+        }
     }
+    None
 }
 
-#[inline]
-fn get_page(name: &str) -> String {
-    let mut filename = format!("{PKGDATADIR}/data/pages/en/{name}");
-    if !check_regular_file(filename.as_str()) {
-        filename = format!("{PKGDATADIR}/data/pages/en/{name}");
+/// Shows a banner listing the settings which were ignored.
+fn show_preference_issues(builder: &Builder, issues: &[PreferenceIssue]) {
+    if issues.is_empty() {
+        return;
     }
+    let details = issues
+        .iter()
+        .map(|issue| {
+            eprintln!("preferences: {issue}");
+            issue.to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let window: Window = builder.object("window").unwrap();
+    let stack: gtk::Stack = builder.object("stack").unwrap();
+    let infobar = gtk::InfoBar::new();
+    infobar.set_message_type(gtk::MessageType::Error);
+    infobar.set_show_close_button(true);
+    infobar.connect_response(|infobar, _| infobar.hide());
+    let label = gtk::Label::new(Some(&format!(
+        "Some settings are wrong, their defaults are used:\n{details}"
+    )));
+    label.set_line_wrap(true);
+    label.set_xalign(0.0);
+    label.set_selectable(true);
+    infobar.content_area().add(&label);
+
+    // the stack is the only child of the window
+    let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    window.remove(&stack);
+    main_box.pack_start(&infobar, false, false, 0);
+    main_box.pack_start(&stack, true, true, 0);
+    infobar.show_all();
+    main_box.show();
+    window.add(&main_box);
+}
+
+fn pages_dir() -> PathBuf {
+    PathBuf::from(format!("{PKGDATADIR}/data/pages"))
+}
 
-    fs::read_to_string(filename).unwrap()
+/// Locales with translated pages or messages, offered by the language selectors.
+fn available_locales() -> Vec<String> {
+    let preferences = unsafe { g_menu_window.clone().unwrap().preferences.clone() };
+    locale::available_locales(
+        &pages_dir(),
+        Path::new(&preferences.locale_path),
+        GETTEXT_PACKAGE,
+    )
+}
+
+/// The saved locale, or the system one, among `locales`.
+fn current_locale(locales: &[String]) -> String {
+    let saved_locale = settings::current().locale;
+    locale::locale_fallbacks(&saved_locale.unwrap_or_else(locale::default_locale))
+        .into_iter()
+        .find(|locale| locales.contains(locale))
+        .unwrap_or_else(|| String::from(locale::FALLBACK_LOCALE))
+}
+
+#[inline]
+fn get_page(name: &str, use_locale: &str) -> String {
+    locale::page_path(&pages_dir(), name, use_locale)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default()
 }
 
 /// Handlers
@@ -357,7 +649,10 @@ fn on_start_menu(param: &[glib::Value]) -> Option<glib::Value> {
 fn on_action_clicked(param: &[glib::Value]) -> Option<glib::Value> {
     let widget = param[0].get::<gtk::Widget>().unwrap();
     return match widget.widget_name().as_str() {
-        "install" => None,
+        "install" => {
+            launch_installer(&widget);
+            None
+        }
         "autostart" => {
             let action = widget.downcast::<gtk::Switch>().unwrap();
             set_autostart(action.is_active());
@@ -392,10 +687,14 @@ fn on_link_clicked(param: &[glib::Value]) -> Option<glib::Value> {
     let name = widget.widget_name();
 
     unsafe {
-        let preferences = &g_menu_window.clone().unwrap().preferences["urls"];
+        let preferences = &g_menu_window.clone().unwrap().preferences;
 
-        let uri = preferences[name.as_str()].as_str().unwrap();
-        let _ = gtk::show_uri_on_window(gtk::Window::NONE, uri, 0);
+        match preferences.url(name.as_str()) {
+            Some(uri) => {
+                let _ = gtk::show_uri_on_window(gtk::Window::NONE, uri, 0);
+            }
+            None => eprintln!("no url for {name} in the preferences"),
+        }
     }
 
     None
@@ -406,10 +705,14 @@ fn on_link1_clicked(param: &[glib::Value]) -> Option<glib::Value> {
     let name = widget.widget_name();
 
     unsafe {
-        let preferences = &g_menu_window.clone().unwrap().preferences["urls"];
+        let preferences = &g_menu_window.clone().unwrap().preferences;
 
-        let uri = preferences[name.as_str()].as_str().unwrap();
-        let _ = gtk::show_uri_on_window(gtk::Window::NONE, uri, 0);
+        match preferences.url(name.as_str()) {
+            Some(uri) => {
+                let _ = gtk::show_uri_on_window(gtk::Window::NONE, uri, 0);
+            }
+            None => eprintln!("no url for {name} in the preferences"),
+        }
     }
 
     Some(false.to_value())
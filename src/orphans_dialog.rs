@@ -0,0 +1,203 @@
+use crate::application_browser::create_column;
+use crate::package_search::OrphanPackage;
+
+use gtk::prelude::*;
+
+use std::rc::Rc;
+
+// columns of the orphans store
+const SELECTED: u32 = 0;
+const NAME: u32 = 1;
+const SIZE: u32 = 2;
+const DESCRIPTION: u32 = 3;
+/// Only optionally required, hidden unless asked for.
+const OPTIONAL: u32 = 4;
+const INSTALLED_SIZE: u32 = 5;
+
+fn description(orphan: &OrphanPackage) -> String {
+    match orphan.optional_for.is_empty() {
+        true => orphan.description.clone(),
+        false => format!(
+            "{}\nOptional for {}",
+            orphan.description,
+            orphan.optional_for.join(", ")
+        ),
+    }
+}
+
+/// Selected rows among the shown ones, their names and installed size.
+fn selection(filter: &gtk::TreeModelFilter) -> (Vec<String>, i64) {
+    let mut names: Vec<String> = Vec::new();
+    let mut size = 0;
+    filter.foreach(|model, _path, iter| {
+        if model
+            .value(iter, SELECTED as i32)
+            .get::<bool>()
+            .unwrap_or_default()
+        {
+            names.push(
+                model
+                    .value(iter, NAME as i32)
+                    .get::<String>()
+                    .unwrap_or_default(),
+            );
+            size += model
+                .value(iter, INSTALLED_SIZE as i32)
+                .get::<i64>()
+                .unwrap_or_default();
+        }
+        false
+    });
+    (names, size)
+}
+
+fn update_summary(filter: &gtk::TreeModelFilter, label: &gtk::Label, remove_btn: &gtk::Widget) {
+    let (names, size) = selection(filter);
+    label.set_text(&match names.len() {
+        0 => String::from("No package selected"),
+        count => format!(
+            "{count} package{} selected, {} freed",
+            if count == 1 { "" } else { "s" },
+            glib::format_size(size.unsigned_abs())
+        ),
+    });
+    remove_btn.set_sensitive(!names.is_empty());
+}
+
+/// Lets the user pick which of `orphans` to remove, all of them are selected at first.
+/// Those only optionally required are listed on request, or from the start when there are no others.
+/// Returns the names of the confirmed packages, none if the dialog was cancelled.
+pub fn review_orphans(parent: Option<&gtk::Window>, orphans: &[OrphanPackage]) -> Vec<String> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Remove orphans"),
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Remove", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_size(560, 400);
+    let remove_btn = dialog
+        .widget_for_response(gtk::ResponseType::Accept)
+        .unwrap();
+    remove_btn.style_context().add_class("destructive-action");
+
+    let store = gtk::ListStore::new(&[
+        bool::static_type(),
+        String::static_type(),
+        String::static_type(),
+        String::static_type(),
+        bool::static_type(),
+        i64::static_type(),
+    ]);
+    for orphan in orphans {
+        store.insert_with_values(
+            None,
+            &[
+                (SELECTED, &true),
+                (NAME, &orphan.name),
+                (
+                    SIZE,
+                    &glib::format_size(orphan.installed_size.unsigned_abs()),
+                ),
+                (DESCRIPTION, &description(orphan)),
+                (OPTIONAL, &!orphan.optional_for.is_empty()),
+                (INSTALLED_SIZE, &orphan.installed_size),
+            ],
+        );
+    }
+
+    let include_optional = gtk::CheckButton::with_label(
+        "Include packages which are only optional dependencies of others",
+    );
+    // otherwise nothing would be listed
+    include_optional.set_active(orphans.iter().all(|orphan| !orphan.optional_for.is_empty()));
+    let filter = gtk::TreeModelFilter::new(&store, None);
+    filter.set_visible_func(
+        glib::clone!(@weak include_optional => @default-return true, move |model, iter| {
+            let optional = model.value(iter, OPTIONAL as i32).get::<bool>().unwrap_or_default();
+            include_optional.is_active() || !optional
+        }),
+    );
+
+    let summary_label = gtk::Label::new(None);
+    summary_label.set_xalign(0.0);
+    let update = Rc::new(
+        glib::clone!(@weak filter, @weak summary_label, @weak remove_btn => move || {
+            update_summary(&filter, &summary_label, &remove_btn);
+        }),
+    );
+
+    include_optional.connect_toggled(glib::clone!(@weak filter, @strong update => move |_| {
+        filter.refilter();
+        update();
+    }));
+
+    let tree_view = gtk::TreeView::with_model(&filter);
+    let selected_renderer = gtk::CellRendererToggle::new();
+    selected_renderer.connect_toggled(
+        glib::clone!(@weak store, @weak filter, @strong update => move |_, path| {
+            let store_iter = filter
+                .convert_path_to_child_path(&path)
+                .and_then(|store_path| store.iter(&store_path));
+            if let Some(store_iter) = store_iter {
+                let selected = store.value(&store_iter, SELECTED as i32).get::<bool>();
+                store.set_value(&store_iter, SELECTED, &(selected != Ok(true)).to_value());
+                update();
+            }
+        }),
+    );
+    tree_view.append_column(&create_column("", &selected_renderer, "active", SELECTED));
+    tree_view.append_column(&create_column(
+        "Package",
+        &gtk::CellRendererText::new(),
+        "text",
+        NAME,
+    ));
+    tree_view.append_column(&create_column(
+        "Size",
+        &gtk::CellRendererText::new(),
+        "text",
+        SIZE,
+    ));
+    let description_renderer = gtk::CellRendererText::new();
+    description_renderer.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    let description_column =
+        create_column("Description", &description_renderer, "text", DESCRIPTION);
+    description_column.set_expand(true);
+    tree_view.append_column(&description_column);
+    tree_view.set_tooltip_column(DESCRIPTION as i32);
+
+    let scrolled_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    scrolled_window.set_vexpand(true);
+    scrolled_window.set_shadow_type(gtk::ShadowType::In);
+    scrolled_window.add(&tree_view);
+
+    let label = gtk::Label::new(Some(
+        "These packages were installed as dependencies and nothing requires them anymore. \
+         Untick those to keep.",
+    ));
+    label.set_line_wrap(true);
+    label.set_xalign(0.0);
+
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.set_border_width(10);
+    content_area.pack_start(&label, false, false, 0);
+    content_area.pack_start(&scrolled_window, true, true, 0);
+    content_area.pack_start(&include_optional, false, false, 0);
+    content_area.pack_start(&summary_label, false, false, 0);
+    update();
+    dialog.show_all();
+
+    let response = dialog.run();
+    let (names, _) = selection(&filter);
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => names,
+        _ => Vec::new(),
+    }
+}
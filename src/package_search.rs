@@ -0,0 +1,315 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// Upper bound of results shown for a query.
+const MAX_RESULTS: usize = 200;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncPackage {
+    pub name: String,
+    pub repo: String,
+    pub version: String,
+    pub description: String,
+    pub download_size: i64,
+    pub installed_size: i64,
+    pub installed: bool,
+    pub curated: bool,
+}
+
+/// Versions and sizes of a package, from the local and the sync databases.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackageDetails {
+    pub installed_version: Option<String>,
+    pub sync_version: Option<String>,
+    pub repo: Option<String>,
+    pub download_size: i64,
+    pub installed_size: i64,
+}
+
+impl PackageDetails {
+    pub fn update_available(&self) -> bool {
+        match (&self.installed_version, &self.sync_version) {
+            (Some(installed), Some(available)) => {
+                alpm::vercmp(installed.as_str(), available.as_str()) == Ordering::Less
+            }
+            _ => false,
+        }
+    }
+}
+
+pub fn package_details(alpm_handle: &alpm::Alpm, pkg_name: &str) -> PackageDetails {
+    let local_pkg = alpm_handle.localdb().pkg(pkg_name).ok();
+    let sync_pkg = alpm_handle
+        .syncdbs()
+        .iter()
+        .find_map(|db| db.pkg(pkg_name).ok());
+
+    PackageDetails {
+        installed_version: local_pkg.map(|pkg| pkg.version().to_string()),
+        sync_version: sync_pkg.map(|pkg| pkg.version().to_string()),
+        repo: sync_pkg
+            .and_then(|pkg| pkg.db())
+            .map(|db| String::from(db.name())),
+        download_size: sync_pkg.map_or(0, |pkg| pkg.size()),
+        installed_size: local_pkg.or(sync_pkg).map_or(0, |pkg| pkg.isize()),
+    }
+}
+
+/// Details of each of `pkg_names`, by name.
+pub fn packages_details<'a>(
+    alpm_handle: &alpm::Alpm,
+    pkg_names: impl IntoIterator<Item = &'a str>,
+) -> HashMap<String, PackageDetails> {
+    pkg_names
+        .into_iter()
+        .map(|pkg_name| {
+            (
+                String::from(pkg_name),
+                package_details(alpm_handle, pkg_name),
+            )
+        })
+        .collect()
+}
+
+/// A package installed as a dependency which no installed package requires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrphanPackage {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub installed_size: i64,
+    /// Installed packages which list it as an optional dependency.
+    pub optional_for: Vec<String>,
+}
+
+/// Orphans of the local database like `pacman -Qtd`, with `include_optional` those
+/// only optionally required as well, like `pacman -Qttd`.
+pub fn orphans(alpm_handle: &alpm::Alpm, include_optional: bool) -> Vec<OrphanPackage> {
+    let mut orphans = alpm_handle
+        .localdb()
+        .pkgs()
+        .iter()
+        .filter(|pkg| pkg.reason() == alpm::PackageReason::Depend)
+        .filter(|pkg| pkg.required_by().is_empty())
+        .filter_map(|pkg| {
+            let optional_for = pkg.optional_for().into_iter().collect::<Vec<String>>();
+            if !include_optional && !optional_for.is_empty() {
+                return None;
+            }
+            Some(OrphanPackage {
+                name: String::from(pkg.name()),
+                version: pkg.version().to_string(),
+                description: String::from(pkg.desc().unwrap_or_default()),
+                installed_size: pkg.isize(),
+                optional_for,
+            })
+        })
+        .collect::<Vec<_>>();
+    orphans.sort_by(|a, b| a.name.cmp(&b.name));
+
+    orphans
+}
+
+/// Names of the packages installed as dependencies which nothing requires anymore, like `pacman -Qtdq`.
+pub fn orphan_packages(alpm_handle: &alpm::Alpm) -> Vec<String> {
+    orphans(alpm_handle, false)
+        .into_iter()
+        .map(|orphan| orphan.name)
+        .collect()
+}
+
+/// Ranks how well a package matches `query`, lower is better, `None` if it does not match.
+/// `query` must be lowercase.
+pub fn match_rank(name: &str, description: &str, provides: &[&str], query: &str) -> Option<u8> {
+    let name = name.to_lowercase();
+    if name == query {
+        Some(0)
+    } else if name.starts_with(query) {
+        Some(1)
+    } else if name.contains(query) {
+        Some(2)
+    } else if provides
+        .iter()
+        .any(|provide| provide.to_lowercase().contains(query))
+    {
+        Some(3)
+    } else if description.to_lowercase().contains(query) {
+        Some(4)
+    } else {
+        None
+    }
+}
+
+/// A package of a sync database, as read by the search.
+#[derive(Clone, Debug)]
+pub struct SyncEntry<'a> {
+    pub repo: &'a str,
+    pub name: &'a str,
+    pub version: &'a str,
+    pub description: &'a str,
+    pub provides: Vec<&'a str>,
+    pub download_size: i64,
+    pub installed_size: i64,
+}
+
+/// Searches the sync databases configured in pacman.conf by name, description and provides.
+/// Packages from the curated catalog come first, then by match quality and name.
+pub fn search_sync_dbs(
+    alpm_handle: &alpm::Alpm,
+    query: &str,
+    curated: &HashSet<String>,
+) -> Vec<SyncPackage> {
+    let localdb = alpm_handle.localdb();
+    let entries = alpm_handle.syncdbs().into_iter().flat_map(|db| {
+        let repo = db.name();
+        db.pkgs().into_iter().map(move |pkg| SyncEntry {
+            repo,
+            name: pkg.name(),
+            version: pkg.version().as_str(),
+            description: pkg.desc().unwrap_or_default(),
+            provides: pkg.provides().into_iter().map(|dep| dep.name()).collect(),
+            download_size: pkg.size(),
+            installed_size: pkg.isize(),
+        })
+    });
+
+    search_entries(entries, |name| localdb.pkg(name).is_ok(), query, curated)
+}
+
+/// Matches `entries`, in the order of their repositories, see `search_sync_dbs`.
+pub fn search_entries<'a, I, F>(
+    entries: I,
+    is_installed: F,
+    query: &str,
+    curated: &HashSet<String>,
+) -> Vec<SyncPackage>
+where
+    I: Iterator<Item = SyncEntry<'a>>,
+    F: Fn(&str) -> bool,
+{
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut results: Vec<(bool, u8, SyncPackage)> = Vec::new();
+
+    for entry in entries {
+        // First repository in pacman.conf wins, as in pacman.
+        if seen.contains(entry.name) {
+            continue;
+        }
+        let rank = match match_rank(entry.name, entry.description, &entry.provides, &query) {
+            Some(rank) => rank,
+            None => continue,
+        };
+
+        seen.insert(entry.name);
+        let curated_pkg = curated.contains(entry.name);
+        results.push((
+            !curated_pkg,
+            rank,
+            SyncPackage {
+                name: String::from(entry.name),
+                repo: String::from(entry.repo),
+                version: String::from(entry.version),
+                description: String::from(entry.description),
+                download_size: entry.download_size,
+                installed_size: entry.installed_size,
+                installed: is_installed(entry.name),
+                curated: curated_pkg,
+            },
+        ));
+    }
+
+    results.sort_by(|a, b| (a.0, a.1, &a.2.name).cmp(&(b.0, b.1, &b.2.name)));
+    results
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(.., pkg)| pkg)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rank_matches() {
+        assert_eq!(match_rank("vim", "Vi Improved", &[], "vim"), Some(0));
+        assert_eq!(match_rank("vim-airline", "", &[], "vim"), Some(1));
+        assert_eq!(match_rank("gvim", "", &[], "vim"), Some(2));
+        assert_eq!(match_rank("neovim-git", "", &["neovim"], "nvim"), None);
+        assert_eq!(match_rank("nvim-qt", "", &[], "nvim"), Some(1));
+        assert_eq!(match_rank("neovim", "", &["vi"], "vi"), Some(2));
+        assert_eq!(
+            match_rank("jdk-openjdk", "", &["java-runtime"], "java"),
+            Some(3)
+        );
+        assert_eq!(
+            match_rank("firefox", "Web Browser", &[], "browser"),
+            Some(4)
+        );
+        assert_eq!(match_rank("firefox", "Web Browser", &[], "mail"), None);
+    }
+
+    fn entry<'a>(repo: &'a str, name: &'a str, description: &'a str) -> SyncEntry<'a> {
+        SyncEntry {
+            repo,
+            name,
+            version: "1.0-1",
+            description,
+            provides: Vec::new(),
+            download_size: 1024,
+            installed_size: 4096,
+        }
+    }
+
+    #[test]
+    fn search_sync_packages() {
+        let entries = vec![
+            entry("core", "vim", "Vi Improved"),
+            entry("extra", "gvim", "Vi Improved, with a GUI"),
+            entry("extra", "vim-airline", "Status line for vim"),
+            entry("extra", "nano", "Pico clone"),
+            entry("testing", "vim", "Vi Improved, newer"),
+            SyncEntry {
+                provides: vec!["vi"],
+                ..entry("extra", "neovim", "Fork of Vim")
+            },
+        ];
+        let curated = HashSet::from([String::from("neovim")]);
+        let installed = |name: &str| name == "vim";
+
+        let results = search_entries(entries.clone().into_iter(), installed, " VIM ", &curated);
+        assert_eq!(
+            results
+                .iter()
+                .map(|pkg| (pkg.repo.as_str(), pkg.name.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("extra", "neovim"),
+                ("core", "vim"),
+                ("extra", "vim-airline"),
+                ("extra", "gvim")
+            ]
+        );
+        assert!(results[0].curated && !results[0].installed);
+        assert!(results[1].installed);
+        assert_eq!(results[1].description, "Vi Improved");
+        assert_eq!(results[1].installed_size, 4096);
+
+        assert!(search_entries(entries.clone().into_iter(), installed, "  ", &curated).is_empty());
+        assert!(search_entries(entries.into_iter(), installed, "emacs", &curated).is_empty());
+
+        let names = (0..MAX_RESULTS + 10)
+            .map(|index| format!("lib{index}"))
+            .collect::<Vec<_>>();
+        let many = names.iter().map(|name| entry("extra", name, ""));
+        assert_eq!(
+            search_entries(many, installed, "lib", &curated).len(),
+            MAX_RESULTS
+        );
+    }
+}
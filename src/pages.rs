@@ -1,15 +1,59 @@
-use crate::application_browser::ApplicationBrowser;
+use crate::application_browser::{create_column, ApplicationBrowser};
+use crate::data_types::SystemdUnits;
+use crate::db_lock::{self, LockState};
+use crate::first_boot_wizard;
+use crate::history::{
+    self, HistoryAction, HistoryEntry, HistoryFilter, HistoryOutcome, PacmanEvent,
+};
+use crate::live_session;
+use crate::orphans_dialog;
+use crate::package_search;
+use crate::services::{self, ServiceEntry, SystemdClient};
+use crate::system_helper::{self, HelperClient};
+use crate::terminal_dialog;
 use crate::utils;
 use crate::utils::PacmanWrapper;
 use gtk::{glib, Builder};
-use std::fmt::Write as _;
+use std::cell::{Cell, RefCell};
 use std::path::Path;
+use std::rc::Rc;
 
 use gtk::prelude::*;
 
 use std::str;
 use subprocess::{Exec, Redirection};
 
+fn toplevel_window(widget: &impl IsA<gtk::Widget>) -> Option<gtk::Window> {
+    widget
+        .toplevel()
+        .and_then(|toplevel| toplevel.downcast::<gtk::Window>().ok())
+}
+
+/// Runs a tweak in a terminal, its outcome is recorded then notified along with a link to its log.
+fn run_tweak(parent: Option<&gtk::Window>, action: HistoryAction, cmd: &str) {
+    let started = history::now();
+    let outcome = terminal_dialog::run_cmd(parent, cmd);
+    history::record(HistoryEntry {
+        timestamp: started,
+        duration: history::now() - started,
+        action,
+        packages: Vec::new(),
+        backend: String::from("terminal"),
+        // no exit code when the terminal was closed first
+        outcome: match outcome.exit_code {
+            Some(0) => HistoryOutcome::Success,
+            Some(_) => HistoryOutcome::Failed,
+            None => HistoryOutcome::Cancelled,
+        },
+        detail: outcome
+            .exit_code
+            .filter(|code| *code != 0)
+            .map(|code| format!("exit code {code}")),
+        log_path: outcome.log_path.clone(),
+    });
+    terminal_dialog::notify_outcome(action.label(), &outcome);
+}
+
 fn create_fixes_section() -> gtk::Box {
     let topbox = gtk::Box::new(gtk::Orientation::Vertical, 2);
     let button_box_f = gtk::Box::new(gtk::Orientation::Horizontal, 10);
@@ -25,41 +69,22 @@ fn create_fixes_section() -> gtk::Box {
     let update_system_btn = gtk::Button::with_label("System update");
     let remove_orphans_btn = gtk::Button::with_label("Remove orphans");
     let clear_pkgcache_btn = gtk::Button::with_label("Clear package cache");
+    let first_boot_btn = gtk::Button::with_label("Setup assistant");
 
-    removelock_btn.connect_clicked(move |_| {
-        // Spawn child process in separate thread.
-        std::thread::spawn(move || {
-            if Path::new("/var/lib/pacman/db.lck").exists() {
-                let _ = Exec::cmd("/sbin/pkexec")
-                    .arg("bash")
-                    .arg("-c")
-                    .arg("rm /var/lib/pacman/db.lck")
-                    .join()
-                    .unwrap();
-                if !Path::new("/var/lib/pacman/db.lck").exists() {
-                    let dialog = gtk::MessageDialog::builder()
-                        .message_type(gtk::MessageType::Info)
-                        .text("Pacman db lock was removed!")
-                        .build();
-                    dialog.show();
-                }
-            }
-        });
-    });
+    removelock_btn.connect_clicked(on_removelock_btn_clicked);
     reinstall_btn.connect_clicked(move |_| {
-        // Spawn child process in separate thread.
-        std::thread::spawn(move || {
-            let _ = utils::run_cmd_terminal(String::from("pacman -S $(pacman -Qnq)"), true);
-        });
+        run_helper_method(
+            HistoryAction::ReinstallAll,
+            "All packages were reinstalled.",
+            |client| client.reinstall_all(),
+        );
     });
     update_system_btn.connect_clicked(on_update_system_btn_clicked);
-    remove_orphans_btn.connect_clicked(move |_| {
-        // Spawn child process in separate thread.
-        std::thread::spawn(move || {
-            let _ = utils::run_cmd_terminal(String::from("pacman -Rns $(pacman -Qtdq)"), true);
-        });
-    });
+    remove_orphans_btn.connect_clicked(on_remove_orphans_btn_clicked);
     clear_pkgcache_btn.connect_clicked(on_clear_pkgcache_btn_clicked);
+    first_boot_btn.connect_clicked(move |button| {
+        first_boot_wizard::show_wizard(toplevel_window(button).as_ref());
+    });
 
     topbox.pack_start(&label, true, false, 1);
     button_box_f.pack_start(&update_system_btn, true, true, 2);
@@ -67,6 +92,10 @@ fn create_fixes_section() -> gtk::Box {
     button_box_s.pack_start(&removelock_btn, true, true, 2);
     button_box_s.pack_start(&clear_pkgcache_btn, true, true, 2);
     button_box_s.pack_end(&remove_orphans_btn, true, true, 2);
+    // the live media is set up when installing, not from the assistant
+    if !crate::is_live_session() {
+        button_box_t.pack_start(&first_boot_btn, true, true, 2);
+    }
     button_box_f.set_halign(gtk::Align::Fill);
     button_box_s.set_halign(gtk::Align::Fill);
     button_box_t.set_halign(gtk::Align::Fill);
@@ -188,32 +217,632 @@ pub fn create_appbrowser_page(builder: &Builder) {
     stack.add_named(&viewport, child_name);
 }
 
-fn on_update_system_btn_clicked(_: &gtk::Button) {
-    let (cmd, escalate) = match utils::get_pacman_wrapper() {
-        PacmanWrapper::Pak => ("pak -Syu", false),
-        PacmanWrapper::Yay => ("yay -Syu", false),
-        PacmanWrapper::Paru => ("paru --removemake -Syu", false),
-        _ => ("pacman -Syu", true),
+/// Switches of a unit on the services page.
+struct ServiceRow {
+    unit: String,
+    enabled: gtk::Switch,
+    running: gtk::Switch,
+}
+
+/// Set while the switches are updated from systemd, not by the user.
+type RefreshGuard = Rc<Cell<bool>>;
+
+fn create_service_row(entry: &ServiceEntry) -> (gtk::Box, ServiceRow) {
+    let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    row_box.set_border_width(5);
+
+    let label = gtk::Label::new(None);
+    label.set_markup(&format!(
+        "<b>{}</b>\n<small>{}</small>",
+        glib::markup_escape_text(&entry.name),
+        glib::markup_escape_text(&entry.description)
+    ));
+    label.set_tooltip_text(Some(&entry.unit));
+    label.set_line_wrap(true);
+    label.set_xalign(0.0);
+    row_box.pack_start(&label, true, true, 0);
+
+    let row = ServiceRow {
+        unit: entry.unit.clone(),
+        enabled: gtk::Switch::new(),
+        running: gtk::Switch::new(),
     };
-    // Spawn child process in separate thread.
+    row.enabled
+        .set_tooltip_text(Some("Start at boot, enabling also starts it now"));
+    row.running.set_tooltip_text(Some("Running now"));
+    for (title, switch) in [("Enabled", &row.enabled), ("Running", &row.running)] {
+        switch.set_valign(gtk::Align::Center);
+        switch.set_sensitive(false);
+        row_box.pack_end(switch, false, false, 0);
+        row_box.pack_end(&gtk::Label::new(Some(title)), false, false, 0);
+    }
+
+    (row_box, row)
+}
+
+/// Queries systemd in a separate thread, then updates the switches.
+fn refresh_services(rows: Rc<Vec<ServiceRow>>, guard: RefreshGuard) {
+    let units = rows.iter().map(|row| row.unit.clone()).collect::<Vec<_>>();
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
     std::thread::spawn(move || {
-        let _ = utils::run_cmd_terminal(String::from(cmd), escalate);
+        let result = SystemdClient::system()
+            .map_err(|err| err.to_string())
+            .and_then(|client| client.query(&units));
+        tx.send(result).expect("Couldn't send data to channel");
+    });
+
+    rx.attach(None, move |result| {
+        let systemd_units = result.unwrap_or_else(|err| {
+            eprintln!("unable to query systemd: {err}");
+            SystemdUnits::new()
+        });
+        guard.set(true);
+        for row in rows.iter() {
+            let loaded = systemd_units.is_loaded(&row.unit);
+            for (switch, state) in [
+                (&row.enabled, systemd_units.is_enabled(&row.unit)),
+                (&row.running, systemd_units.is_active(&row.unit)),
+            ] {
+                switch.set_state(state);
+                switch.set_active(state);
+                switch.set_sensitive(loaded);
+            }
+        }
+        guard.set(false);
+        glib::Continue(false)
+    });
+}
+
+/// Asks the system helper for the change, the switches follow systemd afterwards.
+fn on_service_switch_set<F>(
+    rows: &Rc<Vec<ServiceRow>>,
+    guard: &RefreshGuard,
+    switch: &gtk::Switch,
+    method: F,
+) -> gtk::Inhibit
+where
+    F: FnOnce(&HelperClient) -> Result<(), String> + Send + 'static,
+{
+    if guard.get() {
+        return gtk::Inhibit(false);
+    }
+    for row in rows.iter() {
+        row.enabled.set_sensitive(false);
+        row.running.set_sensitive(false);
+    }
+    let parent = toplevel_window(switch);
+
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    std::thread::spawn(move || {
+        let result = HelperClient::system()
+            .map_err(|err| err.to_string())
+            .and_then(|client| method(&client));
+        tx.send(result).expect("Couldn't send data to channel");
+    });
+
+    let (rows, guard) = (rows.clone(), guard.clone());
+    rx.attach(None, move |result| {
+        if let Err(err) = result {
+            let dialog = gtk::MessageDialog::new(
+                parent.as_ref(),
+                gtk::DialogFlags::MODAL,
+                gtk::MessageType::Error,
+                gtk::ButtonsType::Close,
+                &err,
+            );
+            dialog.run();
+            dialog.close();
+        }
+        refresh_services(rows.clone(), guard.clone());
+        glib::Continue(false)
     });
+
+    // the state is set once systemd reports it
+    gtk::Inhibit(true)
 }
 
-fn on_clear_pkgcache_btn_clicked(_: &gtk::Button) {
-    let (cmd, escalate) = match utils::get_pacman_wrapper() {
-        PacmanWrapper::Pak => ("pak -Sc", false),
-        PacmanWrapper::Yay => ("yay -Sc", false),
-        PacmanWrapper::Paru => ("paru -Sc", false),
-        _ => ("pacman -Sc", true),
+pub fn create_services_page(builder: &Builder) {
+    let services = match services::load_services(&services::services_path()) {
+        Ok(services) => services,
+        Err(err) => {
+            eprintln!("services page: {err}");
+            return;
+        }
     };
-    // Spawn child process in separate thread.
+    let install: gtk::Button = builder.object("servicesBrowser").unwrap();
+    install.set_visible(true);
+
+    let viewport = gtk::Viewport::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    let image = gtk::Image::from_icon_name(Some("go-previous"), gtk::IconSize::Button);
+    let back_btn = gtk::Button::new();
+    back_btn.set_image(Some(&image));
+    back_btn.set_widget_name("home");
+
+    back_btn.connect_clicked(glib::clone!(@weak builder => move |button| {
+        let name = button.widget_name();
+        let stack: gtk::Stack = builder.object("stack").unwrap();
+        stack.set_visible_child_name(&format!("{name}page"));
+    }));
+
+    let list_box = gtk::ListBox::new();
+    list_box.set_selection_mode(gtk::SelectionMode::None);
+    let mut rows: Vec<ServiceRow> = Vec::new();
+    for entry in &services {
+        let (row_box, row) = create_service_row(entry);
+        list_box.add(&row_box);
+        rows.push(row);
+    }
+
+    let rows = Rc::new(rows);
+    let guard: RefreshGuard = Rc::new(Cell::new(false));
+    for row in rows.iter() {
+        let unit = row.unit.clone();
+        row.enabled.connect_state_set(
+            glib::clone!(@strong rows, @strong guard => move |switch, state| {
+                let unit = unit.clone();
+                on_service_switch_set(&rows, &guard, switch, move |client| {
+                    client.toggle_service(&unit, state)
+                })
+            }),
+        );
+        let unit = row.unit.clone();
+        row.running.connect_state_set(
+            glib::clone!(@strong rows, @strong guard => move |switch, state| {
+                let unit = unit.clone();
+                on_service_switch_set(&rows, &guard, switch, move |client| {
+                    client.set_service_running(&unit, state)
+                })
+            }),
+        );
+    }
+
+    let reload_btn = gtk::Button::with_label("Refresh");
+    reload_btn.connect_clicked(glib::clone!(@strong rows, @strong guard => move |_| {
+        refresh_services(rows.clone(), guard.clone());
+    }));
+    // states may have changed outside of the menu
+    viewport.connect_map(glib::clone!(@strong rows, @strong guard => move |_| {
+        refresh_services(rows.clone(), guard.clone());
+    }));
+
+    let grid = gtk::Grid::new();
+    grid.set_hexpand(true);
+    grid.set_margin_start(10);
+    grid.set_margin_end(10);
+    grid.set_margin_top(5);
+    grid.set_margin_bottom(5);
+    grid.set_row_spacing(10);
+    grid.attach(&back_btn, 0, 1, 1, 1);
+    list_box.set_hexpand(true);
+    grid.attach(&list_box, 1, 2, 5, 1);
+    reload_btn.set_halign(gtk::Align::End);
+    grid.attach(&reload_btn, 1, 3, 5, 1);
+    viewport.add(&grid);
+    viewport.show_all();
+
+    let stack: gtk::Stack = builder.object("stack").unwrap();
+    let child_name = "servicesBrowserpage";
+    stack.add_named(&viewport, child_name);
+}
+
+/// Columns of the history page store.
+const HISTORY_TIME: u32 = 0;
+const HISTORY_ACTION: u32 = 1;
+const HISTORY_PACKAGES: u32 = 2;
+const HISTORY_BACKEND: u32 = 3;
+const HISTORY_OUTCOME: u32 = 4;
+/// Index of the entry in the journal.
+const HISTORY_INDEX: u32 = 5;
+
+/// The journal and the package changes of pacman.log, read when the page is shown.
+type HistoryData = Rc<RefCell<(Vec<HistoryEntry>, Vec<PacmanEvent>)>>;
+
+fn format_time(timestamp: i64) -> String {
+    glib::DateTime::from_unix_local(timestamp)
+        .and_then(|time| time.format("%Y-%m-%d %H:%M"))
+        .map(String::from)
+        .unwrap_or_default()
+}
+
+/// Lists the entries kept by `filter` and `search`, latest first.
+fn fill_history_store(
+    store: &gtk::ListStore,
+    entries: &[HistoryEntry],
+    filter: HistoryFilter,
+    search: &str,
+) {
+    store.clear();
+    for (index, entry) in entries.iter().enumerate().rev() {
+        if !entry.matches(filter, search) {
+            continue;
+        }
+        store.insert_with_values(
+            None,
+            &[
+                (HISTORY_TIME, &format_time(entry.timestamp)),
+                (HISTORY_ACTION, &entry.action.label()),
+                (HISTORY_PACKAGES, &entry.packages.join(" ")),
+                (HISTORY_BACKEND, &entry.backend),
+                (HISTORY_OUTCOME, &entry.outcome.label()),
+                (HISTORY_INDEX, &(index as u32)),
+            ],
+        );
+    }
+}
+
+/// What went wrong, then the lines of pacman.log written while `entry` ran.
+fn history_details(entry: &HistoryEntry, events: &[PacmanEvent]) -> String {
+    let mut details = format!("{}: {}", entry.action.label(), entry.outcome.label());
+    if let Some(detail) = &entry.detail {
+        details.push_str(&format!(" ({detail})"));
+    }
+    details.push('\n');
+
+    let events = history::events_for(entry, events);
+    if events.is_empty() {
+        details.push_str(&format!(
+            "\nNo package change in {}.",
+            history::PACMAN_LOG_PATH
+        ));
+    } else {
+        details.push_str(&format!("\n{}:\n", history::PACMAN_LOG_PATH));
+        for event in events {
+            details.push_str(&format!(
+                "[{}] {} {} ({})\n",
+                format_time(event.timestamp),
+                event.action,
+                event.package,
+                event.version
+            ));
+        }
+    }
+    details
+}
+
+pub fn create_history_page(builder: &Builder) {
+    let history_btn: gtk::Button = builder.object("historyBrowser").unwrap();
+    history_btn.set_visible(true);
+
+    let viewport = gtk::Viewport::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    let image = gtk::Image::from_icon_name(Some("go-previous"), gtk::IconSize::Button);
+    let back_btn = gtk::Button::new();
+    back_btn.set_image(Some(&image));
+    back_btn.set_widget_name("home");
+
+    back_btn.connect_clicked(glib::clone!(@weak builder => move |button| {
+        let name = button.widget_name();
+        let stack: gtk::Stack = builder.object("stack").unwrap();
+        stack.set_visible_child_name(&format!("{name}page"));
+    }));
+
+    let filter_combo = gtk::ComboBoxText::new();
+    for (id, label) in [
+        ("all", "All"),
+        ("packages", "Apps"),
+        ("tweaks", "Tweaks"),
+        ("failures", "Failures"),
+    ] {
+        filter_combo.append(Some(id), label);
+    }
+    filter_combo.set_active_id(Some("all"));
+    let search_entry = gtk::SearchEntry::new();
+    search_entry.set_hexpand(true);
+    search_entry.set_placeholder_text(Some("Search packages"));
+
+    let store = gtk::ListStore::new(&[
+        String::static_type(),
+        String::static_type(),
+        String::static_type(),
+        String::static_type(),
+        String::static_type(),
+        u32::static_type(),
+    ]);
+    let tree_view = gtk::TreeView::with_model(&store);
+    for (title, store_column) in [
+        ("Time", HISTORY_TIME),
+        ("Action", HISTORY_ACTION),
+        ("Packages", HISTORY_PACKAGES),
+        ("Backend", HISTORY_BACKEND),
+        ("Outcome", HISTORY_OUTCOME),
+    ] {
+        let renderer = gtk::CellRendererText::new();
+        if store_column == HISTORY_PACKAGES {
+            renderer.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        }
+        let column = create_column(title, &renderer, "text", store_column);
+        column.set_resizable(true);
+        column.set_expand(store_column == HISTORY_PACKAGES);
+        tree_view.append_column(&column);
+    }
+    let list_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    list_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    list_window.set_shadow_type(gtk::ShadowType::In);
+    list_window.set_vexpand(true);
+    list_window.set_min_content_height(200);
+    list_window.add(&tree_view);
+
+    let details_view = gtk::TextView::new();
+    details_view.set_editable(false);
+    details_view.set_cursor_visible(false);
+    details_view.set_monospace(true);
+    details_view.set_wrap_mode(gtk::WrapMode::WordChar);
+    let details_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    details_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    details_window.set_shadow_type(gtk::ShadowType::In);
+    details_window.set_min_content_height(120);
+    details_window.add(&details_view);
+
+    let view_log_btn = gtk::Button::with_label("View log");
+    view_log_btn.set_sensitive(false);
+    let reload_btn = gtk::Button::with_label("Refresh");
+
+    let data: HistoryData = Rc::new(RefCell::new((Vec::new(), Vec::new())));
+    let refill = Rc::new(
+        glib::clone!(@weak store, @weak filter_combo, @weak search_entry, @strong data => move || {
+            let filter = HistoryFilter::from_id(filter_combo.active_id().as_deref().unwrap_or("all"));
+            fill_history_store(&store, &data.borrow().0, filter, &search_entry.text());
+        }),
+    );
+    filter_combo.connect_changed(glib::clone!(@strong refill => move |_| refill()));
+    search_entry.connect_search_changed(glib::clone!(@strong refill => move |_| refill()));
+
+    let selected_entry = Rc::new(
+        glib::clone!(@weak tree_view, @strong data => @default-return None, move || {
+            let (model, iter) = tree_view.selection().selected()?;
+            let index = model.value(&iter, HISTORY_INDEX as i32).get::<u32>().ok()?;
+            data.borrow().0.get(index as usize).cloned()
+        }),
+    );
+    tree_view.selection().connect_changed(
+        glib::clone!(@weak details_view, @weak view_log_btn, @strong data, @strong selected_entry => move |_| {
+            let entry = selected_entry();
+            let text = entry
+                .as_ref()
+                .map(|entry| history_details(entry, &data.borrow().1))
+                .unwrap_or_default();
+            if let Some(buffer) = details_view.buffer() {
+                buffer.set_text(&text);
+            }
+            view_log_btn.set_sensitive(entry.is_some_and(|entry| entry.log_path.is_some()));
+        }),
+    );
+    view_log_btn.connect_clicked(glib::clone!(@strong selected_entry => move |_| {
+        let log_path = selected_entry().and_then(|entry| entry.log_path);
+        if let (Some(log_path), Some(application)) = (log_path, gio::Application::default()) {
+            application.activate_action(
+                terminal_dialog::VIEW_LOG_ACTION,
+                Some(&log_path.to_string_lossy().to_variant()),
+            );
+        }
+    }));
+
+    // pacman.log grows large, both are read away from the UI thread
+    let reload = Rc::new(glib::clone!(@strong data, @strong refill => move || {
+        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        std::thread::spawn(move || {
+            let entries = history::load(&history::history_path());
+            let events = history::parse_pacman_log(
+                &std::fs::read_to_string(history::PACMAN_LOG_PATH).unwrap_or_default(),
+            );
+            tx.send((entries, events)).expect("Couldn't send data to channel");
+        });
+        rx.attach(
+            None,
+            glib::clone!(@strong data, @strong refill => move |loaded| {
+                *data.borrow_mut() = loaded;
+                refill();
+                glib::Continue(false)
+            }),
+        );
+    }));
+    reload_btn.connect_clicked(glib::clone!(@strong reload => move |_| reload()));
+    viewport.connect_map(glib::clone!(@strong reload => move |_| reload()));
+
+    let grid = gtk::Grid::new();
+    grid.set_hexpand(true);
+    grid.set_margin_start(10);
+    grid.set_margin_end(10);
+    grid.set_margin_top(5);
+    grid.set_margin_bottom(5);
+    grid.set_row_spacing(10);
+    grid.set_column_spacing(10);
+    grid.attach(&back_btn, 0, 1, 1, 1);
+    grid.attach(&filter_combo, 1, 1, 1, 1);
+    grid.attach(&search_entry, 2, 1, 4, 1);
+    grid.attach(&list_window, 1, 2, 5, 1);
+    grid.attach(&details_window, 1, 3, 5, 1);
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    button_box.set_halign(gtk::Align::End);
+    button_box.pack_start(&view_log_btn, false, false, 0);
+    button_box.pack_start(&reload_btn, false, false, 0);
+    grid.attach(&button_box, 1, 4, 5, 1);
+    viewport.add(&grid);
+    viewport.show_all();
+
+    let stack: gtk::Stack = builder.object("stack").unwrap();
+    let child_name = "historyBrowserpage";
+    stack.add_named(&viewport, child_name);
+}
+
+/// Calls the system helper in a separate thread, then records and reports the outcome.
+fn run_helper_method<F>(action: HistoryAction, done_message: &'static str, method: F)
+where
+    F: FnOnce(&HelperClient) -> Result<(), String> + Send + 'static,
+{
+    let started = history::now();
+    // Create context channel.
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+
     std::thread::spawn(move || {
-        let _ = utils::run_cmd_terminal(String::from(cmd), escalate);
+        let result = HelperClient::system()
+            .map_err(|err| err.to_string())
+            .and_then(|client| method(&client));
+        tx.send(result).expect("Couldn't send data to channel");
+    });
+
+    rx.attach(None, move |result| {
+        history::record(HistoryEntry {
+            timestamp: started,
+            duration: history::now() - started,
+            action,
+            packages: Vec::new(),
+            backend: String::from("system_helper"),
+            // a dismissed polkit prompt is reported as not authorized
+            outcome: match &result {
+                Ok(_) => HistoryOutcome::Success,
+                Err(err) if system_helper::is_not_authorized(err) => HistoryOutcome::Cancelled,
+                Err(_) => HistoryOutcome::Failed,
+            },
+            detail: result.as_ref().err().cloned(),
+            log_path: None,
+        });
+        let (message_type, text) = match result {
+            Ok(_) => (gtk::MessageType::Info, String::from(done_message)),
+            Err(err) => (gtk::MessageType::Error, err),
+        };
+        let dialog = gtk::MessageDialog::builder()
+            .message_type(message_type)
+            .buttons(gtk::ButtonsType::Close)
+            .text(&text)
+            .build();
+        dialog.connect_response(|dialog, _| dialog.close());
+        dialog.show();
+        glib::Continue(false)
     });
 }
 
+fn show_message(parent: Option<&gtk::Window>, message_type: gtk::MessageType, text: &str) {
+    let dialog = gtk::MessageDialog::new(
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        message_type,
+        gtk::ButtonsType::Close,
+        text,
+    );
+    dialog.run();
+    dialog.close();
+}
+
+/// Only removes a stale lock, the system helper checks again as root.
+fn on_removelock_btn_clicked(button: &gtk::Button) {
+    let (message_type, text) =
+        match db_lock::check(Path::new(db_lock::DB_LOCK_PATH), Path::new("/proc")) {
+            LockState::Stale => {
+                run_helper_method(
+                    HistoryAction::RemoveDbLock,
+                    "Pacman db lock was removed!",
+                    |client| client.remove_db_lock(),
+                );
+                return;
+            }
+            LockState::Absent => (
+                gtk::MessageType::Info,
+                String::from("The package database is not locked."),
+            ),
+            LockState::Held(holders) => (
+                gtk::MessageType::Warning,
+                format!(
+                    "The package database is in use, the lock was kept. \
+                     Wait for these programs to finish:\n\n{}",
+                    db_lock::describe(&holders)
+                ),
+            ),
+        };
+    show_message(toplevel_window(button).as_ref(), message_type, &text);
+}
+
+/// Lists the orphans for review, only those the user confirms are removed.
+fn on_remove_orphans_btn_clicked(button: &gtk::Button) {
+    let parent = toplevel_window(button);
+    let orphans = pacmanconf::Config::with_opts(None, Some("/etc/pacman.conf"), Some("/"))
+        .map_err(|err| err.to_string())
+        .and_then(|pacman| alpm_utils::alpm_with_conf(&pacman).map_err(|err| err.to_string()))
+        .map(|alpm| package_search::orphans(&alpm, true));
+    let orphans = match orphans {
+        Ok(orphans) => orphans,
+        Err(err) => {
+            show_message(parent.as_ref(), gtk::MessageType::Error, &err);
+            return;
+        }
+    };
+    if orphans.is_empty() {
+        show_message(
+            parent.as_ref(),
+            gtk::MessageType::Info,
+            "There are no orphan packages, nothing to clean.",
+        );
+        return;
+    }
+
+    let pkg_names = orphans_dialog::review_orphans(parent.as_ref(), &orphans);
+    if pkg_names.is_empty() {
+        return;
+    }
+    // only the reviewed packages, not their own dependencies
+    let cmd = match utils::get_pacman_wrapper() {
+        PacmanWrapper::Pak => "pak -Rn",
+        PacmanWrapper::Yay => "yay -Rn",
+        PacmanWrapper::Paru => "paru -Rn",
+        PacmanWrapper::Pacman => {
+            run_helper_method(
+                HistoryAction::RemoveOrphans,
+                "The orphan packages were removed.",
+                move |client| client.remove(&pkg_names),
+            );
+            return;
+        }
+    };
+    run_tweak(
+        parent.as_ref(),
+        HistoryAction::RemoveOrphans,
+        &format!("{cmd} {}", pkg_names.join(" ")),
+    );
+}
+
+fn on_update_system_btn_clicked(button: &gtk::Button) {
+    update_system(toplevel_window(button).as_ref());
+}
+
+/// Updates the system through the pacman wrapper, or the system helper for plain pacman.
+pub fn update_system(parent: Option<&gtk::Window>) {
+    let cmd = match utils::get_pacman_wrapper() {
+        PacmanWrapper::Pak => "pak -Syu",
+        PacmanWrapper::Yay => "yay -Syu",
+        PacmanWrapper::Paru => "paru --removemake -Syu",
+        PacmanWrapper::Pacman => {
+            run_helper_method(
+                HistoryAction::SystemUpdate,
+                "The system is up to date.",
+                |client| client.sync_upgrade(),
+            );
+            return;
+        }
+    };
+    run_tweak(parent, HistoryAction::SystemUpdate, cmd);
+}
+
+fn on_clear_pkgcache_btn_clicked(button: &gtk::Button) {
+    let cmd = match utils::get_pacman_wrapper() {
+        PacmanWrapper::Pak => "pak -Sc",
+        PacmanWrapper::Yay => "yay -Sc",
+        PacmanWrapper::Paru => "paru -Sc",
+        PacmanWrapper::Pacman => {
+            run_helper_method(
+                HistoryAction::ClearCache,
+                "The package cache was cleared.",
+                |client| client.clear_cache(),
+            );
+            return;
+        }
+    };
+    run_tweak(
+        toplevel_window(button).as_ref(),
+        HistoryAction::ClearCache,
+        cmd,
+    );
+}
+
 fn on_appbtn_clicked(button: &gtk::Button) {
     // Get button label.
     let name = button.label().unwrap();
@@ -231,20 +860,6 @@ fn on_appbtn_clicked(button: &gtk::Button) {
         return;
     }
 
-    let mut envs = String::new();
-    for env in glib::listenv() {
-        if env == "PATH" {
-            envs += "PATH=/sbin:/bin:/usr/local/sbin:/usr/local/bin:/usr/bin:/usr/sbin ";
-            continue;
-        }
-        let _ = write!(
-            envs,
-            "{}=\"{}\" ",
-            env.to_str().unwrap(),
-            glib::getenv(&env).unwrap().to_str().unwrap()
-        );
-    }
-
     // Get executable path.
     let mut exe_path = Exec::cmd("which")
         .arg(binname)
@@ -253,23 +868,21 @@ fn on_appbtn_clicked(button: &gtk::Button) {
         .unwrap()
         .stdout_str();
     exe_path.pop();
-    let bash_cmd = format!("{} {}", &envs, &exe_path);
+
+    // only the display variables are passed through pkexec
+    let command = match is_sudo {
+        true => live_session::graphical_root_command(&exe_path, false, |name| {
+            glib::getenv(name).and_then(|value| value.into_string().ok())
+        }),
+        false => vec![exe_path],
+    };
 
     // Create context channel.
     let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
 
     // Spawn child process in separate thread.
     std::thread::spawn(move || {
-        let exit_status = if is_sudo {
-            Exec::cmd("/sbin/pkexec")
-                .arg("bash")
-                .arg("-c")
-                .arg(bash_cmd)
-                .join()
-                .unwrap()
-        } else {
-            Exec::shell(bash_cmd).join().unwrap()
-        };
+        let exit_status = Exec::cmd(&command[0]).args(&command[1..]).join().unwrap();
         tx.send(format!(
             "Exit status successfully? = {:?}",
             exit_status.success()
@@ -0,0 +1,228 @@
+use crate::config::{LOCALEDIR, PKGDATADIR};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Distribution settings, the bundled file can be overridden from /etc and by each user.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub autostart_path: String,
+    /// Public keys allowed to sign the catalog downloaded from `catalog_url`.
+    pub catalog_keyring: String,
+    pub catalog_url: String,
+    pub data_path: String,
+    pub desktop_path: String,
+    pub first_boot_wizard: bool,
+    pub installer_path: String,
+    pub live_path: String,
+    pub locale_path: String,
+    pub logo_path: String,
+    pub ui_path: String,
+    pub style_path: String,
+    pub terminal: String,
+    pub transaction_backend: String,
+    pub urls: BTreeMap<String, String>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        let urls = [
+            ("github", "https://github.com/vicharak-in"),
+            ("software", "https://github.com/vicharak-in"),
+            ("forum", "https://vicharak.in"),
+            ("wiki", "https://vicharak.in"),
+        ];
+
+        Self {
+            autostart_path: String::from("~/.config/autostart/vaamos-menu.desktop"),
+            catalog_keyring: String::from("/etc/vaamos-menu/catalog-keyring.gpg"),
+            catalog_url: String::new(),
+            data_path: format!("{PKGDATADIR}/data/"),
+            desktop_path: String::from("/usr/share/applications/vaamos-menu.desktop"),
+            first_boot_wizard: true,
+            installer_path: String::from("/usr/bin/calamares"),
+            live_path: String::from("/run/archiso/bootmnt/arch"),
+            locale_path: format!("{LOCALEDIR}/"),
+            logo_path: String::from("/usr/share/icons/hicolor/scalable/apps/"),
+            ui_path: format!("{PKGDATADIR}/ui/vaamos-menu.glade"),
+            style_path: format!("{PKGDATADIR}/ui/style.css"),
+            terminal: String::from("embedded"),
+            transaction_backend: String::from("native"),
+            urls: urls
+                .iter()
+                .map(|(name, url)| (String::from(*name), String::from(*url)))
+                .collect(),
+        }
+    }
+}
+
+/// A setting which could not be used, the previous layer's value is kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreferenceIssue {
+    pub origin: String,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for PreferenceIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "{}: {key}: {}", self.origin, self.message),
+            None => write!(f, "{}: {}", self.origin, self.message),
+        }
+    }
+}
+
+/// The bundled preferences, then the system and user overrides.
+pub fn preference_files() -> Vec<PathBuf> {
+    vec![
+        PathBuf::from(format!("{PKGDATADIR}/data/preferences.json")),
+        PathBuf::from("/etc/vaamos-menu/preferences.json"),
+        glib::user_config_dir().join("vaamos-menu/preferences.json"),
+    ]
+}
+
+impl Preferences {
+    pub fn load() -> (Self, Vec<PreferenceIssue>) {
+        Self::load_layered(&preference_files())
+    }
+
+    /// Applies each file on top of the defaults, key by key.
+    /// The first file is expected to exist, the overrides are optional.
+    pub fn load_layered(paths: &[PathBuf]) -> (Self, Vec<PreferenceIssue>) {
+        let mut merged = serde_json::to_value(Self::default()).unwrap();
+        let mut issues: Vec<PreferenceIssue> = Vec::new();
+
+        for (index, path) in paths.iter().enumerate() {
+            let origin = path.display().to_string();
+            let issue = |key: Option<&str>, message: String| PreferenceIssue {
+                origin: origin.clone(),
+                key: key.map(String::from),
+                message,
+            };
+
+            let data = match fs::read_to_string(path) {
+                Ok(data) => data,
+                Err(_) if index > 0 && !path.exists() => continue,
+                Err(err) => {
+                    issues.push(issue(None, format!("{err}, defaults are used")));
+                    continue;
+                }
+            };
+            let layer = match serde_json::from_str::<serde_json::Value>(&data) {
+                Ok(serde_json::Value::Object(layer)) => layer,
+                Ok(_) => {
+                    issues.push(issue(None, String::from("expected a JSON object")));
+                    continue;
+                }
+                Err(err) => {
+                    issues.push(issue(None, format!("{err}, the file is ignored")));
+                    continue;
+                }
+            };
+
+            for (key, value) in layer {
+                if merged.get(&key).is_none() {
+                    issues.push(issue(Some(&key), String::from("unknown setting")));
+                    continue;
+                }
+                let mut candidate = merged.clone();
+                match (&mut candidate[&key], value) {
+                    // urls are overridden one by one
+                    (serde_json::Value::Object(current), serde_json::Value::Object(value)) => {
+                        current.extend(value)
+                    }
+                    (current, value) => *current = value,
+                }
+                match serde_json::from_value::<Self>(candidate.clone()) {
+                    Ok(_) => merged = candidate,
+                    Err(err) => issues.push(issue(Some(&key), err.to_string())),
+                }
+            }
+        }
+
+        (serde_json::from_value(merged).unwrap_or_default(), issues)
+    }
+
+    pub fn url(&self, name: &str) -> Option<&str> {
+        self.urls.get(name).map(String::as_str)
+    }
+}
+
+/// Checks that the files the window needs are there.
+pub fn check_paths(preferences: &Preferences) -> Vec<PreferenceIssue> {
+    [
+        ("ui_path", &preferences.ui_path),
+        ("style_path", &preferences.style_path),
+    ]
+    .iter()
+    .filter(|(_, path)| !Path::new(path).is_file())
+    .map(|(key, path)| PreferenceIssue {
+        origin: String::from("preferences"),
+        key: Some(String::from(*key)),
+        message: format!("{path} does not exist"),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn layers_and_issues() {
+        let temp_dir = TempDir::new("preferences");
+        let dir = temp_dir.path();
+        let system = dir.join("system.json");
+        let etc = dir.join("etc.json");
+        let user = dir.join("user.json");
+        fs::write(
+            &system,
+            r#"{"catalog_url": "https://example.org/catalog.json", "first_boot_wizard": "yes",
+                "urls": {"forum": "https://forum.example.org"}, "colour": "blue"}"#,
+        )
+        .unwrap();
+        fs::write(
+            &etc,
+            r#"{"transaction_backend": "terminal", "urls": {"wiki": 3}}"#,
+        )
+        .unwrap();
+        fs::write(&user, "{\"ui_path\": ").unwrap();
+
+        let (preferences, issues) =
+            Preferences::load_layered(&[system.clone(), etc.clone(), user.clone()]);
+        assert_eq!(preferences.catalog_url, "https://example.org/catalog.json");
+        assert_eq!(preferences.transaction_backend, "terminal");
+        assert!(preferences.first_boot_wizard);
+        assert_eq!(preferences.url("forum"), Some("https://forum.example.org"));
+        assert_eq!(preferences.url("wiki"), Some("https://vicharak.in"));
+        assert_eq!(preferences.ui_path, Preferences::default().ui_path);
+
+        let keys = issues
+            .iter()
+            .map(|issue| (issue.origin.clone(), issue.key.clone()))
+            .collect::<Vec<_>>();
+        let origin = |path: &PathBuf| path.display().to_string();
+        assert_eq!(
+            keys,
+            [
+                (origin(&system), Some(String::from("colour"))),
+                (origin(&system), Some(String::from("first_boot_wizard"))),
+                (origin(&etc), Some(String::from("urls"))),
+                (origin(&user), None),
+            ]
+        );
+
+        // only the bundled file is required
+        let (preferences, issues) =
+            Preferences::load_layered(&[dir.join("missing.json"), dir.join("missing-too.json")]);
+        assert_eq!(preferences, Preferences::default());
+        assert_eq!(issues.len(), 1);
+    }
+}
@@ -0,0 +1,273 @@
+use crate::catalog::Catalog;
+use crate::system_helper;
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PROFILE_VERSION: u32 = 1;
+
+/// A saved app selection, entries are written like the pending lists:
+/// the app package followed by its extra packages.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Profile {
+    pub version: u32,
+    #[serde(default)]
+    pub name: String,
+    /// Catalog apps installed when the profile was saved.
+    #[serde(default)]
+    pub installed: Vec<String>,
+    #[serde(default)]
+    pub install: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Parse(err) => write!(f, "not a profile: {err}"),
+            Self::Invalid(reason) => write!(f, "invalid profile: {reason}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ProfileError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ProfileError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Parse(err)
+    }
+}
+
+/// Differences between a profile and the current system.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ProfileDiff {
+    /// Wanted by the profile, not installed here.
+    pub to_install: Vec<String>,
+    /// Removed by the profile, still installed here.
+    pub to_remove: Vec<String>,
+    /// Catalog apps installed here which the profile does not have.
+    pub not_in_profile: Vec<String>,
+}
+
+impl ProfileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.to_install.is_empty() && self.to_remove.is_empty() && self.not_in_profile.is_empty()
+    }
+}
+
+pub fn profiles_dir() -> PathBuf {
+    glib::user_config_dir().join("vaamos-menu/profiles")
+}
+
+/// The package an entry is shown as, its other packages are extras.
+fn entry_pkg(entry: &str) -> &str {
+    entry.split_whitespace().next().unwrap_or_default()
+}
+
+fn push_unique(entries: &mut Vec<String>, entry: &str) {
+    if !entries.iter().any(|x| x == entry) {
+        entries.push(String::from(entry));
+    }
+}
+
+impl Profile {
+    /// Captures the pending selection and the catalog apps which are installed.
+    pub fn capture(
+        name: &str,
+        catalog: &Catalog,
+        is_installed: impl Fn(&str) -> bool,
+        install: &[String],
+        remove: &[String],
+    ) -> Self {
+        let mut installed: Vec<String> = Vec::new();
+        for app in catalog.groups.iter().flat_map(|group| &group.apps) {
+            if is_installed(&app.pkg) {
+                push_unique(&mut installed, &app.packages().join(" "));
+            }
+        }
+
+        Self {
+            version: PROFILE_VERSION,
+            name: String::from(name),
+            installed,
+            install: install.to_vec(),
+            remove: remove.to_vec(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ProfileError> {
+        let data = fs::read_to_string(path)?;
+        let mut profile = serde_json::from_str::<Self>(&data)?;
+        profile.validate()?;
+        if profile.name.is_empty() {
+            profile.name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+
+        Ok(profile)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ProfileError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Profiles come from other machines, only package names are accepted.
+    fn validate(&self) -> Result<(), ProfileError> {
+        if self.version == 0 || self.version > PROFILE_VERSION {
+            return Err(ProfileError::Invalid(format!(
+                "unsupported version {}",
+                self.version
+            )));
+        }
+        let entries = self
+            .installed
+            .iter()
+            .chain(&self.install)
+            .chain(&self.remove);
+        for entry in entries {
+            let mut packages = entry.split_whitespace().peekable();
+            if packages.peek().is_none() {
+                return Err(ProfileError::Invalid(String::from("empty entry")));
+            }
+            if let Some(pkg_name) = packages.find(|pkg| !system_helper::valid_package_name(pkg)) {
+                return Err(ProfileError::Invalid(format!(
+                    "invalid package name: {pkg_name}"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Entries the profile wants installed.
+    pub fn wanted(&self) -> Vec<String> {
+        let mut wanted: Vec<String> = Vec::new();
+        for entry in self.installed.iter().chain(&self.install) {
+            if !self.remove.iter().any(|x| entry_pkg(x) == entry_pkg(entry)) {
+                push_unique(&mut wanted, entry);
+            }
+        }
+        wanted
+    }
+
+    pub fn diff(&self, catalog: &Catalog, is_installed: impl Fn(&str) -> bool) -> ProfileDiff {
+        let mut diff = ProfileDiff::default();
+        let wanted = self.wanted();
+
+        for entry in &wanted {
+            if !is_installed(entry_pkg(entry)) {
+                push_unique(&mut diff.to_install, entry);
+            }
+        }
+        for entry in &self.remove {
+            if is_installed(entry_pkg(entry)) {
+                push_unique(&mut diff.to_remove, entry);
+            }
+        }
+        for app in catalog.groups.iter().flat_map(|group| &group.apps) {
+            let listed = wanted
+                .iter()
+                .chain(&self.remove)
+                .any(|entry| entry_pkg(entry) == app.pkg);
+            if !listed && is_installed(&app.pkg) {
+                push_unique(&mut diff.not_in_profile, &app.packages().join(" "));
+            }
+        }
+
+        diff
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    const CATALOG: &str = r#"[{"name": "Tools", "apps": [
+        {"name": "Vim", "pkg": "vim"},
+        {"name": "Git", "pkg": "git", "extra": ["git-lfs"]},
+        {"name": "Emacs", "pkg": "emacs"}
+    ]}]"#;
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::default();
+        catalog.merge_json(CATALOG, "test");
+        catalog
+    }
+
+    #[test]
+    fn capture_and_diff() {
+        let catalog = catalog();
+        let here = |pkg: &str| pkg == "vim" || pkg == "git";
+        let profile = Profile::capture(
+            "lab",
+            &catalog,
+            here,
+            &[String::from("htop")],
+            &[String::from("vim")],
+        );
+        assert_eq!(profile.installed, ["vim", "git git-lfs"]);
+        assert_eq!(profile.wanted(), ["git git-lfs", "htop"]);
+
+        let there = |pkg: &str| pkg == "vim" || pkg == "emacs";
+        let diff = profile.diff(&catalog, there);
+        assert_eq!(diff.to_install, ["git git-lfs", "htop"]);
+        assert_eq!(diff.to_remove, ["vim"]);
+        assert_eq!(diff.not_in_profile, ["emacs"]);
+
+        let same = profile.diff(&catalog, |pkg: &str| pkg == "git" || pkg == "htop");
+        assert!(same.is_empty());
+    }
+
+    #[test]
+    fn load_rejects_bad_names() {
+        let dir = TempDir::new("profile");
+        let path = dir.path().join("lab.json");
+
+        let profile = Profile {
+            version: PROFILE_VERSION,
+            install: vec![String::from("git git-lfs")],
+            ..Profile::default()
+        };
+        profile.save(&path).unwrap();
+        let loaded = Profile::load(&path).unwrap();
+        assert_eq!(loaded.name, "lab");
+        assert_eq!(loaded.install, profile.install);
+
+        fs::write(&path, r#"{"version": 1, "install": ["vim; rm -rf /"]}"#).unwrap();
+        assert!(matches!(
+            Profile::load(&path),
+            Err(ProfileError::Invalid(_))
+        ));
+        fs::write(&path, r#"{"version": 9}"#).unwrap();
+        assert!(matches!(
+            Profile::load(&path),
+            Err(ProfileError::Invalid(_))
+        ));
+        fs::write(&path, "[]").unwrap();
+        assert!(matches!(Profile::load(&path), Err(ProfileError::Parse(_))));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::profile::{self, Profile, ProfileDiff};
+use crate::utils;
+
+use gtk::prelude::*;
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+/// Asks for a profile file, to save the selection to or to open.
+pub fn choose_file(parent: Option<&gtk::Window>, save: bool) -> Option<PathBuf> {
+    let (title, action, accept) = match save {
+        true => ("Save profile", gtk::FileChooserAction::Save, "Save"),
+        false => ("Open profile", gtk::FileChooserAction::Open, "Open"),
+    };
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some(title),
+        parent,
+        action,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            (accept, gtk::ResponseType::Accept),
+        ],
+    );
+    let filter = gtk::FileFilter::new();
+    filter.set_name(Some("Profiles"));
+    filter.add_pattern("*.json");
+    dialog.add_filter(&filter);
+
+    let profiles_dir = profile::profiles_dir();
+    let _ = fs::create_dir_all(&profiles_dir);
+    dialog.set_current_folder(&profiles_dir);
+    if save {
+        dialog.set_current_name("profile.json");
+        dialog.set_do_overwrite_confirmation(true);
+    }
+
+    let response = dialog.run();
+    let path = dialog.filename();
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => path.map(|path| match path.extension() {
+            Some(_) => path,
+            None => path.with_extension("json"),
+        }),
+        _ => None,
+    }
+}
+
+pub fn show_error(parent: Option<&gtk::Window>, text: &str) {
+    let dialog = gtk::MessageDialog::new(
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Close,
+        text,
+    );
+    dialog.run();
+    dialog.close();
+}
+
+fn write_entries(markup: &mut String, title: &str, entries: &[String]) {
+    if entries.is_empty() {
+        return;
+    }
+    let _ = writeln!(markup, "<b>{title} ({})</b>", entries.len());
+    for entry in entries {
+        let _ = writeln!(markup, "    {}", glib::markup_escape_text(entry));
+    }
+    markup.push('\n');
+}
+
+pub fn diff_markup(diff: &ProfileDiff) -> String {
+    if diff.is_empty() {
+        return String::from("This system already matches the profile.");
+    }
+
+    let mut markup = String::new();
+    write_entries(&mut markup, "To install", &diff.to_install);
+    write_entries(&mut markup, "To remove", &diff.to_remove);
+    write_entries(&mut markup, "Only on this system", &diff.not_in_profile);
+    markup.trim_end().to_string()
+}
+
+/// Shows how the system differs from `profile`.
+/// Returns true if its changes must be checked in the app browser.
+pub fn compare_profile(
+    parent: Option<&gtk::Window>,
+    profile: &Profile,
+    diff: &ProfileDiff,
+) -> bool {
+    let mut buttons = vec![("Close", gtk::ResponseType::Close)];
+    if !diff.to_install.is_empty() || !diff.to_remove.is_empty() {
+        buttons.push(("Select changes", gtk::ResponseType::Accept));
+    }
+    let dialog = utils::markup_dialog(
+        parent,
+        &format!("Profile {}", profile.name),
+        &diff_markup(diff),
+        &buttons,
+    );
+
+    let response = dialog.run();
+    dialog.close();
+
+    response == gtk::ResponseType::Accept
+}
@@ -0,0 +1,170 @@
+use crate::alpm_backend::{self, TransactionError, WorkerEvent, WorkerRequest};
+
+use gtk::prelude::*;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+fn append_log(text_view: &gtk::TextView, text: &str) {
+    let buffer = match text_view.buffer() {
+        Some(buffer) => buffer,
+        None => return,
+    };
+    let mut end = buffer.end_iter();
+    buffer.insert(&mut end, &format!("{text}\n"));
+
+    if let Some(mark) = buffer.create_mark(None, &buffer.end_iter(), false) {
+        text_view.scroll_mark_onscreen(&mark);
+        buffer.delete_mark(&mark);
+    }
+}
+
+fn ask_question(parent: &gtk::Dialog, text: &str) -> bool {
+    let dialog = gtk::MessageDialog::new(
+        Some(parent),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::YesNo,
+        text,
+    );
+    let response = dialog.run();
+    dialog.close();
+
+    response == gtk::ResponseType::Yes
+}
+
+/// Runs the transaction through the privileged worker, showing its progress.
+pub fn run_transaction(
+    parent: Option<&gtk::Window>,
+    install: &[String],
+    remove: &[String],
+) -> Result<(), TransactionError> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Applying changes"),
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[("Close", gtk::ResponseType::Close)],
+    );
+    dialog.set_default_size(520, 360);
+    dialog.set_response_sensitive(gtk::ResponseType::Close, false);
+
+    let status_label = gtk::Label::new(Some("Waiting for authentication…"));
+    status_label.set_xalign(0.0);
+    status_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    let progress_bar = gtk::ProgressBar::new();
+    progress_bar.set_show_text(true);
+
+    let text_view = gtk::TextView::new();
+    text_view.set_editable(false);
+    text_view.set_cursor_visible(false);
+    text_view.set_monospace(true);
+    text_view.set_wrap_mode(gtk::WrapMode::WordChar);
+    let scrolled_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    scrolled_window.set_shadow_type(gtk::ShadowType::In);
+    scrolled_window.set_vexpand(true);
+    scrolled_window.add(&text_view);
+
+    let content_area = dialog.content_area();
+    content_area.set_border_width(10);
+    content_area.set_spacing(6);
+    content_area.pack_start(&status_label, false, false, 0);
+    content_area.pack_start(&progress_bar, false, false, 0);
+    content_area.pack_start(&scrolled_window, true, true, 0);
+
+    // the transaction cannot be interrupted once started
+    let finished = Rc::new(Cell::new(false));
+    dialog.connect_delete_event(glib::clone!(@strong finished => move |_, _| {
+        gtk::Inhibit(!finished.get())
+    }));
+    dialog.show_all();
+
+    let request = WorkerRequest {
+        install: install.to_vec(),
+        remove: remove.to_vec(),
+    };
+    let result = Rc::new(RefCell::new(Err(TransactionError::Cancelled)));
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let worker = match alpm_backend::spawn_worker(&request, move |event| {
+        let _ = tx.send(event);
+    }) {
+        Ok(worker) => Rc::new(RefCell::new(worker)),
+        Err(err) => {
+            dialog.close();
+            return Err(TransactionError::Failed(format!(
+                "unable to start the privileged helper: {err}"
+            )));
+        }
+    };
+
+    rx.attach(
+        None,
+        glib::clone!(@weak dialog, @strong finished, @strong result => @default-return glib::Continue(false), move |event| {
+            match event {
+                WorkerEvent::Progress { step, package, percent, current, total } => {
+                    match package.is_empty() {
+                        true => status_label.set_text(&step),
+                        false => status_label.set_text(&format!("{step} {package}")),
+                    }
+                    let total = total.max(1) as f64;
+                    let done = current.saturating_sub(1) as f64 + f64::from(percent) / 100.0;
+                    progress_bar.set_fraction((done / total).clamp(0.0, 1.0));
+                    progress_bar.set_text(Some(&format!("{current}/{}", total as usize)));
+                }
+                WorkerEvent::Download { file, downloaded, total } => {
+                    status_label.set_text(&format!("Downloading {file}"));
+                    if total > 0 {
+                        progress_bar.set_fraction((downloaded as f64 / total as f64).clamp(0.0, 1.0));
+                        progress_bar.set_text(Some(&format!(
+                            "{} / {}",
+                            glib::format_size(downloaded.max(0) as u64),
+                            glib::format_size(total as u64)
+                        )));
+                    }
+                }
+                WorkerEvent::Package { action, package } => {
+                    append_log(&text_view, &format!("{package} {action}"));
+                }
+                WorkerEvent::Message { text } => append_log(&text_view, &text),
+                WorkerEvent::Log { level, text } => {
+                    append_log(&text_view, &format!("{level}: {text}"));
+                }
+                WorkerEvent::Question { text } => {
+                    append_log(&text_view, &text);
+                    let answer = ask_question(&dialog, &text);
+                    worker.borrow_mut().answer(answer);
+                }
+                WorkerEvent::Done { success, error } => {
+                    finished.set(true);
+                    match (success, error) {
+                        (true, _) => {
+                            status_label.set_text("Transaction completed");
+                            progress_bar.set_fraction(1.0);
+                            *result.borrow_mut() = Ok(());
+                        }
+                        (false, error) => {
+                            let error = error.unwrap_or_else(|| String::from("unknown error"));
+                            status_label.set_text("Transaction failed");
+                            append_log(&text_view, &format!("error: {error}"));
+                            *result.borrow_mut() = Err(TransactionError::Failed(error));
+                        }
+                    }
+                    dialog.set_response_sensitive(gtk::ResponseType::Close, true);
+                    return glib::Continue(false);
+                }
+                WorkerEvent::Cancelled => {
+                    // nothing happened, no need to keep the dialog around
+                    finished.set(true);
+                    dialog.response(gtk::ResponseType::Close);
+                    return glib::Continue(false);
+                }
+            }
+            glib::Continue(true)
+        }),
+    );
+
+    dialog.run();
+    dialog.close();
+
+    result.replace(Err(TransactionError::Cancelled))
+}
@@ -0,0 +1,300 @@
+use crate::config::PKGDATADIR;
+use crate::data_types::SystemdUnits;
+use crate::system_helper::valid_unit_name;
+
+use glib::{ToVariant, Variant};
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SYSTEMD_BUS_NAME: &str = "org.freedesktop.systemd1";
+pub const SYSTEMD_OBJECT_PATH: &str = "/org/freedesktop/systemd1";
+pub const SYSTEMD_MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+
+/// A unit offered on the services page.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ServiceEntry {
+    pub unit: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+pub fn services_path() -> PathBuf {
+    PathBuf::from(format!("{PKGDATADIR}/data/services.json"))
+}
+
+/// Loads the curated units, entries with an invalid unit name are left out.
+pub fn load_services(path: &Path) -> Result<Vec<ServiceEntry>, String> {
+    let data = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let entries = serde_json::from_str::<Vec<ServiceEntry>>(&data)
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+
+    Ok(entries
+        .into_iter()
+        .filter(|entry| {
+            let valid = valid_unit_name(&entry.unit);
+            if !valid {
+                eprintln!("{}: invalid unit name {}", path.display(), entry.unit);
+            }
+            valid
+        })
+        .collect())
+}
+
+/// Reads unit states from the systemd manager, which needs no privilege.
+/// Changes go through the system helper.
+pub struct SystemdClient {
+    connection: gio::DBusConnection,
+}
+
+impl SystemdClient {
+    pub fn system() -> Result<Self, glib::Error> {
+        let connection = gio::bus_get_sync(gio::BusType::System, gio::Cancellable::NONE)?;
+        Ok(Self::for_connection(connection))
+    }
+
+    pub fn for_connection(connection: gio::DBusConnection) -> Self {
+        Self { connection }
+    }
+
+    fn call(&self, method_name: &str, parameters: &Variant) -> Result<Variant, glib::Error> {
+        self.connection.call_sync(
+            Some(SYSTEMD_BUS_NAME),
+            SYSTEMD_OBJECT_PATH,
+            SYSTEMD_MANAGER_INTERFACE,
+            method_name,
+            Some(parameters),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+        )
+    }
+
+    pub fn query(&self, units: &[String]) -> Result<SystemdUnits, String> {
+        let mut systemd_units = SystemdUnits::new();
+        if units.is_empty() {
+            return Ok(systemd_units);
+        }
+
+        // a(ssssssouso): name, description, load state, active state, ...
+        let reply = self
+            .call("ListUnitsByNames", &(units.to_vec(),).to_variant())
+            .map_err(|err| err.message().to_string())?;
+        for unit in reply.child_value(0).iter() {
+            let field = |index| unit.child_value(index).str().map(String::from);
+            let (name, load_state, active_state) = match (field(0), field(2), field(3)) {
+                (Some(name), Some(load_state), Some(active_state)) => {
+                    (name, load_state, active_state)
+                }
+                _ => continue,
+            };
+            if load_state == "loaded" {
+                systemd_units.loaded_units.push(name.clone());
+            }
+            if matches!(active_state.as_str(), "active" | "reloading" | "activating") {
+                systemd_units.active_units.push(name);
+            }
+        }
+
+        // fails for units without a unit file, they are not enabled either
+        for unit in units {
+            let state = self
+                .call("GetUnitFileState", &(unit,).to_variant())
+                .ok()
+                .and_then(|reply| reply.get::<(String,)>());
+            if let Some((state,)) = state {
+                if matches!(state.as_str(), "enabled" | "enabled-runtime") {
+                    systemd_units.enabled_units.push(unit.clone());
+                }
+            }
+        }
+
+        Ok(systemd_units)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::system_helper::test::{connect, private_bus};
+    use glib::variant::ObjectPath;
+
+    const MANAGER_XML: &str = r#"
+<node>
+  <interface name="org.freedesktop.systemd1.Manager">
+    <method name="ListUnitsByNames">
+      <arg name="names" type="as" direction="in"/>
+      <arg name="units" type="a(ssssssouso)" direction="out"/>
+    </method>
+    <method name="GetUnitFileState">
+      <arg name="file" type="s" direction="in"/>
+      <arg name="state" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+    /// Unit, load state, active state and unit file state.
+    const MOCK_UNITS: &[(&str, &str, &str, &str)] = &[
+        ("bluetooth.service", "loaded", "active", "enabled"),
+        ("cups.service", "loaded", "inactive", "disabled"),
+        ("fstrim.timer", "loaded", "active", "enabled-runtime"),
+        ("sshd.service", "not-found", "inactive", ""),
+    ];
+
+    type UnitRow = (
+        String,
+        String,
+        String,
+        String,
+        String,
+        String,
+        ObjectPath,
+        u32,
+        String,
+        ObjectPath,
+    );
+
+    fn unit_row(name: &str) -> UnitRow {
+        let (load_state, active_state) = MOCK_UNITS
+            .iter()
+            .find(|unit| unit.0 == name)
+            .map_or(("not-found", "inactive"), |unit| (unit.1, unit.2));
+        let path = format!(
+            "/org/freedesktop/systemd1/unit/{}",
+            name.replace(['.', '-'], "_")
+        );
+        (
+            String::from(name),
+            String::new(),
+            String::from(load_state),
+            String::from(active_state),
+            String::from("dead"),
+            String::new(),
+            ObjectPath::try_from(path).unwrap(),
+            0,
+            String::new(),
+            ObjectPath::try_from("/").unwrap(),
+        )
+    }
+
+    /// Exports a fake systemd manager, as systemd1 would on the system bus.
+    fn serve_mock_systemd(address: String) {
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let context = glib::MainContext::new();
+            context
+                .with_thread_default(|| {
+                    let connection = connect(&address);
+                    let node_info = gio::DBusNodeInfo::for_xml(MANAGER_XML).unwrap();
+                    let interface_info = node_info
+                        .lookup_interface(SYSTEMD_MANAGER_INTERFACE)
+                        .unwrap();
+                    connection
+                        .register_object(
+                            SYSTEMD_OBJECT_PATH,
+                            &interface_info,
+                            |_, _, _, _, method_name, parameters, invocation| match method_name {
+                                "ListUnitsByNames" => {
+                                    let (names,) = parameters.get::<(Vec<String>,)>().unwrap();
+                                    let rows =
+                                        names.iter().map(|name| unit_row(name)).collect::<Vec<_>>();
+                                    invocation.return_value(Some(&(rows,).to_variant()));
+                                }
+                                _ => {
+                                    let (name,) = parameters.get::<(String,)>().unwrap();
+                                    match MOCK_UNITS
+                                        .iter()
+                                        .find(|unit| unit.0 == name && !unit.3.is_empty())
+                                    {
+                                        Some(unit) => {
+                                            invocation.return_value(Some(&(unit.3,).to_variant()))
+                                        }
+                                        None => invocation.return_dbus_error(
+                                            "org.freedesktop.systemd1.NoSuchUnit",
+                                            &format!("No such file or directory: {name}"),
+                                        ),
+                                    }
+                                }
+                            },
+                            |_, _, _, _, _| ().to_variant(),
+                            |_, _, _, _, _, _| false,
+                        )
+                        .unwrap();
+                    connection
+                        .call_sync(
+                            Some("org.freedesktop.DBus"),
+                            "/org/freedesktop/DBus",
+                            "org.freedesktop.DBus",
+                            "RequestName",
+                            Some(&(SYSTEMD_BUS_NAME, 4_u32).to_variant()),
+                            None,
+                            gio::DBusCallFlags::NONE,
+                            -1,
+                            gio::Cancellable::NONE,
+                        )
+                        .unwrap();
+                    ready_tx.send(()).unwrap();
+                    glib::MainLoop::new(Some(&context), false).run();
+                })
+                .unwrap();
+        });
+        ready_rx.recv().unwrap();
+    }
+
+    #[test]
+    fn query_mock_systemd() {
+        let (mut daemon, address) = match private_bus() {
+            Some(bus) => bus,
+            None => {
+                eprintln!("dbus-daemon not found, skipping");
+                return;
+            }
+        };
+        serve_mock_systemd(address.clone());
+
+        let client = SystemdClient::for_connection(connect(&address));
+        let units = MOCK_UNITS
+            .iter()
+            .map(|unit| String::from(unit.0))
+            .collect::<Vec<_>>();
+        let systemd_units = client.query(&units).unwrap();
+        assert_eq!(
+            systemd_units,
+            SystemdUnits {
+                loaded_units: vec![
+                    String::from("bluetooth.service"),
+                    String::from("cups.service"),
+                    String::from("fstrim.timer"),
+                ],
+                enabled_units: vec![
+                    String::from("bluetooth.service"),
+                    String::from("fstrim.timer"),
+                ],
+                active_units: vec![
+                    String::from("bluetooth.service"),
+                    String::from("fstrim.timer"),
+                ],
+            }
+        );
+        assert!(!systemd_units.is_loaded("sshd.service"));
+        assert!(systemd_units.is_enabled("fstrim.timer"));
+        assert!(!systemd_units.is_active("cups.service"));
+
+        daemon.kill().unwrap();
+        let _ = daemon.wait();
+    }
+
+    #[test]
+    fn bundled_services() {
+        let services = load_services(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/data/services.json"
+        )))
+        .unwrap();
+        assert!(services.iter().any(|entry| entry.unit == "sshd.service"));
+    }
+}
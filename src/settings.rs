@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+use once_cell::sync::Lazy;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WindowGeometry {
+    pub width: i32,
+    pub height: i32,
+    #[serde(default)]
+    pub maximized: bool,
+}
+
+/// Choices of the user, kept across sessions.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub locale: Option<String>,
+    /// Shows the extended selection of packages in the app browser.
+    pub advanced: bool,
+    /// Last group filter of the app browser.
+    pub group: Option<String>,
+    pub window: Option<WindowGeometry>,
+    /// Visibility of the optional app browser columns, by id.
+    pub columns: BTreeMap<String, bool>,
+    pub dismissed_notices: Vec<String>,
+    /// Closes the embedded terminal once a command succeeded.
+    pub terminal_close_when_done: bool,
+}
+
+static SETTINGS: Lazy<Mutex<Settings>> = Lazy::new(|| Mutex::new(Settings::load(&settings_path())));
+
+pub fn settings_path() -> PathBuf {
+    glib::user_config_dir().join("vaamos-menu/settings.json")
+}
+
+/// Column visibility was saved on its own before the settings file.
+fn legacy_columns_path(path: &Path) -> PathBuf {
+    path.with_file_name("columns.json")
+}
+
+/// Moves an unreadable settings file aside, so it can be looked at later.
+fn back_up(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}.bak", glib::real_time() / 1_000_000));
+    let backup = PathBuf::from(backup);
+    if let Err(err) = fs::rename(path, &backup) {
+        eprintln!("unable to back up {}: {err}", path.display());
+    }
+    backup
+}
+
+impl Settings {
+    /// Loads the settings, defaults are used if the file is missing or corrupt.
+    pub fn load(path: &Path) -> Self {
+        let mut settings = match fs::read_to_string(path) {
+            Ok(data) => match serde_json::from_str::<Self>(&data) {
+                Ok(settings) => settings,
+                Err(err) => {
+                    let backup = back_up(path);
+                    eprintln!(
+                        "{}: {err}, settings were reset and the old file moved to {}",
+                        path.display(),
+                        backup.display()
+                    );
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        };
+
+        let legacy_path = legacy_columns_path(path);
+        if settings.columns.is_empty() {
+            if let Some(columns) = fs::read_to_string(&legacy_path)
+                .ok()
+                .and_then(|data| serde_json::from_str::<BTreeMap<String, bool>>(&data).ok())
+            {
+                settings.columns = columns;
+                if settings.save(path).is_ok() {
+                    let _ = fs::remove_file(&legacy_path);
+                }
+            }
+        }
+
+        settings
+    }
+
+    /// Writes the settings through a temporary file, an interrupted write keeps the old ones.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(config_dir) = path.parent() {
+            fs::create_dir_all(config_dir)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn is_dismissed(&self, notice: &str) -> bool {
+        self.dismissed_notices.iter().any(|x| x == notice)
+    }
+}
+
+/// A copy of the current settings.
+pub fn current() -> Settings {
+    SETTINGS.lock().unwrap().clone()
+}
+
+/// Changes the settings, they are saved right away if anything changed.
+pub fn update(change: impl FnOnce(&mut Settings)) {
+    let mut settings = SETTINGS.lock().unwrap();
+    let before = settings.clone();
+    change(&mut settings);
+    if *settings == before {
+        return;
+    }
+
+    let path = settings_path();
+    if let Err(err) = settings.save(&path) {
+        eprintln!("unable to save {}: {err}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new("settings");
+        let path = dir.path().join("settings.json");
+        assert_eq!(Settings::load(&path), Settings::default());
+
+        let settings = Settings {
+            locale: Some(String::from("pt_BR")),
+            advanced: true,
+            window: Some(WindowGeometry {
+                width: 800,
+                height: 600,
+                maximized: false,
+            }),
+            dismissed_notices: vec![String::from("catalog")],
+            ..Settings::default()
+        };
+        settings.save(&path).unwrap();
+        let loaded = Settings::load(&path);
+        assert_eq!(loaded, settings);
+        assert!(loaded.is_dismissed("catalog"));
+
+        // unknown and missing keys are fine
+        fs::write(&path, r#"{"advanced": true, "theme": "dark"}"#).unwrap();
+        assert!(Settings::load(&path).advanced);
+    }
+
+    #[test]
+    fn corrupt_file_is_backed_up() {
+        let temp_dir = TempDir::new("settings");
+        let dir = temp_dir.path();
+        let path = dir.join("settings.json");
+        fs::write(&path, "{\"advanced\": tru").unwrap();
+
+        assert_eq!(Settings::load(&path), Settings::default());
+        assert!(!path.exists());
+        let backups = fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".bak"))
+            .count();
+        assert_eq!(backups, 1);
+    }
+
+    #[test]
+    fn columns_are_migrated() {
+        let temp_dir = TempDir::new("settings");
+        let dir = temp_dir.path();
+        let path = dir.join("settings.json");
+        fs::write(dir.join("columns.json"), r#"{"repository": true}"#).unwrap();
+
+        let settings = Settings::load(&path);
+        assert_eq!(settings.columns.get("repository"), Some(&true));
+        assert!(!dir.join("columns.json").exists());
+        assert_eq!(Settings::load(&path).columns, settings.columns);
+    }
+}
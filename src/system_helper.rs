@@ -0,0 +1,757 @@
+use crate::db_lock::{self, LockState, DB_LOCK_PATH};
+use crate::hooks::{self, HookAction};
+
+use glib::{ToVariant, Variant};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Argument switching the binary into the system bus helper mode.
+pub const HELPER_ARG: &str = "--system-helper";
+
+pub const BUS_NAME: &str = "org.vaamos.Menu.Helper";
+pub const OBJECT_PATH: &str = "/org/vaamos/Menu/Helper";
+pub const INTERFACE_NAME: &str = "org.vaamos.Menu.Helper";
+
+const INTROSPECTION_XML: &str = r#"
+<node>
+  <interface name="org.vaamos.Menu.Helper">
+    <method name="Install">
+      <arg name="packages" type="as" direction="in"/>
+    </method>
+    <method name="Remove">
+      <arg name="packages" type="as" direction="in"/>
+    </method>
+    <method name="SyncUpgrade"/>
+    <method name="ReinstallAll"/>
+    <method name="ClearCache"/>
+    <method name="RemoveDbLock"/>
+    <method name="ToggleService">
+      <arg name="unit" type="s" direction="in"/>
+      <arg name="enable" type="b" direction="in"/>
+    </method>
+    <method name="SetServiceRunning">
+      <arg name="unit" type="s" direction="in"/>
+      <arg name="running" type="b" direction="in"/>
+    </method>
+    <method name="RunHook">
+      <arg name="action" type="s" direction="in"/>
+      <arg name="argument" type="s" direction="in"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+/// Time without any call before the helper exits, it is started again on demand.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A call to the helper, with its arguments validated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HelperMethod {
+    Install(Vec<String>),
+    Remove(Vec<String>),
+    SyncUpgrade,
+    /// Reinstalls every package from the sync databases, like `pacman -S $(pacman -Qnq)`.
+    ReinstallAll,
+    ClearCache,
+    RemoveDbLock,
+    ToggleService(String, bool),
+    SetServiceRunning(String, bool),
+    RunHook(HookAction),
+}
+
+impl HelperMethod {
+    pub fn parse(method_name: &str, parameters: &Variant) -> Result<Self, HelperError> {
+        let invalid = || HelperError::InvalidArgs(format!("invalid arguments for {method_name}"));
+        let method = match method_name {
+            "Install" => Self::Install(parameters.get::<(Vec<String>,)>().ok_or_else(invalid)?.0),
+            "Remove" => Self::Remove(parameters.get::<(Vec<String>,)>().ok_or_else(invalid)?.0),
+            "SyncUpgrade" => Self::SyncUpgrade,
+            "ReinstallAll" => Self::ReinstallAll,
+            "ClearCache" => Self::ClearCache,
+            "RemoveDbLock" => Self::RemoveDbLock,
+            "ToggleService" => {
+                let (unit, enable) = parameters.get::<(String, bool)>().ok_or_else(invalid)?;
+                Self::ToggleService(unit, enable)
+            }
+            "SetServiceRunning" => {
+                let (unit, running) = parameters.get::<(String, bool)>().ok_or_else(invalid)?;
+                Self::SetServiceRunning(unit, running)
+            }
+            "RunHook" => {
+                let (action, argument) =
+                    parameters.get::<(String, String)>().ok_or_else(invalid)?;
+                Self::RunHook(HookAction::from_parts(&action, &argument).ok_or_else(|| {
+                    HelperError::InvalidArgs(format!("unknown hook action: {action}"))
+                })?)
+            }
+            _ => return Err(HelperError::UnknownMethod(String::from(method_name))),
+        };
+        method.validate()?;
+
+        Ok(method)
+    }
+
+    /// Polkit action guarding the method.
+    pub fn action_id(&self) -> &'static str {
+        match self {
+            Self::Install(_) => "org.vaamos.menu.install",
+            Self::Remove(_) => "org.vaamos.menu.remove",
+            Self::SyncUpgrade => "org.vaamos.menu.sync-upgrade",
+            Self::ReinstallAll => "org.vaamos.menu.reinstall-all",
+            Self::ClearCache => "org.vaamos.menu.clear-cache",
+            Self::RemoveDbLock => "org.vaamos.menu.remove-db-lock",
+            Self::ToggleService(..) => "org.vaamos.menu.toggle-service",
+            Self::SetServiceRunning(..) => "org.vaamos.menu.start-stop-service",
+            Self::RunHook(_) => "org.vaamos.menu.run-hook",
+        }
+    }
+
+    fn validate(&self) -> Result<(), HelperError> {
+        match self {
+            Self::Install(packages) | Self::Remove(packages) => {
+                if packages.is_empty() {
+                    return Err(HelperError::InvalidArgs(String::from("no package given")));
+                }
+                match packages
+                    .iter()
+                    .find(|pkg_name| !valid_package_name(pkg_name))
+                {
+                    Some(pkg_name) => Err(HelperError::InvalidArgs(format!(
+                        "invalid package name: {pkg_name}"
+                    ))),
+                    None => Ok(()),
+                }
+            }
+            Self::ToggleService(unit, _) | Self::SetServiceRunning(unit, _)
+                if !valid_unit_name(unit) =>
+            {
+                Err(HelperError::InvalidArgs(format!(
+                    "invalid unit name: {unit}"
+                )))
+            }
+            Self::RunHook(action) => action.validate().map_err(HelperError::InvalidArgs),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Package names as accepted by makepkg, which also keeps options out.
+pub fn valid_package_name(pkg_name: &str) -> bool {
+    !pkg_name.is_empty()
+        && !pkg_name.starts_with(['-', '.'])
+        && pkg_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@._+-".contains(c))
+}
+
+pub fn valid_unit_name(unit: &str) -> bool {
+    let stem = match unit.rsplit_once('.') {
+        Some((stem, "service" | "socket" | "timer" | "path")) => stem,
+        _ => return false,
+    };
+    !stem.is_empty()
+        && !stem.starts_with('-')
+        && stem
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ":-_.@\\".contains(c))
+}
+
+/// Start of the message of `HelperError::NotAuthorized`, as the client receives it.
+const NOT_AUTHORIZED: &str = "not authorized: ";
+
+/// Whether a call failed because polkit refused it, also when its prompt was dismissed.
+pub fn is_not_authorized(message: &str) -> bool {
+    message.starts_with(NOT_AUTHORIZED)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HelperError {
+    NotAuthorized(String),
+    InvalidArgs(String),
+    UnknownMethod(String),
+    Failed(String),
+}
+
+impl HelperError {
+    pub fn dbus_name(&self) -> &'static str {
+        match self {
+            Self::NotAuthorized(_) => "org.vaamos.Menu.Helper.Error.NotAuthorized",
+            Self::InvalidArgs(_) => "org.freedesktop.DBus.Error.InvalidArgs",
+            Self::UnknownMethod(_) => "org.freedesktop.DBus.Error.UnknownMethod",
+            Self::Failed(_) => "org.vaamos.Menu.Helper.Error.Failed",
+        }
+    }
+}
+
+impl fmt::Display for HelperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAuthorized(action_id) => write!(f, "{NOT_AUTHORIZED}{action_id}"),
+            Self::InvalidArgs(reason) => write!(f, "{reason}"),
+            Self::UnknownMethod(method_name) => write!(f, "unknown method: {method_name}"),
+            Self::Failed(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// What the helper does, the root side of every method.
+/// `caller_uid` is the user who sent the call, when the bus could tell.
+pub trait HelperBackend: Send + Sync {
+    fn run(&self, method: &HelperMethod, caller_uid: Option<u32>) -> Result<(), String>;
+}
+
+/// Decides whether `sender` may perform `action_id`.
+pub trait Authorizer: Send + Sync {
+    fn authorize(&self, connection: &gio::DBusConnection, sender: &str, action_id: &str) -> bool;
+}
+
+/// Runs pacman and systemctl, never through a shell.
+pub struct SystemBackend;
+
+fn run_command(program: &str, args: &[&str]) -> Result<(), String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|err| format!("unable to run {program}: {err}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .map_or_else(
+            || format!("{program} exited with {}", output.status),
+            String::from,
+        );
+    Err(reason)
+}
+
+/// Name of the user `uid`, also for users outside of /etc/passwd.
+fn user_name(uid: u32) -> Result<String, String> {
+    let output = Command::new("getent")
+        .args(["passwd", &uid.to_string()])
+        .output()
+        .map_err(|err| format!("unable to run getent: {err}"))?;
+    String::from_utf8_lossy(&output.stdout)
+        .split(':')
+        .next()
+        .map(String::from)
+        .filter(|name| output.status.success() && !name.is_empty())
+        .ok_or_else(|| format!("unknown user {uid}"))
+}
+
+fn run_hook(action: &HookAction, caller_uid: Option<u32>) -> Result<(), String> {
+    let caller = || {
+        caller_uid
+            .ok_or_else(|| String::from("unknown caller"))
+            .and_then(user_name)
+    };
+    match action {
+        HookAction::EnableUnit { unit } => {
+            run_command("systemctl", &["enable", "--now", "--", unit])
+        }
+        HookAction::DisableUnit { unit } => {
+            run_command("systemctl", &["disable", "--now", "--", unit])
+        }
+        HookAction::AddUserToGroup { group } => {
+            run_command("gpasswd", &["--add", &caller()?, group])
+        }
+        HookAction::RunHelper { helper } => {
+            let path = hooks::helpers_dir().join(helper);
+            if !path.is_file() {
+                return Err(format!("no helper named {helper}"));
+            }
+            // the helper may need to know who it is run for
+            let output = Command::new(&path)
+                .env("HOOK_USER", caller().unwrap_or_default())
+                .output()
+                .map_err(|err| format!("unable to run {helper}: {err}"))?;
+            match output.status.success() {
+                true => Ok(()),
+                false => Err(format!("{helper} exited with {}", output.status)),
+            }
+        }
+    }
+}
+
+impl HelperBackend for SystemBackend {
+    fn run(&self, method: &HelperMethod, caller_uid: Option<u32>) -> Result<(), String> {
+        match method {
+            HelperMethod::Install(packages) => {
+                let mut args = vec!["-S", "--noconfirm", "--needed", "--"];
+                args.extend(packages.iter().map(String::as_str));
+                run_command("pacman", &args)
+            }
+            HelperMethod::Remove(packages) => {
+                let mut args = vec!["-R", "--noconfirm", "--"];
+                args.extend(packages.iter().map(String::as_str));
+                run_command("pacman", &args)
+            }
+            HelperMethod::SyncUpgrade => run_command("pacman", &["-Syu", "--noconfirm"]),
+            HelperMethod::ReinstallAll => {
+                let output = Command::new("pacman")
+                    .arg("-Qnq")
+                    .output()
+                    .map_err(|err| format!("unable to run pacman: {err}"))?;
+                let packages = String::from_utf8_lossy(&output.stdout);
+                let mut args = vec!["-S", "--noconfirm", "--"];
+                args.extend(
+                    packages
+                        .lines()
+                        .filter(|pkg_name| valid_package_name(pkg_name)),
+                );
+                run_command("pacman", &args)
+            }
+            HelperMethod::ClearCache => run_command("pacman", &["-Sc", "--noconfirm"]),
+            // removing the lock under a running transaction corrupts the database
+            HelperMethod::RemoveDbLock => {
+                match db_lock::check(Path::new(DB_LOCK_PATH), Path::new("/proc")) {
+                    LockState::Absent => Ok(()),
+                    LockState::Stale => {
+                        fs::remove_file(DB_LOCK_PATH).map_err(|err| err.to_string())
+                    }
+                    LockState::Held(holders) => Err(format!(
+                        "the package database is in use by:\n{}",
+                        db_lock::describe(&holders)
+                    )),
+                }
+            }
+            HelperMethod::ToggleService(unit, enable) => {
+                let action = match enable {
+                    true => "enable",
+                    false => "disable",
+                };
+                run_command("systemctl", &[action, "--now", "--", unit])
+            }
+            HelperMethod::SetServiceRunning(unit, running) => {
+                let action = match running {
+                    true => "start",
+                    false => "stop",
+                };
+                run_command("systemctl", &[action, "--", unit])
+            }
+            HelperMethod::RunHook(action) => run_hook(action, caller_uid),
+        }
+    }
+}
+
+/// Remembers when the last call completed, to exit once idle.
+struct IdleTracker {
+    backend: SystemBackend,
+    last_call: Mutex<Instant>,
+}
+
+impl HelperBackend for IdleTracker {
+    fn run(&self, method: &HelperMethod, caller_uid: Option<u32>) -> Result<(), String> {
+        let result = self.backend.run(method, caller_uid);
+        *self.last_call.lock().unwrap() = Instant::now();
+        result
+    }
+}
+
+/// Asks polkit on the system bus, the user may be prompted for a password.
+pub struct PolkitAuthorizer;
+
+impl Authorizer for PolkitAuthorizer {
+    fn authorize(&self, connection: &gio::DBusConnection, sender: &str, action_id: &str) -> bool {
+        let subject = (
+            "system-bus-name",
+            HashMap::from([("name", sender.to_variant())]),
+        );
+        // AllowUserInteraction
+        let parameters = (subject, action_id, HashMap::<&str, &str>::new(), 1_u32, "");
+        let reply = connection.call_sync(
+            Some("org.freedesktop.PolicyKit1"),
+            "/org/freedesktop/PolicyKit1/Authority",
+            "org.freedesktop.PolicyKit1.Authority",
+            "CheckAuthorization",
+            Some(&parameters.to_variant()),
+            Some(glib::VariantTy::new("((bba{ss}))").unwrap()),
+            gio::DBusCallFlags::NONE,
+            // the user may take a while to authenticate, never time out
+            i32::MAX,
+            gio::Cancellable::NONE,
+        );
+
+        match reply {
+            Ok(reply) => reply.child_value(0).child_value(0).get::<bool>() == Some(true),
+            Err(err) => {
+                eprintln!("polkit check for {action_id} failed: {err}");
+                false
+            }
+        }
+    }
+}
+
+/// Unix user of `sender`, as the bus daemon knows it.
+fn caller_uid(connection: &gio::DBusConnection, sender: &str) -> Option<u32> {
+    connection
+        .call_sync(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "GetConnectionUnixUser",
+            Some(&(sender,).to_variant()),
+            Some(glib::VariantTy::new("(u)").unwrap()),
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+        )
+        .ok()
+        .and_then(|reply| reply.get::<(u32,)>())
+        .map(|(uid,)| uid)
+}
+
+/// Parses, authorizes and runs a method call.
+pub fn dispatch(
+    backend: &dyn HelperBackend,
+    authorizer: &dyn Authorizer,
+    connection: &gio::DBusConnection,
+    sender: &str,
+    method_name: &str,
+    parameters: &Variant,
+) -> Result<(), HelperError> {
+    let method = HelperMethod::parse(method_name, parameters)?;
+    if !authorizer.authorize(connection, sender, method.action_id()) {
+        return Err(HelperError::NotAuthorized(String::from(method.action_id())));
+    }
+
+    backend
+        .run(&method, caller_uid(connection, sender))
+        .map_err(HelperError::Failed)
+}
+
+/// Exports the helper object on `connection`, method calls are handled
+/// in the thread-default main context.
+pub fn register(
+    connection: &gio::DBusConnection,
+    backend: Arc<dyn HelperBackend>,
+    authorizer: Arc<dyn Authorizer>,
+) -> Result<gio::RegistrationId, glib::Error> {
+    let node_info = gio::DBusNodeInfo::for_xml(INTROSPECTION_XML)?;
+    let interface_info = node_info
+        .lookup_interface(INTERFACE_NAME)
+        .expect("helper interface is described");
+
+    connection.register_object(
+        OBJECT_PATH,
+        &interface_info,
+        move |connection, sender, _, _, method_name, parameters, invocation| match dispatch(
+            backend.as_ref(),
+            authorizer.as_ref(),
+            &connection,
+            sender,
+            method_name,
+            &parameters,
+        ) {
+            Ok(_) => invocation.return_value(None),
+            Err(err) => invocation.return_dbus_error(err.dbus_name(), &err.to_string()),
+        },
+        |_, _, _, _, _| ().to_variant(),
+        |_, _, _, _, _, _| false,
+    )
+}
+
+/// Requests `BUS_NAME` on `connection`, fails if it is already owned.
+pub fn request_name(connection: &gio::DBusConnection) -> Result<(), glib::Error> {
+    // DBUS_NAME_FLAG_DO_NOT_QUEUE
+    let reply = connection.call_sync(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "RequestName",
+        Some(&(BUS_NAME, 4_u32).to_variant()),
+        Some(glib::VariantTy::new("(u)").unwrap()),
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+    )?;
+
+    // DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER
+    match reply.get::<(u32,)>() {
+        Some((1,)) => Ok(()),
+        _ => Err(glib::Error::new(
+            gio::IOErrorEnum::Exists,
+            &format!("{BUS_NAME} is already owned"),
+        )),
+    }
+}
+
+/// Entry point of the helper, started by D-Bus activation on the system bus.
+/// Returns the process exit code.
+pub fn run_daemon() -> i32 {
+    let connection = match gio::bus_get_sync(gio::BusType::System, gio::Cancellable::NONE) {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("unable to connect to the system bus: {err}");
+            return 1;
+        }
+    };
+    let tracker = Arc::new(IdleTracker {
+        backend: SystemBackend,
+        last_call: Mutex::new(Instant::now()),
+    });
+    if let Err(err) = register(&connection, tracker.clone(), Arc::new(PolkitAuthorizer))
+        .and_then(|_| request_name(&connection))
+    {
+        eprintln!("unable to export the helper: {err}");
+        return 1;
+    }
+
+    // calls are blocking, so the check only runs between them
+    let main_loop = glib::MainLoop::new(None, false);
+    glib::timeout_add_seconds_local(
+        10,
+        glib::clone!(@strong main_loop => move || {
+            if tracker.last_call.lock().unwrap().elapsed() < IDLE_TIMEOUT {
+                return glib::Continue(true);
+            }
+            main_loop.quit();
+            glib::Continue(false)
+        }),
+    );
+    main_loop.run();
+
+    0
+}
+
+/// Drops the "GDBus.Error:<name>: " prefix of errors returned by the helper.
+fn remote_error_message(err: &glib::Error) -> String {
+    let message = err.message();
+    match message.strip_prefix("GDBus.Error:") {
+        Some(remote) => remote
+            .split_once(": ")
+            .map_or(remote, |(_, message)| message)
+            .to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// Typed proxy of the helper, calls block until the operation is done.
+pub struct HelperClient {
+    connection: gio::DBusConnection,
+}
+
+impl HelperClient {
+    pub fn system() -> Result<Self, glib::Error> {
+        let connection = gio::bus_get_sync(gio::BusType::System, gio::Cancellable::NONE)?;
+        Ok(Self::for_connection(connection))
+    }
+
+    pub fn for_connection(connection: gio::DBusConnection) -> Self {
+        Self { connection }
+    }
+
+    fn call(&self, method_name: &str, parameters: Option<Variant>) -> Result<(), String> {
+        self.connection
+            .call_sync(
+                Some(BUS_NAME),
+                OBJECT_PATH,
+                INTERFACE_NAME,
+                method_name,
+                parameters.as_ref(),
+                None,
+                gio::DBusCallFlags::ALLOW_INTERACTIVE_AUTHORIZATION,
+                // pacman may take a long time, never time out
+                i32::MAX,
+                gio::Cancellable::NONE,
+            )
+            .map(|_| ())
+            .map_err(|err| remote_error_message(&err))
+    }
+
+    pub fn install(&self, packages: &[String]) -> Result<(), String> {
+        self.call("Install", Some((packages.to_vec(),).to_variant()))
+    }
+
+    pub fn remove(&self, packages: &[String]) -> Result<(), String> {
+        self.call("Remove", Some((packages.to_vec(),).to_variant()))
+    }
+
+    pub fn sync_upgrade(&self) -> Result<(), String> {
+        self.call("SyncUpgrade", None)
+    }
+
+    pub fn reinstall_all(&self) -> Result<(), String> {
+        self.call("ReinstallAll", None)
+    }
+
+    pub fn clear_cache(&self) -> Result<(), String> {
+        self.call("ClearCache", None)
+    }
+
+    pub fn remove_db_lock(&self) -> Result<(), String> {
+        self.call("RemoveDbLock", None)
+    }
+
+    pub fn toggle_service(&self, unit: &str, enable: bool) -> Result<(), String> {
+        self.call("ToggleService", Some((unit, enable).to_variant()))
+    }
+
+    pub fn set_service_running(&self, unit: &str, running: bool) -> Result<(), String> {
+        self.call("SetServiceRunning", Some((unit, running).to_variant()))
+    }
+
+    pub fn run_hook(&self, action: &HookAction) -> Result<(), String> {
+        self.call("RunHook", Some(action.parts().to_variant()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Stdio};
+
+    #[derive(Default)]
+    struct MockBackend {
+        calls: Mutex<Vec<HelperMethod>>,
+    }
+
+    impl HelperBackend for MockBackend {
+        fn run(&self, method: &HelperMethod, _: Option<u32>) -> Result<(), String> {
+            self.calls.lock().unwrap().push(method.clone());
+            match method {
+                HelperMethod::ClearCache => Err(String::from("cache is busy")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// Denies service changes, allows anything else.
+    struct MockAuthorizer;
+
+    impl Authorizer for MockAuthorizer {
+        fn authorize(&self, _: &gio::DBusConnection, _: &str, action_id: &str) -> bool {
+            action_id != "org.vaamos.menu.toggle-service"
+        }
+    }
+
+    /// Starts a private session bus, `None` when dbus-daemon is not installed.
+    pub(crate) fn private_bus() -> Option<(Child, String)> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.as_mut()?)
+            .read_line(&mut address)
+            .ok()?;
+
+        Some((daemon, String::from(address.trim())))
+    }
+
+    pub(crate) fn connect(address: &str) -> gio::DBusConnection {
+        gio::DBusConnection::for_address_sync(
+            address,
+            gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            gio::Cancellable::NONE,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn validate_arguments() {
+        assert!(valid_package_name("libreoffice-fresh"));
+        assert!(valid_package_name("gtk+3"));
+        assert!(!valid_package_name("--overwrite=*"));
+        assert!(!valid_package_name("vim; rm -rf /"));
+        assert!(valid_unit_name("bluetooth.service"));
+        assert!(valid_unit_name("getty@tty1.service"));
+        assert!(!valid_unit_name("--now"));
+        assert!(!valid_unit_name("bluetooth"));
+
+        let parameters = (vec!["vim", "-Syu"],).to_variant();
+        assert!(matches!(
+            HelperMethod::parse("Install", &parameters),
+            Err(HelperError::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            HelperMethod::parse("Install", &().to_variant()),
+            Err(HelperError::InvalidArgs(_))
+        ));
+        assert_eq!(
+            HelperMethod::parse("ToggleService", &("sshd.service", true).to_variant()),
+            Ok(HelperMethod::ToggleService(
+                String::from("sshd.service"),
+                true
+            ))
+        );
+        assert!(matches!(
+            HelperMethod::parse("SetServiceRunning", &("--now", false).to_variant()),
+            Err(HelperError::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            HelperMethod::parse("RunHook", &("add_user_to_group", "wheel").to_variant()),
+            Err(HelperError::InvalidArgs(_))
+        ));
+    }
+
+    #[test]
+    fn serve_on_private_bus() {
+        let (mut daemon, address) = match private_bus() {
+            Some(bus) => bus,
+            None => {
+                eprintln!("dbus-daemon not found, skipping");
+                return;
+            }
+        };
+
+        let backend = Arc::new(MockBackend::default());
+        let service_backend = backend.clone();
+        let service_address = address.clone();
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let context = glib::MainContext::new();
+            context
+                .with_thread_default(|| {
+                    let connection = connect(&service_address);
+                    register(&connection, service_backend, Arc::new(MockAuthorizer)).unwrap();
+                    request_name(&connection).unwrap();
+                    ready_tx.send(()).unwrap();
+                    glib::MainLoop::new(Some(&context), false).run();
+                })
+                .unwrap();
+        });
+        ready_rx.recv().unwrap();
+
+        let client = HelperClient::for_connection(connect(&address));
+        client.install(&[String::from("firefox")]).unwrap();
+        client.remove_db_lock().unwrap();
+        assert_eq!(client.clear_cache(), Err(String::from("cache is busy")));
+        let err = client.toggle_service("sshd.service", true).unwrap_err();
+        assert_eq!(err, "not authorized: org.vaamos.menu.toggle-service");
+        assert!(is_not_authorized(&err));
+        assert!(!is_not_authorized("cache is busy"));
+        assert!(client.remove(&[String::from("-Rns")]).is_err());
+        let hook = HookAction::AddUserToGroup {
+            group: String::from("video"),
+        };
+        client.run_hook(&hook).unwrap();
+
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            vec![
+                HelperMethod::Install(vec![String::from("firefox")]),
+                HelperMethod::RemoveDbLock,
+                HelperMethod::ClearCache,
+                HelperMethod::RunHook(hook),
+            ]
+        );
+
+        daemon.kill().unwrap();
+        let _ = daemon.wait();
+    }
+}
@@ -0,0 +1,232 @@
+use std::fs;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+
+/// Transcripts kept in the logs directory, older ones are removed.
+const KEPT_LOGS: usize = 50;
+
+/// Files the terminal-helper script writes to the report directory.
+const REPORT_STATUS: &str = "status";
+const REPORT_TRANSCRIPT: &str = "transcript";
+
+/// Where package and tweak commands run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TerminalMode {
+    /// A terminal inside the menu window.
+    #[default]
+    Embedded,
+    /// The first terminal emulator found by the terminal-helper script.
+    External,
+}
+
+impl TerminalMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "embedded" => Some(Self::Embedded),
+            "external" => Some(Self::External),
+            _ => None,
+        }
+    }
+}
+
+/// How a command run in a terminal ended.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandOutcome {
+    /// None when the command could not be started or was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// Saved scrollback of the terminal.
+    pub log_path: Option<PathBuf>,
+}
+
+impl CommandOutcome {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Program and arguments running `cmd` through bash, as the user: root commands go through
+/// the system helper.
+pub fn command_argv(cmd: &str) -> Vec<String> {
+    vec![String::from("bash"), String::from("-c"), String::from(cmd)]
+}
+
+/// Exit code from a status as returned by waitpid.
+pub fn exit_code(wait_status: i32) -> Option<i32> {
+    match wait_status & 0x7f {
+        0 => Some((wait_status >> 8) & 0xff),
+        _ => None,
+    }
+}
+
+/// `$XDG_STATE_HOME/vaamos-menu`, `~/.local/state/vaamos-menu` by default.
+pub fn state_dir() -> PathBuf {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| glib::home_dir().join(".local/state"));
+    state_home.join("vaamos-menu")
+}
+
+pub fn logs_dir() -> PathBuf {
+    state_dir().join("logs")
+}
+
+/// Log file for `cmd` started at `started` (seconds since the epoch), named after its program.
+pub fn log_path(dir: &Path, started: i64, cmd: &str) -> PathBuf {
+    let program = cmd
+        .split_whitespace()
+        .next()
+        .and_then(|program| program.rsplit('/').next())
+        .filter(|program| {
+            !program.is_empty()
+                && program
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-".contains(c))
+        })
+        .unwrap_or("command");
+    dir.join(format!("{started}-{program}.log"))
+}
+
+/// Removes the oldest logs of `dir` beyond the `keep` most recent ones.
+pub fn prune_logs(dir: &Path, keep: usize) {
+    let mut logs = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+    if logs.len() <= keep {
+        return;
+    }
+
+    // names start with the time the command was started
+    logs.sort_by_key(|path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split('-').next())
+            .and_then(|started| started.parse::<i64>().ok())
+            .unwrap_or(0)
+    });
+    for path in &logs[..logs.len() - keep] {
+        if let Err(err) = fs::remove_file(path) {
+            eprintln!("unable to remove {}: {err}", path.display());
+        }
+    }
+}
+
+/// A new log file for `cmd` in `dir`, the directory is created and old logs pruned.
+pub fn new_log_path(dir: &Path, cmd: &str) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    prune_logs(dir, KEPT_LOGS - 1);
+    Ok(log_path(dir, glib::real_time() / 1_000_000, cmd))
+}
+
+/// Directory where the terminal-helper script leaves the exit code and output of a command.
+/// Its files are created beforehand, so they stay the user's when the command runs as root.
+pub fn create_report_dir() -> std::io::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!(
+        "vaamos-menu-{}-{}",
+        std::process::id(),
+        glib::real_time()
+    ));
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    fs::write(dir.join(REPORT_STATUS), "")?;
+    fs::write(dir.join(REPORT_TRANSCRIPT), "")?;
+    Ok(dir)
+}
+
+/// Reads what the command wrote to the report directory `dir`, its transcript is copied to `logs_dir`.
+/// No exit code is reported when the terminal was closed before the command ended.
+pub fn read_report(dir: &Path, logs_dir: &Path, cmd: &str) -> CommandOutcome {
+    let exit_code = fs::read_to_string(dir.join(REPORT_STATUS))
+        .ok()
+        .and_then(|status| status.trim().parse::<i32>().ok());
+
+    let transcript = dir.join(REPORT_TRANSCRIPT);
+    let log_path = match fs::metadata(&transcript) {
+        Ok(metadata) if metadata.len() > 0 => new_log_path(logs_dir, cmd)
+            .and_then(|log_path| fs::copy(&transcript, &log_path).map(|_| log_path))
+            .map_err(|err| eprintln!("unable to save the terminal log: {err}"))
+            .ok(),
+        _ => None,
+    };
+
+    CommandOutcome {
+        exit_code,
+        log_path,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn commands_and_status() {
+        assert_eq!(command_argv("yay -Syu"), ["bash", "-c", "yay -Syu"]);
+
+        assert_eq!(exit_code(0), Some(0));
+        assert_eq!(exit_code(1 << 8), Some(1));
+        assert_eq!(exit_code(127 << 8), Some(127));
+        // SIGTERM
+        assert_eq!(exit_code(15), None);
+    }
+
+    #[test]
+    fn log_files() {
+        let temp_dir = TempDir::new("logs");
+        let dir = temp_dir.path();
+        assert_eq!(
+            log_path(dir, 1700000000, "/usr/bin/paru --removemake -Syu"),
+            dir.join("1700000000-paru.log")
+        );
+        assert_eq!(
+            log_path(dir, 1700000000, "$(pacman -Qtdq)"),
+            dir.join("1700000000-command.log")
+        );
+
+        for started in [300, 1000, 20, 4000] {
+            fs::write(log_path(dir, started, "yay -Syu"), "").unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        prune_logs(dir, 2);
+        let mut left = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, ["1000-yay.log", "4000-yay.log", "notes.txt"]);
+    }
+
+    #[test]
+    fn helper_reports() {
+        let report_dir = create_report_dir().unwrap();
+        let logs_dir = report_dir.join("logs");
+        // closed before the command ended
+        assert_eq!(
+            read_report(&report_dir, &logs_dir, "yay -Syu"),
+            CommandOutcome::default()
+        );
+
+        fs::write(report_dir.join(REPORT_STATUS), "1\n").unwrap();
+        fs::write(
+            report_dir.join(REPORT_TRANSCRIPT),
+            "error: target not found: foo\n",
+        )
+        .unwrap();
+        let outcome = read_report(&report_dir, &logs_dir, "yay -Syu");
+        assert_eq!(outcome.exit_code, Some(1));
+        assert!(!outcome.success());
+        let log_path = outcome.log_path.unwrap();
+        assert!(log_path.starts_with(&logs_dir));
+        assert_eq!(
+            fs::read_to_string(log_path).unwrap(),
+            "error: target not found: foo\n"
+        );
+
+        fs::remove_dir_all(&report_dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use crate::settings;
+use crate::terminal::{self, CommandOutcome, TerminalMode};
+use crate::utils;
+use crate::vte_terminal::Terminal;
+
+use gtk::prelude::*;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+pub const VIEW_LOG_ACTION: &str = "view-log";
+
+/// Runs `cmd` in a terminal inside a dialog, its scrollback is saved to a log once done.
+pub fn run_embedded(parent: Option<&gtk::Window>, cmd: &str) -> CommandOutcome {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Running command"),
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[("Close", gtk::ResponseType::Close)],
+    );
+    dialog.set_default_size(720, 440);
+    dialog.set_response_sensitive(gtk::ResponseType::Close, false);
+
+    let status_label = gtk::Label::new(Some(cmd));
+    status_label.set_xalign(0.0);
+    status_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+
+    let terminal = Terminal::new();
+    let scrolled_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    scrolled_window.set_shadow_type(gtk::ShadowType::In);
+    scrolled_window.set_vexpand(true);
+    scrolled_window.add(&terminal.widget);
+
+    let close_check = gtk::CheckButton::with_label("Close when done");
+    close_check.set_active(settings::current().terminal_close_when_done);
+    close_check.connect_toggled(|check| {
+        let active = check.is_active();
+        settings::update(|settings| settings.terminal_close_when_done = active);
+    });
+
+    let content_area = dialog.content_area();
+    content_area.set_border_width(10);
+    content_area.set_spacing(6);
+    content_area.pack_start(&status_label, false, false, 0);
+    content_area.pack_start(&scrolled_window, true, true, 0);
+    content_area.pack_start(&close_check, false, false, 0);
+
+    // closing the dialog would leave the command running unseen
+    let finished = Rc::new(Cell::new(false));
+    dialog.connect_delete_event(glib::clone!(@strong finished => move |_, _| {
+        gtk::Inhibit(!finished.get())
+    }));
+    dialog.show_all();
+    terminal.widget.grab_focus();
+
+    let outcome = Rc::new(RefCell::new(CommandOutcome::default()));
+    let widget = terminal.widget.clone();
+    let cmd_name = String::from(cmd);
+    let finish = Rc::new(
+        glib::clone!(@weak dialog, @weak status_label, @weak close_check, @weak widget, @strong finished, @strong outcome => move |exit_code: Option<i32>| {
+            finished.set(true);
+            let terminal = Terminal { widget };
+            let log_path = terminal::new_log_path(&terminal::logs_dir(), &cmd_name)
+                .map_err(|err| err.to_string())
+                .and_then(|path| {
+                    terminal
+                        .save_contents(&path)
+                        .map(|_| path)
+                        .map_err(|err| err.to_string())
+                });
+            let log_path = match log_path {
+                Ok(path) => Some(path),
+                Err(err) => {
+                    eprintln!("unable to save the terminal log: {err}");
+                    None
+                }
+            };
+
+            status_label.set_text(&match exit_code {
+                Some(0) => String::from("Done."),
+                Some(code) => format!("Failed with exit code {code}."),
+                None => String::from("The command was interrupted."),
+            });
+            *outcome.borrow_mut() = CommandOutcome { exit_code, log_path };
+            dialog.set_response_sensitive(gtk::ResponseType::Close, true);
+            if close_check.is_active() && outcome.borrow().success() {
+                dialog.response(gtk::ResponseType::Close);
+            }
+        }),
+    );
+
+    terminal.feed(&format!("$ {cmd}\n"));
+    terminal.connect_child_exited(glib::clone!(@strong finish => move |wait_status| {
+        finish(terminal::exit_code(wait_status));
+    }));
+    terminal.spawn(
+        &terminal::command_argv(cmd),
+        glib::clone!(@weak widget, @strong finish => move |result| {
+            if let Err(err) = result {
+                Terminal { widget }.feed(&format!("unable to start the command: {err}\n"));
+                finish(None);
+            }
+        }),
+    );
+
+    dialog.run();
+    dialog.close();
+
+    outcome.take()
+}
+
+/// Runs `cmd` in the embedded terminal, or in an external emulator when the preferences ask for it.
+/// Returns once the command is done.
+pub fn run_cmd(parent: Option<&gtk::Window>, cmd: &str) -> CommandOutcome {
+    match crate::terminal_mode() {
+        TerminalMode::Embedded => run_embedded(parent, cmd),
+        TerminalMode::External => {
+            let cmd = String::from(cmd);
+            utils::wait_for(move || utils::run_cmd_terminal(cmd))
+        }
+    }
+}
+
+/// Opens the log of a command, the target of the notifications' "View log" button.
+pub fn add_view_log_action(application: &gtk::Application) {
+    let action = gio::SimpleAction::new(VIEW_LOG_ACTION, Some(glib::VariantTy::STRING));
+    action.connect_activate(|_, parameter| {
+        let log_path = match parameter.and_then(|parameter| parameter.str()) {
+            Some(log_path) => log_path,
+            None => return,
+        };
+        let uri = gio::File::for_path(log_path).uri();
+        if let Err(err) = gio::AppInfo::launch_default_for_uri(&uri, gio::AppLaunchContext::NONE) {
+            eprintln!("unable to open {log_path}: {err}");
+        }
+    });
+    application.add_action(&action);
+}
+
+/// Tells how the command run for `title` ended, through a desktop notification.
+pub fn notify_outcome(title: &str, outcome: &CommandOutcome) {
+    let application = match gio::Application::default() {
+        Some(application) => application,
+        None => return,
+    };
+    let body = match outcome.exit_code {
+        Some(0) => String::from("Completed successfully."),
+        Some(code) => format!("Failed with exit code {code}."),
+        None => String::from("Did not complete."),
+    };
+
+    let notification = gio::Notification::new(title);
+    notification.set_body(Some(&body));
+    if let Some(log_path) = &outcome.log_path {
+        notification.add_button_with_target_value(
+            "View log",
+            &format!("app.{VIEW_LOG_ACTION}"),
+            Some(&log_path.to_string_lossy().to_variant()),
+        );
+    }
+    application.send_notification(Some("terminal-command"), &notification);
+}
@@ -0,0 +1,348 @@
+use crate::alpm_helper::{PackageOutcome, UpdateReport};
+use crate::utils;
+
+use gtk::prelude::*;
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write as _;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PreviewPackage {
+    pub name: String,
+    pub version: String,
+    pub repo: String,
+    pub download_size: i64,
+    pub installed_size: i64,
+}
+
+/// What a transaction would do, resolved against the local and sync databases.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionPreview {
+    /// Requested packages to install.
+    pub install: Vec<PreviewPackage>,
+    /// Dependencies pulled in by the requested packages.
+    pub dependencies: Vec<PreviewPackage>,
+    /// Requested packages to remove.
+    pub remove: Vec<PreviewPackage>,
+    /// Installed packages which depend on a package to remove: (package, required by).
+    pub broken: Vec<(String, Vec<String>)>,
+    /// Packages to install conflicting with installed ones: (package, installed).
+    pub conflicts: Vec<(String, String)>,
+    /// Targets or dependencies found in no database.
+    pub missing: Vec<String>,
+    pub download_size: i64,
+    pub disk_delta: i64,
+}
+
+impl TransactionPreview {
+    /// Failures which would make pacman abort the transaction.
+    pub fn has_errors(&self) -> bool {
+        !self.missing.is_empty() || !self.broken.is_empty()
+    }
+}
+
+fn preview_package(pkg: &alpm::Package) -> PreviewPackage {
+    PreviewPackage {
+        name: String::from(pkg.name()),
+        version: pkg.version().to_string(),
+        repo: pkg
+            .db()
+            .map(|db| String::from(db.name()))
+            .unwrap_or_default(),
+        download_size: pkg.download_size(),
+        installed_size: pkg.isize(),
+    }
+}
+
+/// Splits the entries of the pending lists, which hold an app with its extra packages.
+pub fn split_targets(pkg_list: &[String]) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    for pkg_name in pkg_list.iter().flat_map(|entry| entry.split_whitespace()) {
+        if !targets.iter().any(|target| target == pkg_name) {
+            targets.push(String::from(pkg_name));
+        }
+    }
+
+    targets
+}
+
+/// Resolves the transaction without locking the database, so it works unprivileged.
+pub fn resolve(
+    alpm_handle: &alpm::Alpm,
+    install_list: &[String],
+    removal_list: &[String],
+) -> TransactionPreview {
+    let mut preview = TransactionPreview::default();
+    let localdb = alpm_handle.localdb();
+    let syncdbs = alpm_handle.syncdbs();
+
+    // removals first, dependencies of new packages must not rely on them
+    let removal_targets = split_targets(removal_list);
+    let removal_set = removal_targets.iter().cloned().collect::<HashSet<String>>();
+    for target in &removal_targets {
+        let pkg = match localdb.pkg(target.as_str()) {
+            Ok(pkg) => pkg,
+            Err(_) => {
+                preview.missing.push(target.clone());
+                continue;
+            }
+        };
+        preview.disk_delta -= pkg.isize();
+        preview.remove.push(preview_package(&pkg));
+
+        let required_by = pkg
+            .required_by()
+            .into_iter()
+            .filter(|name| !removal_set.contains(name))
+            .collect::<Vec<String>>();
+        if !required_by.is_empty() {
+            preview.broken.push((target.clone(), required_by));
+        }
+    }
+
+    let mut planned: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<(String, bool)> = split_targets(install_list)
+        .into_iter()
+        .map(|target| (target, true))
+        .collect();
+    while let Some((target, requested)) = queue.pop_front() {
+        let pkg = match syncdbs.find_satisfier(target.as_str()) {
+            Some(pkg) => pkg,
+            None => {
+                preview.missing.push(target);
+                continue;
+            }
+        };
+        if !planned.insert(String::from(pkg.name())) {
+            continue;
+        }
+
+        preview.download_size += pkg.download_size();
+        preview.disk_delta += pkg.isize();
+        match requested {
+            true => preview.install.push(preview_package(&pkg)),
+            false => preview.dependencies.push(preview_package(&pkg)),
+        }
+
+        for dep in pkg.depends() {
+            let dep = dep.to_string();
+            let installed = localdb
+                .pkgs()
+                .find_satisfier(dep.as_str())
+                .filter(|local_pkg| !removal_set.contains(local_pkg.name()));
+            if installed.is_none() {
+                queue.push_back((dep, false));
+            }
+        }
+        for conflict in pkg.conflicts() {
+            if let Some(local_pkg) = localdb.pkgs().find_satisfier(conflict.to_string()) {
+                if local_pkg.name() != pkg.name() && !removal_set.contains(local_pkg.name()) {
+                    preview
+                        .conflicts
+                        .push((String::from(pkg.name()), String::from(local_pkg.name())));
+                }
+            }
+        }
+    }
+
+    // installed packages declaring a conflict with a new one
+    for local_pkg in localdb.pkgs() {
+        if removal_set.contains(local_pkg.name()) {
+            continue;
+        }
+        for conflict in local_pkg.conflicts() {
+            if planned.contains(conflict.name())
+                && !preview
+                    .conflicts
+                    .iter()
+                    .any(|(new_pkg, _)| new_pkg == conflict.name())
+            {
+                preview.conflicts.push((
+                    String::from(conflict.name()),
+                    String::from(local_pkg.name()),
+                ));
+            }
+        }
+    }
+
+    preview
+}
+
+fn format_size(size: i64) -> String {
+    let formatted = glib::format_size(size.unsigned_abs());
+    match size < 0 {
+        true => format!("-{formatted}"),
+        false => formatted.to_string(),
+    }
+}
+
+fn write_packages(markup: &mut String, title: &str, packages: &[PreviewPackage]) {
+    if packages.is_empty() {
+        return;
+    }
+    let _ = writeln!(markup, "<b>{title} ({})</b>", packages.len());
+    for pkg in packages {
+        let repo = match pkg.repo.is_empty() {
+            true => String::new(),
+            false => format!("{}/", pkg.repo),
+        };
+        let _ = writeln!(
+            markup,
+            "    {}{} {}",
+            glib::markup_escape_text(&repo),
+            glib::markup_escape_text(&pkg.name),
+            glib::markup_escape_text(&pkg.version)
+        );
+    }
+    markup.push('\n');
+}
+
+pub fn preview_markup(preview: &TransactionPreview) -> String {
+    let mut markup = String::new();
+
+    if !preview.missing.is_empty() {
+        let _ = writeln!(
+            markup,
+            "<span foreground=\"red\"><b>Packages not found:</b> {}</span>\n",
+            glib::markup_escape_text(&preview.missing.join(", "))
+        );
+    }
+    for (pkg_name, required_by) in &preview.broken {
+        let _ = writeln!(
+            markup,
+            "<span foreground=\"red\"><b>{}</b> is required by: {}</span>",
+            glib::markup_escape_text(pkg_name),
+            glib::markup_escape_text(&required_by.join(", "))
+        );
+    }
+    if !preview.broken.is_empty() {
+        markup.push('\n');
+    }
+    for (pkg_name, installed) in &preview.conflicts {
+        let _ = writeln!(
+            markup,
+            "<span foreground=\"orange\"><b>{}</b> conflicts with the installed <b>{}</b></span>",
+            glib::markup_escape_text(pkg_name),
+            glib::markup_escape_text(installed)
+        );
+    }
+    if !preview.conflicts.is_empty() {
+        markup.push('\n');
+    }
+
+    write_packages(&mut markup, "To install", &preview.install);
+    write_packages(&mut markup, "Dependencies", &preview.dependencies);
+    write_packages(&mut markup, "To remove", &preview.remove);
+
+    let _ = write!(
+        markup,
+        "<b>Total download:</b> {}\n<b>Disk space change:</b> {}",
+        format_size(preview.download_size),
+        format_size(preview.disk_delta)
+    );
+
+    markup
+}
+
+/// Shows what the transaction will do, returns whether the user confirmed it.
+pub fn confirm_transaction(parent: Option<&gtk::Window>, preview: &TransactionPreview) -> bool {
+    let dialog = utils::markup_dialog(
+        parent,
+        "Review changes",
+        &preview_markup(preview),
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Confirm", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_response_sensitive(gtk::ResponseType::Accept, !preview.has_errors());
+
+    let response = dialog.run();
+    dialog.close();
+
+    response == gtk::ResponseType::Accept
+}
+
+pub fn report_markup(report: &UpdateReport) -> String {
+    let mut markup = String::new();
+    let _ = writeln!(
+        markup,
+        "<b>{}</b>\n",
+        match report.has_failures() {
+            true => "Some changes could not be applied.",
+            false => "The changes have been applied.",
+        }
+    );
+
+    for pkg in &report.packages {
+        let color = match pkg.outcome {
+            PackageOutcome::Failed => "red",
+            PackageOutcome::Skipped => "orange",
+            _ => "green",
+        };
+        let _ = write!(
+            markup,
+            "<span foreground=\"{color}\">{}</span>  {}",
+            pkg.outcome.label(),
+            glib::markup_escape_text(&pkg.package)
+        );
+        if let Some(reason) = &pkg.reason {
+            let _ = write!(markup, " <i>({})</i>", glib::markup_escape_text(reason));
+        }
+        markup.push('\n');
+    }
+    if !report.hooks.is_empty() {
+        markup.push_str("\n<b>Follow-up steps</b>\n");
+    }
+    for hook in &report.hooks {
+        let (color, label) = match hook.error {
+            Some(_) => ("red", "Failed"),
+            None => ("green", "Done"),
+        };
+        let _ = write!(
+            markup,
+            "<span foreground=\"{color}\">{label}</span>  {}: {}",
+            glib::markup_escape_text(&hook.package),
+            glib::markup_escape_text(&hook.action.to_string())
+        );
+        if let Some(error) = &hook.error {
+            let _ = write!(markup, " <i>({})</i>", glib::markup_escape_text(error));
+        }
+        markup.push('\n');
+    }
+    if report.packages.iter().any(|pkg| pkg.pending) {
+        markup.push_str("\nItems which were not applied stay selected, so they can be retried.");
+    }
+
+    markup
+}
+
+/// Shows the outcome of every requested package.
+pub fn show_update_report(parent: Option<&gtk::Window>, report: &UpdateReport) {
+    let dialog = utils::markup_dialog(
+        parent,
+        "Changes applied",
+        &report_markup(report),
+        &[("Close", gtk::ResponseType::Close)],
+    );
+    dialog.run();
+    dialog.close();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_pending_entries() {
+        let pkg_list = vec![
+            String::from("libreoffice-fresh libreoffice-fresh-fr"),
+            String::from("firefox"),
+            String::from("libreoffice-fresh"),
+        ];
+        assert_eq!(
+            split_targets(&pkg_list),
+            vec!["libreoffice-fresh", "libreoffice-fresh-fr", "firefox"]
+        );
+    }
+}
@@ -1,5 +1,10 @@
+use crate::config::PKGDATADIR;
+use crate::terminal::{self, CommandOutcome};
+
 use gtk::prelude::*;
+use std::cell::RefCell;
 use std::path::Path;
+use std::rc::Rc;
 use std::{fs, str};
 use subprocess::{Exec, Redirection};
 
@@ -29,6 +34,25 @@ pub fn check_regular_file(path: &str) -> bool {
     }
 }
 
+/// Links the desktop file into the autostart directory, or removes the link.
+pub fn set_autostart(
+    autostart_path: &str,
+    desktop_path: &str,
+    autostart: bool,
+) -> std::io::Result<()> {
+    let config_dir = Path::new(autostart_path).parent().unwrap();
+    if !config_dir.exists() {
+        fs::create_dir_all(config_dir)?;
+    }
+    if autostart && !check_regular_file(autostart_path) {
+        std::os::unix::fs::symlink(desktop_path, autostart_path)?;
+    } else if !autostart && check_regular_file(autostart_path) {
+        fs::remove_file(autostart_path)?;
+    }
+
+    Ok(())
+}
+
 pub fn create_combo_with_model(group_store: &gtk::ListStore) -> gtk::ComboBox {
     let group_combo = gtk::ComboBox::with_model(group_store);
     let combo_renderer = gtk::CellRendererText::new();
@@ -39,20 +63,69 @@ pub fn create_combo_with_model(group_store: &gtk::ListStore) -> gtk::ComboBox {
     group_combo
 }
 
-pub fn run_cmd_terminal(cmd: String, escalate: bool) -> bool {
-    let cmd_formated = format!("{cmd}; read -p 'Press enter to exit'");
-    let mut args: Vec<&str> = vec![];
-    if escalate {
-        args.extend_from_slice(&["-s", "pkexec /usr/share/vaamos-menu/scripts/rootshell.sh"]);
+/// Modal dialog showing `markup` in a scrolled label, with `buttons`.
+/// The last button is the default one.
+pub fn markup_dialog(
+    parent: Option<&gtk::Window>,
+    title: &str,
+    markup: &str,
+    buttons: &[(&str, gtk::ResponseType)],
+) -> gtk::Dialog {
+    let dialog = gtk::Dialog::with_buttons(
+        Some(title),
+        parent,
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        buttons,
+    );
+    if let Some((_, response)) = buttons.last() {
+        dialog.set_default_response(*response);
     }
-    args.push(cmd_formated.as_str());
+    dialog.set_default_size(480, 360);
+
+    let label = gtk::Label::new(None);
+    label.set_markup(markup);
+    label.set_line_wrap(true);
+    label.set_selectable(true);
+    label.set_xalign(0.0);
+    label.set_valign(gtk::Align::Start);
+
+    let scrolled_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+    scrolled_window.set_vexpand(true);
+    scrolled_window.set_border_width(10);
+    scrolled_window.add(&label);
+    dialog
+        .content_area()
+        .pack_start(&scrolled_window, true, true, 0);
+    dialog.show_all();
 
-    let exit_status = Exec::cmd("/usr/share/vaamos-menu/scripts/terminal-helper")
+    dialog
+}
+
+/// Runs `cmd` in the first terminal emulator found, the terminal-helper script reports
+/// its exit code and output back.
+pub fn run_cmd_terminal(cmd: String) -> CommandOutcome {
+    let report_dir = match terminal::create_report_dir() {
+        Ok(report_dir) => report_dir,
+        Err(err) => {
+            eprintln!("unable to create the report directory: {err}");
+            return CommandOutcome::default();
+        }
+    };
+    let report_arg = report_dir.to_string_lossy();
+    let args = ["-o", &report_arg, cmd.as_str()];
+
+    // nothing is reported when no terminal emulator is installed
+    if let Err(err) = Exec::cmd(format!("{PKGDATADIR}/scripts/terminal-helper"))
         .args(args.as_slice())
         .stdout(Redirection::Pipe)
         .join()
-        .unwrap();
-    exit_status.success()
+    {
+        eprintln!("unable to run the terminal helper: {err}");
+    }
+    let outcome = terminal::read_report(&report_dir, &terminal::logs_dir(), &cmd);
+    let _ = fs::remove_dir_all(&report_dir);
+    outcome
 }
 
 #[inline]
@@ -68,6 +141,27 @@ pub fn get_pacman_wrapper() -> PacmanWrapper {
     PacmanWrapper::Pacman
 }
 
+/// Waits for `work` done on another thread, the window keeps being drawn meanwhile.
+pub fn wait_for<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+    let main_loop = glib::MainLoop::new(None, false);
+    let result = Rc::new(RefCell::new(None));
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    std::thread::spawn(move || {
+        let _ = tx.send(work());
+    });
+    rx.attach(
+        None,
+        glib::clone!(@strong main_loop, @strong result => move |value| {
+            *result.borrow_mut() = Some(value);
+            main_loop.quit();
+            glib::Continue(false)
+        }),
+    );
+    main_loop.run();
+
+    result.take().unwrap()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
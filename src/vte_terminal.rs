@@ -0,0 +1,224 @@
+use glib::translate::*;
+use gtk::prelude::*;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
+
+/// The few entry points of libvte used by the menu, there are no bindings for gtk-rs 0.16.
+mod ffi {
+    use std::os::raw::{c_char, c_int, c_long};
+
+    pub type VteTerminal = gtk::ffi::GtkWidget;
+    pub type SpawnCallback = unsafe extern "C" fn(
+        *mut VteTerminal,
+        glib::ffi::GPid,
+        *mut glib::ffi::GError,
+        glib::ffi::gpointer,
+    );
+
+    pub const VTE_PTY_DEFAULT: c_int = 0;
+    pub const VTE_WRITE_DEFAULT: c_int = 0;
+
+    #[link(name = "vte-2.91")]
+    extern "C" {
+        pub fn vte_terminal_new() -> *mut gtk::ffi::GtkWidget;
+        pub fn vte_terminal_set_scrollback_lines(terminal: *mut VteTerminal, lines: c_long);
+        pub fn vte_terminal_feed(terminal: *mut VteTerminal, data: *const c_char, length: isize);
+        pub fn vte_terminal_spawn_async(
+            terminal: *mut VteTerminal,
+            pty_flags: c_int,
+            working_directory: *const c_char,
+            argv: *mut *mut c_char,
+            envv: *mut *mut c_char,
+            spawn_flags: glib::ffi::GSpawnFlags,
+            child_setup: glib::ffi::GSpawnChildSetupFunc,
+            child_setup_data: glib::ffi::gpointer,
+            child_setup_data_destroy: glib::ffi::GDestroyNotify,
+            timeout: c_int,
+            cancellable: *mut gio::ffi::GCancellable,
+            callback: Option<SpawnCallback>,
+            user_data: glib::ffi::gpointer,
+        );
+        pub fn vte_terminal_write_contents_sync(
+            terminal: *mut VteTerminal,
+            stream: *mut gio::ffi::GOutputStream,
+            flags: c_int,
+            cancellable: *mut gio::ffi::GCancellable,
+            error: *mut *mut glib::ffi::GError,
+        ) -> glib::ffi::gboolean;
+    }
+}
+
+type SpawnDone = Box<dyn FnOnce(Result<(), String>)>;
+
+unsafe extern "C" fn spawn_trampoline(
+    _terminal: *mut ffi::VteTerminal,
+    _pid: glib::ffi::GPid,
+    error: *mut glib::ffi::GError,
+    user_data: glib::ffi::gpointer,
+) {
+    let done = Box::from_raw(user_data as *mut SpawnDone);
+    // the error stays owned by libvte
+    match error.is_null() {
+        true => done(Ok(())),
+        false => done(Err(CStr::from_ptr((*error).message)
+            .to_string_lossy()
+            .into_owned())),
+    }
+}
+
+/// A libvte terminal widget.
+pub struct Terminal {
+    pub widget: gtk::Widget,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        let widget: gtk::Widget = unsafe { from_glib_none(ffi::vte_terminal_new()) };
+        unsafe { ffi::vte_terminal_set_scrollback_lines(widget.to_glib_none().0, 10_000) };
+        Self { widget }
+    }
+
+    fn as_ptr(&self) -> *mut ffi::VteTerminal {
+        self.widget.to_glib_none().0
+    }
+
+    /// Shows `text` as if the command had printed it.
+    pub fn feed(&self, text: &str) {
+        let text = text.replace('\n', "\r\n");
+        unsafe {
+            ffi::vte_terminal_feed(
+                self.as_ptr(),
+                text.as_ptr() as *const c_char,
+                text.len() as isize,
+            )
+        };
+    }
+
+    /// Starts `argv` in the terminal, `done` tells whether it could be started.
+    pub fn spawn(&self, argv: &[String], done: impl FnOnce(Result<(), String>) + 'static) {
+        let argv = match argv
+            .iter()
+            .map(|arg| CString::new(arg.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(argv) => argv,
+            Err(err) => return done(Err(err.to_string())),
+        };
+        // libvte copies the arguments before returning
+        let mut argv_ptrs = argv
+            .iter()
+            .map(|arg| arg.as_ptr() as *mut c_char)
+            .chain(std::iter::once(ptr::null_mut()))
+            .collect::<Vec<_>>();
+        let done: Box<SpawnDone> = Box::new(Box::new(done));
+
+        unsafe {
+            ffi::vte_terminal_spawn_async(
+                self.as_ptr(),
+                ffi::VTE_PTY_DEFAULT,
+                ptr::null(),
+                argv_ptrs.as_mut_ptr(),
+                ptr::null_mut(),
+                glib::ffi::G_SPAWN_SEARCH_PATH,
+                None,
+                ptr::null_mut(),
+                None,
+                -1,
+                ptr::null_mut(),
+                Some(spawn_trampoline),
+                Box::into_raw(done) as glib::ffi::gpointer,
+            )
+        };
+    }
+
+    /// Calls `f` with the wait status of the command once it exited.
+    pub fn connect_child_exited(&self, f: impl Fn(i32) + 'static) {
+        self.widget
+            .connect_local("child-exited", false, move |values| {
+                f(values
+                    .get(1)
+                    .and_then(|value| value.get::<i32>().ok())
+                    .unwrap_or(-1));
+                None
+            });
+    }
+
+    /// Writes the scrollback and the screen to `path`, as plain text.
+    pub fn save_contents(&self, path: &Path) -> Result<(), glib::Error> {
+        let stream = gio::File::for_path(path).replace(
+            None,
+            false,
+            gio::FileCreateFlags::PRIVATE,
+            gio::Cancellable::NONE,
+        )?;
+        let mut error = ptr::null_mut();
+        let written = unsafe {
+            ffi::vte_terminal_write_contents_sync(
+                self.as_ptr(),
+                stream.upcast_ref::<gio::OutputStream>().to_glib_none().0,
+                ffi::VTE_WRITE_DEFAULT,
+                ptr::null_mut(),
+                &mut error,
+            )
+        };
+        stream.close(gio::Cancellable::NONE)?;
+        match written {
+            glib::ffi::GFALSE => Err(unsafe { from_glib_full(error) }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terminal;
+    use crate::test_utils::TempDir;
+
+    use std::cell::{Cell, RefCell};
+    use std::fs;
+    use std::rc::Rc;
+
+    #[test]
+    fn spawn_and_save() {
+        if gtk::init().is_err() {
+            eprintln!("no display, skipping");
+            return;
+        }
+
+        let terminal = Terminal::new();
+        let main_loop = glib::MainLoop::new(None, false);
+        let spawned = Rc::new(RefCell::new(None));
+        let wait_status = Rc::new(Cell::new(None));
+        terminal.connect_child_exited(
+            glib::clone!(@strong main_loop, @strong wait_status => move |status| {
+                wait_status.set(Some(status));
+                main_loop.quit();
+            }),
+        );
+        terminal.spawn(
+            &[
+                String::from("sh"),
+                String::from("-c"),
+                String::from("echo spawned; exit 3"),
+            ],
+            glib::clone!(@strong main_loop, @strong spawned => move |result| {
+                if result.is_err() {
+                    main_loop.quit();
+                }
+                *spawned.borrow_mut() = Some(result);
+            }),
+        );
+        main_loop.run();
+        assert_eq!(spawned.take(), Some(Ok(())));
+        assert_eq!(wait_status.get().and_then(terminal::exit_code), Some(3));
+
+        let dir = TempDir::new("vte");
+        let path = dir.path().join("contents.log");
+        terminal.save_contents(&path).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("spawned"));
+    }
+}